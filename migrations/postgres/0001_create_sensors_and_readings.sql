@@ -0,0 +1,31 @@
+-- sensors, with dates in milliseconds
+CREATE TABLE sensors (
+    id TEXT PRIMARY KEY,
+    profile_owner_id TEXT NOT NULL,
+    api_token TEXT NOT NULL,
+    device_uuid TEXT NOT NULL,
+    mac TEXT NOT NULL,
+    model TEXT NOT NULL,
+    manufacturer TEXT NOT NULL,
+    feature_uuid TEXT NOT NULL,
+    feature_name TEXT NOT NULL,
+    value DOUBLE PRECISION NOT NULL,
+    created_at BIGINT NOT NULL,
+    modified_at BIGINT NOT NULL,
+    deleted_at BIGINT,
+    -- a sensor feature is identified by device, feature and type
+    CONSTRAINT sensors_device_feature_type_key UNIQUE (device_uuid, feature_uuid, feature_name)
+);
+CREATE INDEX sensors_profile_owner_id ON sensors (profile_owner_id);
+CREATE INDEX sensors_mac ON sensors (mac);
+
+-- history of sensors values, with timestamp in milliseconds
+CREATE TABLE readings (
+    id BIGSERIAL PRIMARY KEY,
+    device_uuid TEXT NOT NULL,
+    feature_uuid TEXT NOT NULL,
+    feature_name TEXT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    value DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX readings_sensor_timestamp ON readings (device_uuid, feature_uuid, feature_name, timestamp);
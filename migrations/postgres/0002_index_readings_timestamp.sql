@@ -0,0 +1,2 @@
+-- expired readings of every sensor are deleted by timestamp
+CREATE INDEX readings_timestamp ON readings (timestamp);
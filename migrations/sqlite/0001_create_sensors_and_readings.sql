@@ -0,0 +1,30 @@
+-- sensors, with dates in milliseconds
+CREATE TABLE sensors (
+    id TEXT PRIMARY KEY NOT NULL,
+    profile_owner_id TEXT NOT NULL,
+    api_token TEXT NOT NULL,
+    device_uuid TEXT NOT NULL,
+    mac TEXT NOT NULL,
+    model TEXT NOT NULL,
+    manufacturer TEXT NOT NULL,
+    feature_uuid TEXT NOT NULL,
+    feature_name TEXT NOT NULL,
+    value REAL NOT NULL,
+    created_at INTEGER NOT NULL,
+    modified_at INTEGER NOT NULL,
+    deleted_at INTEGER,
+    -- a sensor feature is identified by device, feature and type
+    UNIQUE (device_uuid, feature_uuid, feature_name)
+);
+CREATE INDEX sensors_profile_owner_id ON sensors (profile_owner_id);
+CREATE INDEX sensors_mac ON sensors (mac);
+
+-- history of sensors values, with timestamp in milliseconds
+CREATE TABLE readings (
+    device_uuid TEXT NOT NULL,
+    feature_uuid TEXT NOT NULL,
+    feature_name TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    value REAL NOT NULL
+);
+CREATE INDEX readings_sensor_timestamp ON readings (device_uuid, feature_uuid, feature_name, timestamp);
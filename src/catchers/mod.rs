@@ -1,33 +1,54 @@
 use tracing::error;
 
-use rocket::http::Status;
 use rocket::request::Request;
 
-use crate::errors::api_error::ApiError;
+use crate::errors::api_error::{ApiError, ErrorCode};
+use crate::guards::ValidationErrors;
 
 #[catch(400)]
 pub fn bad_request(_: &Request) -> ApiError {
     error!(target: "app", "catcher 400 - bad_request");
-    ApiError {
-        code: Status::BadRequest.code,
-        message: "Bad request".to_string(),
-    }
+    ApiError::new(ErrorCode::BadRequest)
+}
+
+#[catch(401)]
+pub fn unauthorized(_: &Request) -> ApiError {
+    error!(target: "app", "catcher 401 - unauthorized");
+    ApiError::new(ErrorCode::Unauthorized)
+}
+
+#[catch(403)]
+pub fn forbidden(_: &Request) -> ApiError {
+    error!(target: "app", "catcher 403 - forbidden");
+    ApiError::new(ErrorCode::Forbidden)
 }
 
 #[catch(404)]
 pub fn not_found(_: &Request) -> ApiError {
     error!(target: "app", "catcher 404 - not_found");
-    ApiError {
-        code: Status::NotFound.code,
-        message: "Not found".to_string(),
+    ApiError::new(ErrorCode::NotFound)
+}
+
+#[catch(422)]
+pub fn unprocessable_entity(req: &Request) -> ApiError {
+    error!(target: "app", "catcher 422 - unprocessable_entity");
+    // errors of inputs rejected by the 'Validated' guard
+    let ValidationErrors(errors) = req.local_cache(ValidationErrors::default);
+    if errors.is_empty() {
+        ApiError::new(ErrorCode::UnprocessableEntity)
+    } else {
+        ApiError::with_errors(ErrorCode::ValidationFailed, errors.clone())
     }
 }
 
 #[catch(500)]
 pub fn internal_server_error(_: &Request) -> ApiError {
     error!(target: "app", "catcher 500 - internal_server_error");
-    ApiError {
-        code: Status::InternalServerError.code,
-        message: "Internal server error".to_string(),
-    }
+    ApiError::new(ErrorCode::InternalError)
+}
+
+#[catch(503)]
+pub fn service_unavailable(_: &Request) -> ApiError {
+    error!(target: "app", "catcher 503 - service_unavailable");
+    ApiError::new(ErrorCode::ServiceUnavailable)
 }
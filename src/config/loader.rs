@@ -0,0 +1,377 @@
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::config::sensor_types::load_sensor_types;
+use crate::config::{ConfigEntry, ConfigSource, Env, StorageBackend};
+use crate::db::collections::CollectionNames;
+use crate::db::retry::RetryPolicy;
+use crate::logging::log_filter;
+use crate::models::sensor_type::{SensorTypeDef, default_sensor_types};
+
+/// Effective config, with the source of every value
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub env: Env,
+    pub sensor_types: Vec<SensorTypeDef>,
+    sources: BTreeMap<String, ConfigSource>,
+}
+
+impl Config {
+    /// Where the value of `key` comes from, `ConfigSource::Default` if it isn't defined
+    pub fn source_of(&self, key: &str) -> ConfigSource {
+        self.sources.get(key).copied().unwrap_or(ConfigSource::Default)
+    }
+
+    /// Effective value of every key, with secrets masked
+    pub fn summary(&self) -> Vec<ConfigEntry> {
+        self.env.summary(|key| self.source_of(key))
+    }
+
+    /// Names of the MongoDB collections, the default ones if not defined
+    pub fn collection_names(&self) -> CollectionNames {
+        let defaults = CollectionNames::default();
+        CollectionNames {
+            sensors: self.env.mongo_sensors_collection.clone().unwrap_or(defaults.sensors),
+            readings: self.env.mongo_readings_collection.clone().unwrap_or(defaults.readings),
+            migrations: self
+                .env
+                .mongo_migrations_collection
+                .clone()
+                .unwrap_or(defaults.migrations),
+        }
+    }
+}
+
+/// All problems found loading the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration, {} problem(s):", self.errors.len())?;
+        for error in self.errors.iter() {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Load the config from layers, each one overriding the previous ones:
+/// 1. defaults
+/// 2. the `.toml` or `.json` file referenced by `CONFIG_FILE`, with lower-case keys, like
+///    ```toml
+///    storage_backend = "postgres"
+///    postgres_max_connections = 20
+///    trusted_callers = ["10.0.0.1", "10.0.0.2"]
+///    ```
+/// 3. `dotenv_vars`, read from the `.env` file
+/// 4. `env_vars`, the environment of the process
+///
+/// It fails with all the invalid or missing values, not only with the first one.
+pub fn load(
+    dotenv_vars: impl IntoIterator<Item = (String, String)>,
+    env_vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<Config, ConfigError> {
+    let mut errors: Vec<String> = Vec::new();
+    let mut values: BTreeMap<String, (String, ConfigSource)> = BTreeMap::new();
+    for (key, value) in dotenv_vars {
+        values.insert(key, (value, ConfigSource::DotEnvFile));
+    }
+    for (key, value) in env_vars {
+        values.insert(key, (value, ConfigSource::Env));
+    }
+
+    let mut file_keys: Vec<String> = Vec::new();
+    if let Some((path, _)) = values.get("CONFIG_FILE").filter(|(path, _)| !path.is_empty()) {
+        let path = path.clone();
+        match read_config_file(Path::new(&path)) {
+            Ok(file_values) => {
+                for (key, value) in file_values {
+                    file_keys.push(key.clone());
+                    // the file has the lowest priority, after defaults
+                    values.entry(key).or_insert((value, ConfigSource::File));
+                }
+            }
+            Err(file_errors) => errors.extend(
+                file_errors
+                    .into_iter()
+                    .map(|error| format!("CONFIG_FILE = {}: {}", path, error)),
+            ),
+        }
+    }
+
+    // envy stops at the first invalid value: drop it and parse again, to report all of them
+    let env = loop {
+        match envy::from_iter::<_, Env>(values.iter().map(|(key, (value, _))| (key.clone(), value.clone()))) {
+            Ok(env) => break Some(env),
+            Err(error) => match parse_error_key(&error.to_string(), &values) {
+                Some((key, message)) if values.remove(&key).is_some() => errors.push(message),
+                _ => {
+                    errors.push(error.to_string());
+                    break None;
+                }
+            },
+        }
+    };
+    let Some(env) = env else {
+        return Err(ConfigError { errors });
+    };
+
+    let known_keys: HashSet<String> = env
+        .summary(|_| ConfigSource::Default)
+        .into_iter()
+        .map(|entry| entry.key)
+        .collect();
+    for key in file_keys.iter().filter(|key| !known_keys.contains(*key)) {
+        errors.push(format!("unknown key {} in CONFIG_FILE", key.to_ascii_lowercase()));
+    }
+    errors.extend(validate(&env));
+
+    let sensor_types = match env.sensor_types_file.as_deref() {
+        Some(path) => load_sensor_types(Path::new(path)).unwrap_or_else(|sensor_types_errors| {
+            errors.push(format!(
+                "SENSOR_TYPES_FILE = {}: {}",
+                path,
+                sensor_types_errors.join("; ")
+            ));
+            Vec::new()
+        }),
+        None => default_sensor_types().unwrap_or_else(|sensor_types_errors| {
+            errors.push(format!("default sensor types: {}", sensor_types_errors.join("; ")));
+            Vec::new()
+        }),
+    };
+
+    if !errors.is_empty() {
+        return Err(ConfigError { errors });
+    }
+    let sources = values.into_iter().map(|(key, (_, source))| (key, source)).collect();
+    Ok(Config {
+        env,
+        sensor_types,
+        sources,
+    })
+}
+
+// keys of `Env` with an enum value, whose errors don't tell the key
+static ENUM_KEYS: &[&str] = &[
+    "STORAGE_BACKEND",
+    "LOG_FORMAT",
+    "LOG_OUTPUT",
+    "LOG_ROTATION",
+    "OTEL_EXPORTER_OTLP_PROTOCOL",
+];
+
+// key and readable message of errors like "<error> while parsing value '<value>' provided by <KEY>",
+// or "unknown variant `<value>`, expected one of ..." of enum values
+fn parse_error_key(message: &str, values: &BTreeMap<String, (String, ConfigSource)>) -> Option<(String, String)> {
+    if let Some((error, rest)) = message.split_once(" while parsing value '") {
+        let (value, key) = rest.rsplit_once("' provided by ")?;
+        return Some((key.to_string(), format!("{} = {}: {}", key, value, error)));
+    }
+    let (variant, _) = message.strip_prefix("unknown variant `")?.split_once('`')?;
+    let key = ENUM_KEYS
+        .iter()
+        .find(|key| values.get(**key).is_some_and(|(value, _)| value == variant))?;
+    Some((key.to_string(), format!("{} = {}: {}", key, variant, message)))
+}
+
+// key-value pairs of a `.toml` or `.json` config file, with upper-case keys and arrays joined by commas
+fn read_config_file(path: &Path) -> Result<Vec<(String, String)>, Vec<String>> {
+    let content = fs::read_to_string(path).map_err(|err| vec![format!("cannot read {}: {}", path.display(), err)])?;
+    let file: Value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&content).map_err(|err| vec![format!("invalid TOML: {}", err)])?,
+        Some("json") => serde_json::from_str(&content).map_err(|err| vec![format!("invalid JSON: {}", err)])?,
+        _ => return Err(vec![format!("{} must be a .toml or .json file", path.display())]),
+    };
+    let Value::Object(fields) = file else {
+        return Err(vec![String::from("must contain a table of settings")]);
+    };
+    let mut values: Vec<(String, String)> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+    for (key, value) in fields {
+        let value = match value {
+            Value::Array(items) => items
+                .iter()
+                .map(scalar_to_string)
+                .collect::<Option<Vec<String>>>()
+                .map(|items| items.join(",")),
+            value => scalar_to_string(&value),
+        };
+        match value {
+            Some(value) => values.push((key.to_ascii_uppercase(), value)),
+            None => errors.push(format!(
+                "{} must be a string, a number, a boolean or an array of them",
+                key
+            )),
+        }
+    }
+    if errors.is_empty() { Ok(values) } else { Err(errors) }
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+// problems of values that are valid one by one, but not for the server
+fn validate(env: &Env) -> Vec<String> {
+    let mut errors: Vec<String> = Vec::new();
+
+    match env.storage_backend {
+        StorageBackend::Mongodb => {
+            if env.mongo_uri.is_empty() {
+                errors.push(String::from("MONGO_URI is required when STORAGE_BACKEND = mongodb"));
+            } else if !has_scheme(env.mongo_uri.expose(), &["mongodb", "mongodb+srv"]) {
+                errors.push(format!(
+                    "MONGO_URI = {} must start with mongodb:// or mongodb+srv://",
+                    env.mongo_uri
+                ));
+            }
+            if env.mongo_db_name.is_empty() {
+                errors.push(String::from("MONGO_DB_NAME is required when STORAGE_BACKEND = mongodb"));
+            }
+        }
+        StorageBackend::Postgres => {
+            if env.postgres_url.is_empty() {
+                errors.push(String::from("POSTGRES_URL is required when STORAGE_BACKEND = postgres"));
+            } else if !has_scheme(env.postgres_url.expose(), &["postgres", "postgresql"]) {
+                errors.push(format!(
+                    "POSTGRES_URL = {} must start with postgres:// or postgresql://",
+                    env.postgres_url
+                ));
+            }
+        }
+        StorageBackend::Sqlite | StorageBackend::Memory => {}
+    }
+    if env.sqlite_path.as_deref() == Some("") {
+        errors.push(String::from("SQLITE_PATH must not be empty"));
+    }
+
+    check_pool(
+        &mut errors,
+        ("POSTGRES", "CONNECTIONS"),
+        env.postgres_min_connections,
+        env.postgres_max_connections,
+    );
+    check_pool(
+        &mut errors,
+        ("MONGO", "POOL_SIZE"),
+        env.mongo_min_pool_size,
+        env.mongo_max_pool_size,
+    );
+    for (key, value) in [
+        ("POSTGRES_ACQUIRE_TIMEOUT_SECS", env.postgres_acquire_timeout_secs),
+        ("MONGO_CONNECT_TIMEOUT_MS", env.mongo_connect_timeout_ms),
+        (
+            "MONGO_SERVER_SELECTION_TIMEOUT_MS",
+            env.mongo_server_selection_timeout_ms,
+        ),
+        ("HEALTH_TIMEOUT_MS", env.health_timeout_ms),
+        ("READINGS_RETENTION_DAYS", env.readings_retention_days),
+    ] {
+        if value == Some(0) {
+            errors.push(format!("{} = 0 must be greater than 0", key));
+        }
+    }
+    if let Err(error) = RetryPolicy::from_env(env) {
+        errors.push(error);
+    }
+
+    if env.log_dir.as_deref() == Some("") {
+        errors.push(String::from("LOG_DIR must not be empty"));
+    }
+    if env.log_max_files == Some(0) {
+        errors.push(String::from("LOG_MAX_FILES = 0 must be greater than 0"));
+    }
+    if env.log_max_size_kb == Some(0) {
+        errors.push(String::from("LOG_MAX_SIZE_KB = 0 must be greater than 0"));
+    }
+    if let Err(error) = log_filter(env.rust_log.as_deref()) {
+        errors.push(error);
+    }
+
+    if let Some(endpoint) = env.otel_exporter_otlp_endpoint.as_ref()
+        && !has_scheme(endpoint.expose(), &["http", "https"])
+    {
+        errors.push(format!(
+            "OTEL_EXPORTER_OTLP_ENDPOINT = {} must start with http:// or https://",
+            endpoint
+        ));
+    }
+    if let Some(ratio) = env.otel_traces_sampler_arg
+        && !(0.0..=1.0).contains(&ratio)
+    {
+        errors.push(format!("OTEL_TRACES_SAMPLER_ARG = {} must be between 0 and 1", ratio));
+    }
+
+    let defaults = CollectionNames::default();
+    let collections = [
+        (
+            "MONGO_SENSORS_COLLECTION",
+            env.mongo_sensors_collection.as_deref(),
+            defaults.sensors.as_str(),
+        ),
+        (
+            "MONGO_READINGS_COLLECTION",
+            env.mongo_readings_collection.as_deref(),
+            defaults.readings.as_str(),
+        ),
+        (
+            "MONGO_MIGRATIONS_COLLECTION",
+            env.mongo_migrations_collection.as_deref(),
+            defaults.migrations.as_str(),
+        ),
+    ];
+    for (key, name, _) in collections.iter() {
+        if let Some(name) = name
+            && (name.is_empty() || name.contains('$') || name.contains('\0') || name.starts_with("system."))
+        {
+            errors.push(format!("{} = {} is not a valid collection name", key, name));
+        }
+    }
+    for (i, (key, name, default)) in collections.iter().enumerate() {
+        for (other_key, other_name, other_default) in collections[i + 1..].iter() {
+            if name.unwrap_or(default) == other_name.unwrap_or(other_default) {
+                errors.push(format!("{} and {} must be different", key, other_key));
+            }
+        }
+    }
+
+    errors
+}
+
+fn has_scheme(url: &str, schemes: &[&str]) -> bool {
+    url.split_once("://")
+        .is_some_and(|(scheme, _)| schemes.contains(&scheme))
+}
+
+// `<prefix>_MIN_<suffix>` must not be greater than `<prefix>_MAX_<suffix>`, that must be at least 1.
+// Both pools have 10 max connections if not defined.
+fn check_pool(errors: &mut Vec<String>, (prefix, suffix): (&str, &str), min: Option<u32>, max: Option<u32>) {
+    let max = max.unwrap_or(10);
+    if max == 0 {
+        errors.push(format!("{}_MAX_{} = 0 must be greater than 0", prefix, suffix));
+    }
+    if let Some(min) = min
+        && min > max
+    {
+        errors.push(format!(
+            "{}_MIN_{} = {} must not be greater than {}_MAX_{} = {}",
+            prefix, suffix, min, prefix, suffix, max
+        ));
+    }
+}
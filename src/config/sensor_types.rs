@@ -0,0 +1,26 @@
+use std::fs;
+use std::path::Path;
+
+use crate::models::sensor_type::{SensorTypeDef, SensorTypesFile, validate_sensor_types};
+
+/// Load and validate the catalogue of sensor types from a `.toml` or `.json` file,
+/// for example:
+/// ```toml
+/// [[types]]
+/// name = "co2"
+/// kind = "int"
+/// unit = "ppm"
+/// min = 0
+/// max = 10000
+/// default = 400
+/// ```
+pub fn load_sensor_types(path: &Path) -> Result<Vec<SensorTypeDef>, Vec<String>> {
+    let content = fs::read_to_string(path).map_err(|err| vec![format!("cannot read {}: {}", path.display(), err)])?;
+    let file: SensorTypesFile = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&content).map_err(|err| vec![format!("invalid TOML: {}", err)])?,
+        Some("json") => serde_json::from_str(&content).map_err(|err| vec![format!("invalid JSON: {}", err)])?,
+        _ => return Err(vec![format!("{} must be a .toml or .json file", path.display())]),
+    };
+    validate_sensor_types(&file.types)?;
+    Ok(file.types)
+}
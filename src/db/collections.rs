@@ -0,0 +1,51 @@
+use std::sync::OnceLock;
+
+/// Names of the MongoDB collections of sensors, readings and applied migrations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionNames {
+    pub sensors: String,
+    pub readings: String,
+    pub migrations: String,
+}
+
+impl Default for CollectionNames {
+    fn default() -> Self {
+        Self {
+            sensors: String::from("sensors"),
+            readings: String::from("readings"),
+            migrations: String::from("migrations"),
+        }
+    }
+}
+
+static NAMES: OnceLock<CollectionNames> = OnceLock::new();
+
+/// Set the names of the collections used by the server.
+/// They can be set only once, before any collection is used,
+/// otherwise the default names are used.
+pub fn init_collection_names(names: CollectionNames) -> Result<(), String> {
+    if *NAMES.get_or_init(|| names.clone()) == names {
+        Ok(())
+    } else {
+        Err(String::from("collection names are already initialized"))
+    }
+}
+
+fn names() -> &'static CollectionNames {
+    NAMES.get_or_init(CollectionNames::default)
+}
+
+/// Name of the collection of sensors, `sensors` by default
+pub fn sensors() -> &'static str {
+    names().sensors.as_str()
+}
+
+/// Name of the time-series collection of readings, `readings` by default
+pub fn readings() -> &'static str {
+    names().readings.as_str()
+}
+
+/// Name of the collection of applied migrations, `migrations` by default
+pub fn migrations() -> &'static str {
+    names().migrations.as_str()
+}
@@ -0,0 +1,270 @@
+use futures::TryStreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{Document, doc};
+use mongodb::error::ErrorKind;
+use mongodb::options::IndexOptions;
+use mongodb::{Collection, Database, IndexModel};
+use tracing::{error, info, warn};
+
+use crate::db::collections;
+use crate::errors::db_error::DbError;
+
+// MongoDB error code returned by `listIndexes` when the collection doesn't exist yet
+const NAMESPACE_NOT_FOUND_CODE: i32 = 26;
+// duplicated keys in the error returned when the unique index cannot be created, all of them are logged
+const MAX_DUPLICATED_KEYS_IN_ERROR: usize = 10;
+
+struct ExpectedIndex {
+    name: &'static str,
+    keys: Document,
+    unique: bool,
+}
+
+fn expected_sensors_indexes() -> Vec<ExpectedIndex> {
+    vec![
+        // a sensor feature is identified by device, feature and type
+        ExpectedIndex {
+            name: "deviceUuid_1_featureUuid_1_featureName_1",
+            keys: doc! {"deviceUuid": 1, "featureUuid": 1, "featureName": 1},
+            unique: true,
+        },
+        ExpectedIndex {
+            name: "profileOwnerId_1",
+            keys: doc! {"profileOwnerId": 1},
+            unique: false,
+        },
+        ExpectedIndex {
+            name: "mac_1",
+            keys: doc! {"mac": 1},
+            unique: false,
+        },
+    ]
+}
+
+/// Create the indexes of the `sensors` collection, or verify them if they already exist.
+/// Differences between expected and actual indexes are logged.
+/// If an existing index conflicts with an expected one, it returns an error,
+/// unless `allow_conflicts` is true.
+pub async fn ensure_indexes(db: &Database, allow_conflicts: bool) -> Result<(), DbError> {
+    info!(target: "app", "ensure_indexes - Verifying 'sensors' indexes...");
+    let collection = db.collection::<Document>(collections::sensors());
+
+    let existing_indexes = list_indexes(&collection).await?;
+    let diff = diff_indexes(&existing_indexes);
+
+    for drift in diff.drifts.iter() {
+        warn!(target: "app", "ensure_indexes - drift: {}", drift);
+    }
+
+    if !diff.conflicts.is_empty() {
+        for conflict in diff.conflicts.iter() {
+            error!(target: "app", "ensure_indexes - conflict: {}", conflict);
+        }
+        if !allow_conflicts {
+            return Err(DbError::Query(format!(
+                "Conflicting indexes on 'sensors' collection: {}",
+                diff.conflicts.join("; ")
+            )));
+        }
+        warn!(target: "app", "ensure_indexes - ignoring conflicting indexes, because MONGO_ALLOW_INDEX_CONFLICTS is enabled");
+    }
+
+    // the unique index cannot be built while sensors are duplicated, as before its introduction
+    if diff.missing.iter().any(index_unique) {
+        let duplicates = find_duplicated_sensors(db).await?;
+        if !duplicates.is_empty() {
+            for duplicate in duplicates.iter() {
+                error!(target: "app", "ensure_indexes - {} sensors with key {}", duplicate.ids.len(), duplicate.key);
+            }
+            let mut keys: Vec<String> = duplicates
+                .iter()
+                .take(MAX_DUPLICATED_KEYS_IN_ERROR)
+                .map(|duplicate| duplicate.key.to_string())
+                .collect();
+            if duplicates.len() > MAX_DUPLICATED_KEYS_IN_ERROR {
+                keys.push(String::from("..."));
+            }
+            return Err(DbError::DuplicateKey(format!(
+                "Cannot create the unique index of 'sensors', {} keys are duplicated, remove them applying migrations with `register migrate`: {}",
+                duplicates.len(),
+                keys.join("; ")
+            )));
+        }
+    }
+
+    if !diff.missing.is_empty() {
+        info!(target: "app", "ensure_indexes - Creating {} missing indexes", diff.missing.len());
+        if let Err(err) = collection.create_indexes(diff.missing).await {
+            return Err(DbError::from(err));
+        }
+    }
+
+    info!(target: "app", "ensure_indexes - 'sensors' indexes verified");
+    Ok(())
+}
+
+/// Sensors sharing the key of the unique index
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicatedSensors {
+    // `deviceUuid`, `featureUuid` and `featureName`
+    pub key: Document,
+    // not soft-deleted and last modified first
+    pub ids: Vec<ObjectId>,
+}
+
+/// Find the sensors with the same `deviceUuid`, `featureUuid` and `featureName`
+pub async fn find_duplicated_sensors(db: &Database) -> Result<Vec<DuplicatedSensors>, DbError> {
+    let collection = db.collection::<Document>(collections::sensors());
+    let pipeline = vec![
+        // sensors without 'deletedAt' come first
+        doc! {"$sort": {"deletedAt": 1, "modifiedAt": -1, "_id": -1}},
+        doc! {"$group": {
+            "_id": {"deviceUuid": "$deviceUuid", "featureUuid": "$featureUuid", "featureName": "$featureName"},
+            "ids": {"$push": "$_id"},
+        }},
+        doc! {"$match": {"ids.1": {"$exists": true}}},
+    ];
+    let duplicate_docs: Vec<Document> = match collection.aggregate(pipeline).allow_disk_use(true).await {
+        Ok(cursor) => cursor.try_collect().await.map_err(DbError::from)?,
+        Err(err) => return Err(DbError::from(err)),
+    };
+    duplicate_docs
+        .iter()
+        .map(
+            |duplicate_doc| match (duplicate_doc.get_document("_id"), duplicate_doc.get_array("ids")) {
+                (Ok(key), Ok(ids)) => Ok(DuplicatedSensors {
+                    key: key.clone(),
+                    ids: ids.iter().filter_map(|id| id.as_object_id()).collect(),
+                }),
+                _ => Err(DbError::MalformedDocument(format!(
+                    "duplicated sensors = {}",
+                    duplicate_doc
+                ))),
+            },
+        )
+        .collect()
+}
+
+/// Indexes of the `sensors` collection that are not the expected ones
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IndexesState {
+    // names of the expected indexes that don't exist
+    pub missing: Vec<String>,
+    pub conflicts: Vec<String>,
+}
+
+/// Verify the indexes of the `sensors` collection, without changing them
+pub async fn check_indexes(db: &Database) -> Result<IndexesState, DbError> {
+    let collection = db.collection::<Document>(collections::sensors());
+    let existing_indexes = list_indexes(&collection).await?;
+    let diff = diff_indexes(&existing_indexes);
+    Ok(IndexesState {
+        missing: diff.missing.iter().map(|index| index_name(index).to_string()).collect(),
+        conflicts: diff.conflicts,
+    })
+}
+
+// differences between existing and expected indexes
+struct IndexesDiff {
+    // expected indexes to create
+    missing: Vec<IndexModel>,
+    // existing indexes incompatible with the expected ones
+    conflicts: Vec<String>,
+    // existing indexes that work, but are not exactly the expected ones
+    drifts: Vec<String>,
+}
+
+async fn list_indexes(collection: &Collection<Document>) -> Result<Vec<IndexModel>, DbError> {
+    match collection.list_indexes().await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(indexes) => Ok(indexes),
+            Err(err) => Err(DbError::from(err)),
+        },
+        Err(err) => match *err.kind {
+            ErrorKind::Command(ref command_error) if command_error.code == NAMESPACE_NOT_FOUND_CODE => Ok(Vec::new()),
+            _ => Err(DbError::from(err)),
+        },
+    }
+}
+
+fn diff_indexes(existing_indexes: &[IndexModel]) -> IndexesDiff {
+    let mut diff = IndexesDiff {
+        missing: Vec::new(),
+        conflicts: Vec::new(),
+        drifts: Vec::new(),
+    };
+
+    let expected_indexes = expected_sensors_indexes();
+    for expected in expected_indexes.iter() {
+        let same_keys = existing_indexes.iter().find(|index| index.keys == expected.keys);
+        let same_name = existing_indexes.iter().find(|index| index_name(index) == expected.name);
+        match (same_keys, same_name) {
+            (Some(existing), _) => {
+                if index_unique(existing) != expected.unique {
+                    diff.conflicts.push(format!(
+                        "index '{}' on {} has unique = {}, expected unique = {}",
+                        index_name(existing),
+                        expected.keys,
+                        index_unique(existing),
+                        expected.unique
+                    ));
+                } else if index_name(existing) != expected.name {
+                    diff.drifts.push(format!(
+                        "index on {} is named '{}', expected '{}'",
+                        expected.keys,
+                        index_name(existing),
+                        expected.name
+                    ));
+                }
+            }
+            (None, Some(existing)) => {
+                diff.conflicts.push(format!(
+                    "index '{}' is defined on {}, expected on {}",
+                    expected.name, existing.keys, expected.keys
+                ));
+            }
+            (None, None) => {
+                let options = IndexOptions::builder()
+                    .name(expected.name.to_string())
+                    .unique(expected.unique)
+                    .build();
+                diff.missing.push(
+                    IndexModel::builder()
+                        .keys(expected.keys.clone())
+                        .options(options)
+                        .build(),
+                );
+            }
+        }
+    }
+
+    for existing in existing_indexes.iter() {
+        let is_expected = expected_indexes
+            .iter()
+            .any(|expected| existing.keys == expected.keys || index_name(existing) == expected.name);
+        if !is_expected && index_name(existing) != "_id_" {
+            diff.drifts.push(format!(
+                "unexpected index '{}' on {}",
+                index_name(existing),
+                existing.keys
+            ));
+        }
+    }
+    diff
+}
+
+fn index_name(index: &IndexModel) -> &str {
+    index
+        .options
+        .as_ref()
+        .and_then(|options| options.name.as_deref())
+        .unwrap_or_default()
+}
+
+fn index_unique(index: &IndexModel) -> bool {
+    index
+        .options
+        .as_ref()
+        .and_then(|options| options.unique)
+        .unwrap_or(false)
+}
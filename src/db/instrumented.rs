@@ -0,0 +1,219 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+
+use crate::db::repository::{DynSensorRepository, SensorRepository};
+use crate::errors::db_error::DbError;
+use crate::metrics::Metrics;
+use crate::models::aggregation::{Agg, Bucket};
+use crate::models::health::HealthCheck;
+use crate::models::inputs::{RegisterInput, SortOrder};
+use crate::models::reading::{Reading, ReadingBucket};
+use crate::models::sensor::{RegisteredSensor, SensorSummary, SensorTypeStats, SensorValue};
+use crate::models::sensor_type::SensorType;
+
+/// Repository measuring latency and errors of every operation of the wrapped one
+pub struct InstrumentedSensorRepository {
+    inner: DynSensorRepository,
+    metrics: Arc<Metrics>,
+}
+
+impl InstrumentedSensorRepository {
+    pub fn new(inner: DynSensorRepository, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+
+    async fn observe<T>(
+        &self,
+        operation: &str,
+        future: impl Future<Output = Result<T, DbError>>,
+    ) -> Result<T, DbError> {
+        let started_at = Instant::now();
+        let result = future.await;
+        self.metrics
+            .db_operation_duration_seconds
+            .with_label_values(&[operation])
+            .observe(started_at.elapsed().as_secs_f64());
+        if result.is_err() {
+            self.metrics
+                .db_operation_errors_total
+                .with_label_values(&[operation])
+                .inc();
+        }
+        result
+    }
+}
+
+#[rocket::async_trait]
+impl SensorRepository for InstrumentedSensorRepository {
+    async fn upsert_sensor(
+        &self,
+        input: &RegisterInput,
+        sensor_type: SensorType,
+        api_token: Option<&str>,
+    ) -> Result<RegisteredSensor, DbError> {
+        let result = self
+            .observe("upsert_sensor", self.inner.upsert_sensor(input, sensor_type, api_token))
+            .await;
+        let registration = match result {
+            Ok(RegisteredSensor { created: true, .. }) => "created",
+            Ok(RegisteredSensor { created: false, .. }) => "updated",
+            Err(_) => "failed",
+        };
+        self.metrics
+            .sensor_registrations_total
+            .with_label_values(&[sensor_type.as_str(), registration])
+            .inc();
+        result
+    }
+
+    async fn find_sensor_value(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+    ) -> Result<Option<SensorValue>, DbError> {
+        self.observe(
+            "find_sensor_value",
+            self.inner.find_sensor_value(device_uuid, feature_uuid, sensor_type),
+        )
+        .await
+    }
+
+    async fn update_sensor_value(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        value: f64,
+    ) -> Result<Option<SensorValue>, DbError> {
+        self.observe(
+            "update_sensor_value",
+            self.inner
+                .update_sensor_value(device_uuid, feature_uuid, sensor_type, value),
+        )
+        .await
+    }
+
+    async fn find_readings(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        from: DateTime,
+        to: DateTime,
+        limit: i64,
+    ) -> Result<Vec<Reading>, DbError> {
+        self.observe(
+            "find_readings",
+            self.inner
+                .find_readings(device_uuid, feature_uuid, sensor_type, from, to, limit),
+        )
+        .await
+    }
+
+    async fn aggregate_readings(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        from: DateTime,
+        to: DateTime,
+        bucket: Bucket,
+        agg: Agg,
+    ) -> Result<Vec<ReadingBucket>, DbError> {
+        self.observe(
+            "aggregate_readings",
+            self.inner
+                .aggregate_readings(device_uuid, feature_uuid, sensor_type, from, to, bucket, agg),
+        )
+        .await
+    }
+
+    async fn find_api_tokens(
+        &self,
+        device_uuid: &str,
+        feature: Option<(&str, SensorType)>,
+        include_deleted: bool,
+    ) -> Result<Vec<String>, DbError> {
+        self.observe(
+            "find_api_tokens",
+            self.inner.find_api_tokens(device_uuid, feature, include_deleted),
+        )
+        .await
+    }
+
+    async fn find_profile_api_tokens(
+        &self,
+        profile_owner_id: ObjectId,
+        include_deleted: bool,
+    ) -> Result<Vec<String>, DbError> {
+        self.observe(
+            "find_profile_api_tokens",
+            self.inner.find_profile_api_tokens(profile_owner_id, include_deleted),
+        )
+        .await
+    }
+
+    async fn find_sensors_by_device(
+        &self,
+        device_uuid: &str,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Result<Vec<SensorSummary>, DbError> {
+        self.observe(
+            "find_sensors_by_device",
+            self.inner
+                .find_sensors_by_device(device_uuid, feature_name, sort_order, limit, after),
+        )
+        .await
+    }
+
+    async fn find_sensors_by_profile(
+        &self,
+        profile_owner_id: ObjectId,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Result<Vec<SensorSummary>, DbError> {
+        self.observe(
+            "find_sensors_by_profile",
+            self.inner
+                .find_sensors_by_profile(profile_owner_id, feature_name, sort_order, limit, after),
+        )
+        .await
+    }
+
+    async fn delete_sensors(
+        &self,
+        device_uuid: &str,
+        feature: Option<(&str, SensorType)>,
+        api_token: &str,
+        purge: bool,
+    ) -> Result<u64, DbError> {
+        self.observe(
+            "delete_sensors",
+            self.inner.delete_sensors(device_uuid, feature, api_token, purge),
+        )
+        .await
+    }
+
+    async fn sensors_stats(&self, stale_before: DateTime) -> Result<Vec<SensorTypeStats>, DbError> {
+        self.observe("sensors_stats", self.inner.sensors_stats(stale_before))
+            .await
+    }
+
+    async fn ping(&self) -> Result<(), DbError> {
+        self.observe("ping", self.inner.ping()).await
+    }
+
+    async fn check_schema(&self) -> Vec<HealthCheck> {
+        self.inner.check_schema().await
+    }
+}
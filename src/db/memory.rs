@@ -0,0 +1,404 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use tracing::info;
+
+use crate::db::repository::SensorRepository;
+use crate::errors::db_error::DbError;
+use crate::guards::tokens_match;
+use crate::models::aggregation::{Agg, Bucket, aggregate_in_buckets};
+use crate::models::inputs::{RegisterInput, SortOrder};
+use crate::models::reading::{Reading, ReadingBucket};
+use crate::models::sensor::{RegisteredSensor, SensorSummary, SensorTypeStats, SensorValue};
+use crate::models::sensor_type::{SensorType, ValueKind};
+
+#[derive(Debug, Clone)]
+struct StoredSensor {
+    id: ObjectId,
+    profile_owner_id: ObjectId,
+    api_token: String,
+    device_uuid: String,
+    mac: String,
+    model: String,
+    manufacturer: String,
+    feature_uuid: String,
+    sensor_type: SensorType,
+    value: f64,
+    created_at: DateTime,
+    modified_at: DateTime,
+    deleted_at: Option<DateTime>,
+}
+
+impl StoredSensor {
+    fn matches(&self, device_uuid: &str, feature: Option<(&str, SensorType)>, include_deleted: bool) -> bool {
+        self.device_uuid == device_uuid
+            && feature.is_none_or(|(feature_uuid, sensor_type)| {
+                self.feature_uuid == feature_uuid && self.sensor_type == sensor_type
+            })
+            && (include_deleted || self.deleted_at.is_none())
+    }
+
+    fn value(&self) -> SensorValue {
+        SensorValue {
+            value: self.value,
+            created_at: self.created_at,
+            modified_at: self.modified_at,
+        }
+    }
+
+    fn summary(&self) -> SensorSummary {
+        SensorSummary {
+            id: self.id,
+            device_uuid: self.device_uuid.clone(),
+            feature_uuid: self.feature_uuid.clone(),
+            feature_name: self.sensor_type.to_string(),
+            value: Some(self.value),
+            created_at: self.created_at,
+            modified_at: self.modified_at,
+            mac: self.mac.clone(),
+            model: self.model.clone(),
+            manufacturer: self.manufacturer.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoredReading {
+    device_uuid: String,
+    feature_uuid: String,
+    sensor_type: SensorType,
+    reading: Reading,
+}
+
+impl StoredReading {
+    fn matches(&self, device_uuid: &str, feature: Option<(&str, SensorType)>) -> bool {
+        self.device_uuid == device_uuid
+            && feature.is_none_or(|(feature_uuid, sensor_type)| {
+                self.feature_uuid == feature_uuid && self.sensor_type == sensor_type
+            })
+    }
+}
+
+/// In-memory storage, lost on restart, for tests and local demos without a database.
+/// Readings are kept forever, ignoring `READINGS_RETENTION_DAYS`.
+#[derive(Debug, Default)]
+pub struct MemorySensorRepository {
+    sensors: Mutex<Vec<StoredSensor>>,
+    readings: Mutex<Vec<StoredReading>>,
+}
+
+impl MemorySensorRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn find_sensors_page(
+        &self,
+        filter: impl Fn(&StoredSensor) -> bool,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Vec<SensorSummary> {
+        // sort by 'modifiedAt' and '_id', to have a stable order when two sensors have the same 'modifiedAt'
+        let compare = |sensor: &StoredSensor, key: (DateTime, ObjectId)| {
+            let ordering = (sensor.modified_at, sensor.id).cmp(&key);
+            match sort_order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            }
+        };
+        let sensors = self.sensors.lock().unwrap();
+        let mut page: Vec<&StoredSensor> = sensors
+            .iter()
+            .filter(|sensor| filter(sensor) && sensor.deleted_at.is_none())
+            .filter(|sensor| feature_name.is_none_or(|feature_name| sensor.sensor_type == feature_name))
+            .filter(|sensor| after.is_none_or(|after| compare(sensor, after) == Ordering::Greater))
+            .collect();
+        page.sort_by(|a, b| compare(a, (b.modified_at, b.id)));
+        page.iter()
+            .take(limit as usize)
+            .map(|sensor| sensor.summary())
+            .collect()
+    }
+}
+
+#[rocket::async_trait]
+impl SensorRepository for MemorySensorRepository {
+    async fn upsert_sensor(
+        &self,
+        input: &RegisterInput,
+        sensor_type: SensorType,
+        api_token: Option<&str>,
+    ) -> Result<RegisteredSensor, DbError> {
+        info!(target: "app", "upsert_sensor - Called with sensor_type = {}", sensor_type);
+        let profile_owner_id = ObjectId::from_str(input.profileOwnerId.as_str())
+            .map_err(|err| DbError::Validation(format!("profileOwnerId: {}", err)))?;
+        let date_now = DateTime::now();
+
+        let mut sensors = self.sensors.lock().unwrap();
+        let existing = sensors
+            .iter_mut()
+            .find(|sensor| sensor.matches(&input.deviceUuid, Some((&input.featureUuid, sensor_type)), true));
+        match existing {
+            Some(sensor)
+                if !tokens_match(&input.apiToken, &sensor.api_token)
+                    && !api_token.is_some_and(|api_token| tokens_match(api_token, &sensor.api_token)) =>
+            {
+                Err(DbError::Forbidden(String::from(
+                    "apiToken doesn't match the registered sensor",
+                )))
+            }
+            Some(sensor) => {
+                // registering again a soft-deleted sensor restores it
+                sensor.api_token = input.apiToken.clone();
+                sensor.mac = input.mac.clone();
+                sensor.model = input.model.clone();
+                sensor.manufacturer = input.manufacturer.clone();
+                sensor.modified_at = date_now;
+                sensor.deleted_at = None;
+                Ok(RegisteredSensor {
+                    id: sensor.id.to_hex(),
+                    created: false,
+                })
+            }
+            None => {
+                let sensor = StoredSensor {
+                    id: ObjectId::new(),
+                    profile_owner_id,
+                    api_token: input.apiToken.clone(),
+                    device_uuid: input.deviceUuid.clone(),
+                    mac: input.mac.clone(),
+                    model: input.model.clone(),
+                    manufacturer: input.manufacturer.clone(),
+                    feature_uuid: input.featureUuid.clone(),
+                    sensor_type,
+                    value: sensor_type.default_value(),
+                    created_at: date_now,
+                    modified_at: date_now,
+                    deleted_at: None,
+                };
+                let id = sensor.id.to_hex();
+                sensors.push(sensor);
+                Ok(RegisteredSensor { id, created: true })
+            }
+        }
+    }
+
+    async fn find_sensor_value(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+    ) -> Result<Option<SensorValue>, DbError> {
+        let sensors = self.sensors.lock().unwrap();
+        Ok(sensors
+            .iter()
+            .find(|sensor| sensor.matches(device_uuid, Some((feature_uuid, sensor_type)), false))
+            .map(StoredSensor::value))
+    }
+
+    async fn update_sensor_value(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        value: f64,
+    ) -> Result<Option<SensorValue>, DbError> {
+        let value = match sensor_type.kind() {
+            ValueKind::Float => value,
+            ValueKind::Int => value.trunc(),
+        };
+        let date_now = DateTime::now();
+
+        let mut sensors = self.sensors.lock().unwrap();
+        let Some(sensor) = sensors
+            .iter_mut()
+            .find(|sensor| sensor.matches(device_uuid, Some((feature_uuid, sensor_type)), false))
+        else {
+            return Ok(None);
+        };
+        sensor.value = value;
+        sensor.modified_at = date_now;
+        self.readings.lock().unwrap().push(StoredReading {
+            device_uuid: device_uuid.to_string(),
+            feature_uuid: feature_uuid.to_string(),
+            sensor_type,
+            reading: Reading {
+                timestamp: date_now,
+                value,
+            },
+        });
+        Ok(Some(sensor.value()))
+    }
+
+    async fn find_readings(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        from: DateTime,
+        to: DateTime,
+        limit: i64,
+    ) -> Result<Vec<Reading>, DbError> {
+        let readings = self.readings.lock().unwrap();
+        // readings are appended in timestamp order
+        Ok(readings
+            .iter()
+            .filter(|stored| stored.matches(device_uuid, Some((feature_uuid, sensor_type))))
+            .filter(|stored| stored.reading.timestamp >= from && stored.reading.timestamp <= to)
+            .take(limit as usize)
+            .map(|stored| stored.reading.clone())
+            .collect())
+    }
+
+    async fn aggregate_readings(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        from: DateTime,
+        to: DateTime,
+        bucket: Bucket,
+        agg: Agg,
+    ) -> Result<Vec<ReadingBucket>, DbError> {
+        let readings: Vec<Reading> = self
+            .readings
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|stored| stored.matches(device_uuid, Some((feature_uuid, sensor_type))))
+            .filter(|stored| stored.reading.timestamp >= from && stored.reading.timestamp < to)
+            .map(|stored| stored.reading.clone())
+            .collect();
+        Ok(aggregate_in_buckets(&readings, from, to, bucket, agg))
+    }
+
+    async fn find_api_tokens(
+        &self,
+        device_uuid: &str,
+        feature: Option<(&str, SensorType)>,
+        include_deleted: bool,
+    ) -> Result<Vec<String>, DbError> {
+        let sensors = self.sensors.lock().unwrap();
+        Ok(sensors
+            .iter()
+            .filter(|sensor| sensor.matches(device_uuid, feature, include_deleted))
+            .map(|sensor| sensor.api_token.clone())
+            .collect())
+    }
+
+    async fn find_profile_api_tokens(
+        &self,
+        profile_owner_id: ObjectId,
+        include_deleted: bool,
+    ) -> Result<Vec<String>, DbError> {
+        let sensors = self.sensors.lock().unwrap();
+        Ok(sensors
+            .iter()
+            .filter(|sensor| {
+                sensor.profile_owner_id == profile_owner_id && (include_deleted || sensor.deleted_at.is_none())
+            })
+            .map(|sensor| sensor.api_token.clone())
+            .collect())
+    }
+
+    async fn find_sensors_by_device(
+        &self,
+        device_uuid: &str,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Result<Vec<SensorSummary>, DbError> {
+        Ok(self.find_sensors_page(
+            |sensor| sensor.device_uuid == device_uuid,
+            feature_name,
+            sort_order,
+            limit,
+            after,
+        ))
+    }
+
+    async fn find_sensors_by_profile(
+        &self,
+        profile_owner_id: ObjectId,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Result<Vec<SensorSummary>, DbError> {
+        Ok(self.find_sensors_page(
+            |sensor| sensor.profile_owner_id == profile_owner_id,
+            feature_name,
+            sort_order,
+            limit,
+            after,
+        ))
+    }
+
+    async fn delete_sensors(
+        &self,
+        device_uuid: &str,
+        feature: Option<(&str, SensorType)>,
+        api_token: &str,
+        purge: bool,
+    ) -> Result<u64, DbError> {
+        // purge removes also soft-deleted sensors
+        let is_deleted =
+            |sensor: &StoredSensor| sensor.matches(device_uuid, feature, purge) && sensor.api_token == api_token;
+        let mut sensors = self.sensors.lock().unwrap();
+        if purge {
+            let count_before = sensors.len();
+            sensors.retain(|sensor| !is_deleted(sensor));
+            self.readings
+                .lock()
+                .unwrap()
+                .retain(|stored| !stored.matches(device_uuid, feature));
+            Ok((count_before - sensors.len()) as u64)
+        } else {
+            let date_now = DateTime::now();
+            let mut deleted_count = 0;
+            for sensor in sensors.iter_mut().filter(|sensor| is_deleted(sensor)) {
+                sensor.deleted_at = Some(date_now);
+                sensor.modified_at = date_now;
+                deleted_count += 1;
+            }
+            Ok(deleted_count)
+        }
+    }
+
+    async fn sensors_stats(&self, stale_before: DateTime) -> Result<Vec<SensorTypeStats>, DbError> {
+        let sensors = self.sensors.lock().unwrap();
+        let mut stats: Vec<SensorTypeStats> = Vec::new();
+        for sensor in sensors.iter().filter(|sensor| sensor.deleted_at.is_none()) {
+            let feature_name = sensor.sensor_type.as_str();
+            let index = match stats
+                .iter()
+                .position(|type_stats| type_stats.feature_name == feature_name)
+            {
+                Some(index) => index,
+                None => {
+                    stats.push(SensorTypeStats {
+                        feature_name: feature_name.to_string(),
+                        registered: 0,
+                        stale: 0,
+                    });
+                    stats.len() - 1
+                }
+            };
+            stats[index].registered += 1;
+            if sensor.modified_at < stale_before {
+                stats[index].stale += 1;
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn ping(&self) -> Result<(), DbError> {
+        Ok(())
+    }
+}
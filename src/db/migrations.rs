@@ -0,0 +1,243 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use futures::TryStreamExt;
+use futures::future::BoxFuture;
+use mongodb::bson::{Bson, DateTime, Document, doc};
+use mongodb::{Collection, Database};
+use tracing::{info, warn};
+
+use crate::db::{collections, indexes};
+use crate::errors::db_error::DbError;
+use crate::models::sensor_type::{SensorType, ValueKind};
+
+/// A migration of the documents in MongoDB.
+/// It must be idempotent, only touching documents that still need it,
+/// and it returns the number of documents changed, or to change in dry-run.
+struct Migration {
+    version: i64,
+    name: &'static str,
+    run: for<'a> fn(&'a Database, bool) -> BoxFuture<'a, Result<u64, DbError>>,
+}
+
+// applied in this order, never change or remove an already released migration
+fn migrations() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            name: "add_sensors_schema_version",
+            run: add_sensors_schema_version,
+        },
+        Migration {
+            version: 2,
+            name: "convert_sensors_values_to_type_kind",
+            run: convert_sensors_values_to_type_kind,
+        },
+        Migration {
+            version: 3,
+            name: "remove_duplicated_sensors",
+            run: remove_duplicated_sensors,
+        },
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationStatus {
+    // recorded in the collection of migrations by a previous run
+    AlreadyApplied,
+    Applied,
+    // not applied because of dry-run
+    Pending,
+}
+
+/// Report of a migration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationOutcome {
+    pub version: i64,
+    pub name: &'static str,
+    pub status: MigrationStatus,
+    // documents changed, or to change if `Pending`
+    pub documents: u64,
+}
+
+impl fmt::Display for MigrationOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            MigrationStatus::AlreadyApplied => write!(f, "{:04} {} - already applied", self.version, self.name),
+            MigrationStatus::Applied => write!(
+                f,
+                "{:04} {} - applied, {} documents changed",
+                self.version, self.name, self.documents
+            ),
+            MigrationStatus::Pending => write!(
+                f,
+                "{:04} {} - pending, {} documents to change",
+                self.version, self.name, self.documents
+            ),
+        }
+    }
+}
+
+/// Apply, in order, the migrations not yet recorded in the collection of migrations, `migrations` by default.
+/// With `dry_run` nothing is changed, and pending migrations report the documents they would change.
+/// It stops at the first failing migration, keeping the ones already applied.
+pub async fn run_migrations(db: &Database, dry_run: bool) -> Result<Vec<MigrationOutcome>, DbError> {
+    info!(target: "app", "run_migrations - Called with dry_run = {}", dry_run);
+    let collection = db.collection::<Document>(collections::migrations());
+    let applied_versions = find_applied_versions(&collection).await?;
+
+    let mut outcomes: Vec<MigrationOutcome> = Vec::new();
+    for migration in migrations() {
+        if applied_versions.contains(&migration.version) {
+            outcomes.push(MigrationOutcome {
+                version: migration.version,
+                name: migration.name,
+                status: MigrationStatus::AlreadyApplied,
+                documents: 0,
+            });
+            continue;
+        }
+        let documents = (migration.run)(db, dry_run).await?;
+        let status = if dry_run {
+            MigrationStatus::Pending
+        } else {
+            // another instance may have applied it at the same time, so the record is an upsert
+            let filter = doc! {"_id": migration.version};
+            let update = doc! {"$setOnInsert": {
+                "name": migration.name,
+                "appliedAt": DateTime::now(),
+                "documents": documents as i64,
+            }};
+            if let Err(err) = collection.update_one(filter, update).upsert(true).await {
+                return Err(DbError::from(err));
+            }
+            MigrationStatus::Applied
+        };
+        let outcome = MigrationOutcome {
+            version: migration.version,
+            name: migration.name,
+            status,
+            documents,
+        };
+        info!(target: "app", "run_migrations - {}", outcome);
+        outcomes.push(outcome);
+    }
+    Ok(outcomes)
+}
+
+/// Names of the migrations not yet applied, in order
+pub async fn pending_migrations(db: &Database) -> Result<Vec<&'static str>, DbError> {
+    let applied_versions = find_applied_versions(&db.collection::<Document>(collections::migrations())).await?;
+    Ok(migrations()
+        .iter()
+        .filter(|migration| !applied_versions.contains(&migration.version))
+        .map(|migration| migration.name)
+        .collect())
+}
+
+async fn find_applied_versions(collection: &Collection<Document>) -> Result<HashSet<i64>, DbError> {
+    match collection.find(doc! {}).projection(doc! {"_id": 1}).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<Document>>().await {
+            Ok(migration_docs) => Ok(migration_docs
+                .iter()
+                .filter_map(|migration_doc| match migration_doc.get("_id") {
+                    Some(Bson::Int64(version)) => Some(*version),
+                    Some(Bson::Int32(version)) => Some(*version as i64),
+                    _ => None,
+                })
+                .collect()),
+            Err(err) => Err(DbError::from(err)),
+        },
+        Err(err) => Err(DbError::from(err)),
+    }
+}
+
+// update the sensors matching `filter`, or only count them in dry-run
+async fn update_sensors(
+    db: &Database,
+    filter: Document,
+    update: impl Into<mongodb::options::UpdateModifications>,
+    dry_run: bool,
+) -> Result<u64, DbError> {
+    let collection = db.collection::<Document>(collections::sensors());
+    let result = if dry_run {
+        collection.count_documents(filter).await
+    } else {
+        collection
+            .update_many(filter, update)
+            .await
+            .map(|update_result| update_result.modified_count)
+    };
+    result.map_err(DbError::from)
+}
+
+// sensors created before versioning have no `schemaVersion`
+fn add_sensors_schema_version(db: &Database, dry_run: bool) -> BoxFuture<'_, Result<u64, DbError>> {
+    Box::pin(async move {
+        let filter = doc! {"schemaVersion": {"$exists": false}};
+        let update = doc! {"$set": {"schemaVersion": 1}};
+        update_sensors(db, filter, update, dry_run).await
+    })
+}
+
+// values must be stored as double or i64, based on the kind of the sensor type
+// (e.g. 'airpressure' was stored as an integer), moving sensors to `schemaVersion` 2,
+// also those of types not in the catalogue, whose value is kept
+fn convert_sensors_values_to_type_kind(db: &Database, dry_run: bool) -> BoxFuture<'_, Result<u64, DbError>> {
+    Box::pin(async move {
+        let mut documents = 0;
+        for sensor_type in SensorType::all() {
+            let (bson_type, conversion) = match sensor_type.kind() {
+                ValueKind::Float => ("double", "$toDouble"),
+                ValueKind::Int => ("long", "$toLong"),
+            };
+            // `schemaVersion` < 2, matching also documents without it in dry-run
+            let filter = doc! {
+                "featureName": sensor_type.as_str(),
+                "schemaVersion": {"$not": {"$gte": 2}},
+            };
+            let update = vec![doc! {"$set": {"value": {conversion: "$value"}, "schemaVersion": 2}}];
+            let converted = update_sensors(db, filter, update, dry_run).await?;
+            if converted > 0 {
+                info!(target: "app", "convert_sensors_values_to_type_kind - {} '{}' sensors with a value stored as {}", converted, sensor_type, bson_type);
+            }
+            documents += converted;
+        }
+        // sensors of types no longer in the catalogue keep their value, they cannot be read anyway
+        let known_names: Vec<&str> = SensorType::all().map(SensorType::as_str).collect();
+        let filter = doc! {
+            "featureName": {"$nin": known_names},
+            "schemaVersion": {"$not": {"$gte": 2}},
+        };
+        let update = doc! {"$set": {"schemaVersion": 2}};
+        let unknown = update_sensors(db, filter, update, dry_run).await?;
+        if unknown > 0 {
+            warn!(target: "app", "convert_sensors_values_to_type_kind - {} sensors of types not in the catalogue, value not converted", unknown);
+        }
+        Ok(documents + unknown)
+    })
+}
+
+// sensors registered before the unique index may be duplicated, preventing its creation.
+// Only the first one of every key is kept, not soft-deleted and last modified,
+// readings are not changed because they are bound to the key
+fn remove_duplicated_sensors(db: &Database, dry_run: bool) -> BoxFuture<'_, Result<u64, DbError>> {
+    Box::pin(async move {
+        let collection = db.collection::<Document>(collections::sensors());
+        let mut documents = 0;
+        for duplicate in indexes::find_duplicated_sensors(db).await? {
+            let removed_ids = &duplicate.ids[1..];
+            warn!(target: "app", "remove_duplicated_sensors - {} sensors with key {}, keeping _id = {}", duplicate.ids.len(), duplicate.key, duplicate.ids[0]);
+            documents += if dry_run {
+                removed_ids.len() as u64
+            } else {
+                collection
+                    .delete_many(doc! {"_id": {"$in": removed_ids}})
+                    .await
+                    .map_err(DbError::from)?
+                    .deleted_count
+            };
+        }
+        Ok(documents)
+    })
+}
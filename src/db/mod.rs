@@ -1,30 +1,279 @@
 use std::env;
-use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-use mongodb::bson::doc;
+use mongodb::bson::{Document, doc};
 use mongodb::options::{ClientOptions, ServerApi, ServerApiVersion};
 use mongodb::{Client, Database};
 use rocket::fairing::AdHoc;
+use rocket::tokio::task::JoinHandle;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Encode, Executor, IntoArguments, Type};
 use tracing::{error, info, warn};
 
-use crate::config::Env;
+use crate::config::{Env, StorageBackend};
+use crate::db::instrumented::InstrumentedSensorRepository;
+use crate::db::memory::MemorySensorRepository;
+use crate::db::mongo::MongoSensorRepository;
+use crate::db::postgres::PostgresSensorRepository;
+use crate::db::repository::DynSensorRepository;
+use crate::db::retry::{RetryPolicy, SystemClock, retry_transient_with_backoff, retry_with_backoff};
+use crate::db::sql::{SqlDatabase, SqlSensorRepository};
+use crate::db::sqlite::SqliteSensorRepository;
+use crate::errors::db_error::DbError;
+use crate::metrics::Metrics;
 
+pub mod collections;
+pub mod indexes;
+pub mod instrumented;
+pub mod memory;
+pub mod migrations;
+pub mod mongo;
+pub mod postgres;
+pub mod readings;
+pub mod repository;
+pub mod retry;
 pub mod sensor;
+pub mod sql;
+pub mod sqlite;
 
-pub fn init(env_config: Env) -> AdHoc {
-    AdHoc::on_ignite("Connecting to MongoDB", |rocket| async {
-        match connect(env_config).await {
-            Ok(database) => rocket.manage(database),
+// period of the deletion of expired readings in SQL databases
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Error that stopped the setup of the storage in background, in degraded mode.
+/// Retrying cannot fix it, so the readiness probe stays down until the server is restarted.
+#[derive(Debug, Clone, Default)]
+pub struct SetupFailure(Arc<OnceLock<DbError>>);
+
+impl SetupFailure {
+    pub fn get(&self) -> Option<&DbError> {
+        self.0.get()
+    }
+
+    fn set(&self, error: DbError) {
+        let _ = self.0.set(error);
+    }
+}
+
+/// Initialize the storage backend selected by `STORAGE_BACKEND`,
+/// managing a `DynSensorRepository`, instrumented with `metrics`, and a `SetupFailure` in Rocket
+pub fn init(env_config: Env, metrics: Arc<Metrics>) -> AdHoc {
+    match env_config.storage_backend {
+        StorageBackend::Mongodb => init_mongodb(env_config, metrics),
+        StorageBackend::Sqlite => init_sqlite(env_config, metrics),
+        StorageBackend::Postgres => init_postgres(env_config, metrics),
+        StorageBackend::Memory => AdHoc::on_ignite("Using in-memory storage", |rocket| async {
+            warn!(target: "app", "In-memory storage - sensors and readings will be lost on restart");
+            let repository: DynSensorRepository = Box::new(InstrumentedSensorRepository::new(
+                Box::new(MemorySensorRepository::new()),
+                metrics,
+            ));
+            rocket.manage(repository).manage(SetupFailure::default())
+        }),
+    }
+}
+
+fn init_mongodb(env_config: Env, metrics: Arc<Metrics>) -> AdHoc {
+    AdHoc::try_on_ignite("Connecting to MongoDB", move |rocket| async move {
+        let policy = match RetryPolicy::from_env(&env_config) {
+            Ok(policy) => policy,
+            Err(error) => {
+                error!(target: "app", "MongoDB - invalid retry policy {}", error);
+                return Err(rocket);
+            }
+        };
+        let database = match mongo_database(&env_config).await {
+            Ok(database) => database,
+            Err(error) => {
+                error!(target: "app", "MongoDB - invalid configuration {:?}", error);
+                return Err(rocket);
+            }
+        };
+        let setup_failure = SetupFailure::default();
+        if env_config.db_start_degraded {
+            // requests fail, and /health/ready is down, until MongoDB answers
+            warn!(target: "app", "MongoDB - starting in degraded mode, connecting in background");
+            spawn_mongodb_setup(database.clone(), env_config.clone(), policy, setup_failure.clone());
+        } else if let Err(error) = setup_mongodb(&database, &env_config, &policy).await {
+            error!(target: "app", "MongoDB - cannot initialize {:?}", error);
+            return Err(rocket);
+        }
+        let repository: DynSensorRepository = Box::new(InstrumentedSensorRepository::new(
+            Box::new(MongoSensorRepository::new(
+                database,
+                env_config.mongo_allow_index_conflicts,
+            )),
+            metrics,
+        ));
+        Ok(rocket.manage(repository).manage(setup_failure))
+    })
+}
+
+/// Set up MongoDB in background for the degraded mode, retrying everything forever while MongoDB is unreachable.
+/// Other errors stop it, and they are stored in `setup_failure`.
+pub fn spawn_mongodb_setup(
+    database: Database,
+    env_config: Env,
+    policy: RetryPolicy,
+    setup_failure: SetupFailure,
+) -> JoinHandle<()> {
+    rocket::tokio::spawn(async move {
+        match setup_mongodb(&database, &env_config, &policy.forever()).await {
+            Ok(()) => info!(target: "app", "MongoDB - connected in background"),
             Err(error) => {
-                error!(target: "app", "MongoDB - cannot connect {:?}", error);
-                panic!("Cannot connect to MongoDB:: {:?}", error)
+                error!(target: "app", "MongoDB - cannot initialize in background, restart after fixing it {:?}", error);
+                setup_failure.set(error);
             }
         }
     })
 }
 
-async fn connect(env_config: Env) -> mongodb::error::Result<Database> {
-    let mongo_uri = env_config.mongo_uri.clone();
+// ping, migrations, indexes and readings collection, all retried as defined by `policy`
+// while MongoDB is unreachable.
+// Migrations come first, to fix the documents that prevent the creation of indexes
+async fn setup_mongodb(database: &Database, env_config: &Env, policy: &RetryPolicy) -> Result<(), DbError> {
+    info!(target: "app", "Setting up MongoDB...");
+    retry_transient_with_backoff(
+        "MongoDB setup",
+        policy,
+        &SystemClock,
+        DbError::is_transient,
+        async || {
+            ping(database).await?;
+            if env_config.mongo_skip_migrations {
+                warn!(target: "app", "MongoDB - skipping migrations, run them with `register migrate`");
+            } else {
+                migrations::run_migrations(database, false).await?;
+            }
+            indexes::ensure_indexes(database, env_config.mongo_allow_index_conflicts).await?;
+            readings::ensure_readings_collection(database, env_config.readings_retention_days).await?;
+            Ok(())
+        },
+    )
+    .await
+}
+
+fn init_sqlite(env_config: Env, metrics: Arc<Metrics>) -> AdHoc {
+    AdHoc::try_on_ignite("Opening SQLite database", move |rocket| async move {
+        let path = env_config.sqlite_path.as_deref().unwrap_or("register.db");
+        match SqliteSensorRepository::connect(path, env_config.readings_retention_days).await {
+            Ok(repository) => {
+                spawn_readings_retention(repository.clone(), env_config.readings_retention_days);
+                let repository: DynSensorRepository =
+                    Box::new(InstrumentedSensorRepository::new(Box::new(repository), metrics));
+                Ok(rocket.manage(repository).manage(SetupFailure::default()))
+            }
+            Err(error) => {
+                error!(target: "app", "SQLite - cannot open database {:?}", error);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+fn init_postgres(env_config: Env, metrics: Arc<Metrics>) -> AdHoc {
+    AdHoc::try_on_ignite("Connecting to PostgreSQL", move |rocket| async move {
+        let policy = match RetryPolicy::from_env(&env_config) {
+            Ok(policy) => policy,
+            Err(error) => {
+                error!(target: "app", "PostgreSQL - invalid retry policy {}", error);
+                return Err(rocket);
+            }
+        };
+        let mut options = match PgConnectOptions::from_str(env_config.postgres_url.expose()) {
+            Ok(options) => options.application_name("register"),
+            Err(error) => {
+                error!(target: "app", "PostgreSQL - invalid POSTGRES_URL {:?}", error);
+                return Err(rocket);
+            }
+        };
+        if env::var("ENV") == Ok(String::from("testing")) {
+            warn!("TESTING ENVIRONMENT - forcing postgres database = 'sensors_test'");
+            options = options.database("sensors_test");
+        }
+        let pool_options = PgPoolOptions::new()
+            .max_connections(env_config.postgres_max_connections.unwrap_or(10))
+            .min_connections(env_config.postgres_min_connections.unwrap_or(0))
+            .acquire_timeout(Duration::from_secs(
+                env_config.postgres_acquire_timeout_secs.unwrap_or(30),
+            ));
+        let repository =
+            PostgresSensorRepository::connect_lazy(options, pool_options, env_config.readings_retention_days);
+        let setup_failure = SetupFailure::default();
+        if env_config.db_start_degraded {
+            // requests fail, and /health/ready is down, until PostgreSQL answers
+            warn!(target: "app", "PostgreSQL - starting in degraded mode, connecting in background");
+            let repository = repository.clone();
+            let setup_failure = setup_failure.clone();
+            rocket::tokio::spawn(async move {
+                if let Err(error) = migrate_postgres(&repository, &policy.forever()).await {
+                    error!(target: "app", "PostgreSQL - cannot initialize in background, restart after fixing it {:?}", error);
+                    setup_failure.set(error);
+                }
+            });
+        } else if let Err(error) = migrate_postgres(&repository, &policy).await {
+            error!(target: "app", "PostgreSQL - cannot connect {:?}", error);
+            return Err(rocket);
+        }
+        spawn_readings_retention(repository.clone(), env_config.readings_retention_days);
+        let repository: DynSensorRepository =
+            Box::new(InstrumentedSensorRepository::new(Box::new(repository), metrics));
+        Ok(rocket.manage(repository).manage(setup_failure))
+    })
+}
+
+// apply migrations, retried as defined by `policy` while PostgreSQL is unreachable
+async fn migrate_postgres(repository: &PostgresSensorRepository, policy: &RetryPolicy) -> Result<(), DbError> {
+    retry_transient_with_backoff(
+        "PostgreSQL migrations",
+        policy,
+        &SystemClock,
+        DbError::is_transient,
+        || repository.migrate(),
+    )
+    .await
+}
+
+// SQL databases have no TTL, so expired readings are deleted every `RETENTION_INTERVAL`,
+// only if `READINGS_RETENTION_DAYS` is defined
+fn spawn_readings_retention<DB>(repository: SqlSensorRepository<DB>, retention_days: Option<u64>)
+where
+    DB: SqlDatabase,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> DB::Arguments<'q>: IntoArguments<'q, DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+{
+    if retention_days.is_none() {
+        return;
+    }
+    rocket::tokio::spawn(async move {
+        let mut interval = rocket::tokio::time::interval(RETENTION_INTERVAL);
+        loop {
+            interval.tick().await;
+            match repository.delete_expired_readings().await {
+                Ok(deleted) => info!(target: "app", "Readings retention - {} expired readings deleted", deleted),
+                Err(error) => warn!(target: "app", "Readings retention - cannot delete expired readings {:?}", error),
+            }
+        }
+    });
+}
+
+/// Connect to the MongoDB database, waiting for the server to answer as defined by `DB_RETRY_*` env vars
+pub async fn connect(env_config: Env) -> Result<Database, DbError> {
+    let policy = RetryPolicy::from_env(&env_config).map_err(DbError::Validation)?;
+    let database = mongo_database(&env_config).await?;
+
+    info!(target: "app", "Pinging MongoDB server...");
+    match retry_with_backoff("MongoDB ping", &policy, &SystemClock, || ping(&database)).await {
+        Ok(_) => Ok(database),
+        Err(err) => Err(DbError::from(err)),
+    }
+}
+
+// client of the MongoDB database, connected only when used
+async fn mongo_database(env_config: &Env) -> mongodb::error::Result<Database> {
+    let mongo_uri = env_config.mongo_uri.expose().to_string();
 
     let mongo_db_name = if env::var("ENV") == Ok(String::from("testing")) {
         warn!("TESTING ENVIRONMENT - forcing mongo_db_name = 'sensors_test'");
@@ -39,35 +288,24 @@ async fn connect(env_config: Env) -> mongodb::error::Result<Database> {
     client_options.server_api = Some(server_api);
     // Set app_name
     client_options.app_name = Some("register".to_string());
+    // Pool and timeouts, if not defined those of mongo_uri are kept
+    client_options.max_pool_size = env_config.mongo_max_pool_size.or(client_options.max_pool_size);
+    client_options.min_pool_size = env_config.mongo_min_pool_size.or(client_options.min_pool_size);
+    client_options.connect_timeout = env_config
+        .mongo_connect_timeout_ms
+        .map(Duration::from_millis)
+        .or(client_options.connect_timeout);
+    client_options.server_selection_timeout = env_config
+        .mongo_server_selection_timeout_ms
+        .map(Duration::from_millis)
+        .or(client_options.server_selection_timeout);
 
-    // Create a new client and connect to the server
+    // Create a new client, it connects to the server on the first operation
     let client = Client::with_options(client_options)?;
-    let database = client.database(mongo_db_name.as_str());
-
-    info!(target: "app", "Pinging MongoDB server...");
-    retry_connect_mongodb(|| async { database.run_command(doc! { "ping": 1 }).await }, 50).await?;
-
-    Ok(database)
+    Ok(client.database(mongo_db_name.as_str()))
 }
 
-async fn retry_connect_mongodb<T, E, Fut, F>(mut f: F, retries: usize) -> Result<T, E>
-where
-    F: FnMut() -> Fut,
-    Fut: Future<Output = Result<T, E>>,
-{
-    let mut count = 0;
-    loop {
-        let result = f().await;
-        if result.is_ok() {
-            info!(target: "app", "MongoDB connected!");
-            return result;
-        } else {
-            if count >= retries {
-                error!(target: "app", "Cannot connect to MongoDB, max tries reached");
-                return result;
-            }
-            count += 1;
-            warn!(target: "app", "MongoDB ping failed (count={}), retrying...", count);
-        }
-    }
+/// Ping the MongoDB server, to check that it answers
+pub async fn ping(database: &Database) -> mongodb::error::Result<Document> {
+    database.run_command(doc! { "ping": 1 }).await
 }
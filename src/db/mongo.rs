@@ -0,0 +1,315 @@
+use mongodb::Database;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{Bson, DateTime, Document};
+use tracing::{Instrument, Span, info_span};
+
+use crate::db;
+use crate::db::repository::SensorRepository;
+use crate::db::{collections, indexes, migrations, sensor};
+use crate::errors::db_error::DbError;
+use crate::models::aggregation::{Agg, Bucket};
+use crate::models::health::HealthCheck;
+use crate::models::inputs::{RegisterInput, SortOrder};
+use crate::models::reading::{Reading, ReadingBucket};
+use crate::models::sensor::{RegisteredSensor, SensorSummary, SensorTypeStats, SensorValue};
+use crate::models::sensor_type::{SensorType, ValueKind};
+
+/// MongoDB storage, using `sensors` and `readings` collections
+pub struct MongoSensorRepository {
+    db: Database,
+    // conflicting indexes don't make the storage unhealthy
+    allow_index_conflicts: bool,
+}
+
+impl MongoSensorRepository {
+    pub fn new(db: Database, allow_index_conflicts: bool) -> Self {
+        Self {
+            db,
+            allow_index_conflicts,
+        }
+    }
+}
+
+// client span of an operation in `db::sensor`, with the collection only if it uses a single one
+fn mongo_span(operation: &'static str, collection: Option<&str>) -> Span {
+    info_span!(
+        target: "app",
+        "mongodb",
+        otel.name = operation,
+        otel.kind = "client",
+        db.system.name = "mongodb",
+        db.operation.name = operation,
+        db.collection.name = collection,
+    )
+}
+
+#[rocket::async_trait]
+impl SensorRepository for MongoSensorRepository {
+    async fn upsert_sensor(
+        &self,
+        input: &RegisterInput,
+        sensor_type: SensorType,
+        api_token: Option<&str>,
+    ) -> Result<RegisteredSensor, DbError> {
+        sensor::upsert_sensor(&self.db, input, sensor_type, api_token)
+            .instrument(mongo_span("upsert_sensor", Some(collections::sensors())))
+            .await
+    }
+
+    async fn find_sensor_value(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+    ) -> Result<Option<SensorValue>, DbError> {
+        match sensor::find_sensor_value_by_uuid(&self.db, device_uuid, feature_uuid, sensor_type)
+            .instrument(mongo_span("find_sensor_value_by_uuid", Some(collections::sensors())))
+            .await?
+        {
+            Some(sensor_doc) => sensor_value(&sensor_doc).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn update_sensor_value(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        value: f64,
+    ) -> Result<Option<SensorValue>, DbError> {
+        // in db, values of int sensors are stored as i64
+        let value = match sensor_type.kind() {
+            ValueKind::Float => Bson::Double(value),
+            ValueKind::Int => Bson::Int64(value as i64),
+        };
+        match sensor::update_sensor_value(&self.db, device_uuid, feature_uuid, sensor_type, value)
+            // updates the sensor and appends a reading
+            .instrument(mongo_span("update_sensor_value", None))
+            .await?
+        {
+            Some(sensor_doc) => sensor_value(&sensor_doc).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn find_readings(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        from: DateTime,
+        to: DateTime,
+        limit: i64,
+    ) -> Result<Vec<Reading>, DbError> {
+        let reading_docs = sensor::find_readings(&self.db, device_uuid, feature_uuid, sensor_type, from, to, limit)
+            .instrument(mongo_span("find_readings", Some(collections::readings())))
+            .await?;
+        reading_docs
+            .iter()
+            .map(|reading_doc| {
+                match (
+                    bson_as_f64(reading_doc.get("value")),
+                    reading_doc.get_datetime("timestamp"),
+                ) {
+                    (Some(value), Ok(timestamp)) => Ok(Reading {
+                        timestamp: *timestamp,
+                        value,
+                    }),
+                    _ => Err(DbError::MalformedDocument(format!("reading = {}", reading_doc))),
+                }
+            })
+            .collect()
+    }
+
+    async fn aggregate_readings(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        from: DateTime,
+        to: DateTime,
+        bucket: Bucket,
+        agg: Agg,
+    ) -> Result<Vec<ReadingBucket>, DbError> {
+        let bucket_docs =
+            sensor::aggregate_readings(&self.db, device_uuid, feature_uuid, sensor_type, from, to, bucket, agg)
+                .instrument(mongo_span("aggregate_readings", Some(collections::readings())))
+                .await?;
+        bucket_docs
+            .iter()
+            .map(|bucket_doc| match bucket_doc.get_datetime("timestamp") {
+                Ok(timestamp) => Ok(ReadingBucket {
+                    timestamp: *timestamp,
+                    value: bson_as_f64(bucket_doc.get("value")),
+                }),
+                Err(_) => Err(DbError::MalformedDocument(format!("readings bucket = {}", bucket_doc))),
+            })
+            .collect()
+    }
+
+    async fn find_api_tokens(
+        &self,
+        device_uuid: &str,
+        feature: Option<(&str, SensorType)>,
+        include_deleted: bool,
+    ) -> Result<Vec<String>, DbError> {
+        sensor::find_api_tokens(&self.db, device_uuid, feature, include_deleted)
+            .instrument(mongo_span("find_api_tokens", Some(collections::sensors())))
+            .await
+    }
+
+    async fn find_profile_api_tokens(
+        &self,
+        profile_owner_id: ObjectId,
+        include_deleted: bool,
+    ) -> Result<Vec<String>, DbError> {
+        sensor::find_profile_api_tokens(&self.db, profile_owner_id, include_deleted)
+            .instrument(mongo_span("find_profile_api_tokens", Some(collections::sensors())))
+            .await
+    }
+
+    async fn find_sensors_by_device(
+        &self,
+        device_uuid: &str,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Result<Vec<SensorSummary>, DbError> {
+        let sensor_docs = sensor::find_sensors_by_device(&self.db, device_uuid, feature_name, sort_order, limit, after)
+            .instrument(mongo_span("find_sensors_by_device", Some(collections::sensors())))
+            .await?;
+        sensor_summaries(&sensor_docs)
+    }
+
+    async fn find_sensors_by_profile(
+        &self,
+        profile_owner_id: ObjectId,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Result<Vec<SensorSummary>, DbError> {
+        let sensor_docs =
+            sensor::find_sensors_by_profile(&self.db, profile_owner_id, feature_name, sort_order, limit, after)
+                .instrument(mongo_span("find_sensors_by_profile", Some(collections::sensors())))
+                .await?;
+        sensor_summaries(&sensor_docs)
+    }
+
+    async fn delete_sensors(
+        &self,
+        device_uuid: &str,
+        feature: Option<(&str, SensorType)>,
+        api_token: &str,
+        purge: bool,
+    ) -> Result<u64, DbError> {
+        // purge deletes also the readings
+        let collection = if purge { None } else { Some(collections::sensors()) };
+        sensor::delete_sensors(&self.db, device_uuid, feature, api_token, purge)
+            .instrument(mongo_span("delete_sensors", collection))
+            .await
+    }
+
+    async fn sensors_stats(&self, stale_before: DateTime) -> Result<Vec<SensorTypeStats>, DbError> {
+        let stats_docs = sensor::sensors_stats(&self.db, stale_before)
+            .instrument(mongo_span("sensors_stats", Some(collections::sensors())))
+            .await?;
+        stats_docs
+            .iter()
+            .map(|stats_doc| {
+                match (
+                    stats_doc.get_str("featureName"),
+                    bson_as_f64(stats_doc.get("registered")),
+                    bson_as_f64(stats_doc.get("stale")),
+                ) {
+                    (Ok(feature_name), Some(registered), Some(stale)) => Ok(SensorTypeStats {
+                        feature_name: feature_name.to_string(),
+                        registered: registered as u64,
+                        stale: stale as u64,
+                    }),
+                    _ => Err(DbError::MalformedDocument(format!("sensors stats = {}", stats_doc))),
+                }
+            })
+            .collect()
+    }
+
+    async fn ping(&self) -> Result<(), DbError> {
+        match db::ping(&self.db).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DbError::from(err)),
+        }
+    }
+
+    async fn check_schema(&self) -> Vec<HealthCheck> {
+        let indexes_check = match indexes::check_indexes(&self.db).await {
+            Ok(state) if !state.missing.is_empty() => {
+                HealthCheck::down("indexes", format!("missing indexes: {}", state.missing.join(", ")))
+            }
+            Ok(state) if state.conflicts.is_empty() => HealthCheck::up("indexes"),
+            Ok(state) if self.allow_index_conflicts => {
+                HealthCheck::up("indexes").with_details(format!("ignored conflicts: {}", state.conflicts.join("; ")))
+            }
+            Ok(state) => HealthCheck::down("indexes", state.conflicts.join("; ")),
+            Err(err) => HealthCheck::down("indexes", err.to_string()),
+        };
+        let migrations_check = match migrations::pending_migrations(&self.db).await {
+            Ok(pending) if pending.is_empty() => HealthCheck::up("migrations"),
+            Ok(pending) => HealthCheck::down("migrations", format!("pending migrations: {}", pending.join(", "))),
+            Err(err) => HealthCheck::down("migrations", err.to_string()),
+        };
+        vec![indexes_check, migrations_check]
+    }
+}
+
+fn sensor_value(sensor_doc: &Document) -> Result<SensorValue, DbError> {
+    match (
+        bson_as_f64(sensor_doc.get("value")),
+        sensor_doc.get_datetime("createdAt"),
+        sensor_doc.get_datetime("modifiedAt"),
+    ) {
+        (Some(value), Ok(created_at), Ok(modified_at)) => Ok(SensorValue {
+            value,
+            created_at: *created_at,
+            modified_at: *modified_at,
+        }),
+        _ => Err(DbError::MalformedDocument(format!("sensor = {}", sensor_doc))),
+    }
+}
+
+fn sensor_summaries(sensor_docs: &[Document]) -> Result<Vec<SensorSummary>, DbError> {
+    sensor_docs
+        .iter()
+        .map(|sensor_doc| {
+            match (
+                sensor_doc.get_object_id("_id"),
+                sensor_doc.get_datetime("createdAt"),
+                sensor_doc.get_datetime("modifiedAt"),
+            ) {
+                (Ok(id), Ok(created_at), Ok(modified_at)) => Ok(SensorSummary {
+                    id,
+                    device_uuid: sensor_doc.get_str("deviceUuid").unwrap_or_default().to_string(),
+                    feature_uuid: sensor_doc.get_str("featureUuid").unwrap_or_default().to_string(),
+                    feature_name: sensor_doc.get_str("featureName").unwrap_or_default().to_string(),
+                    value: bson_as_f64(sensor_doc.get("value")),
+                    created_at: *created_at,
+                    modified_at: *modified_at,
+                    mac: sensor_doc.get_str("mac").unwrap_or_default().to_string(),
+                    model: sensor_doc.get_str("model").unwrap_or_default().to_string(),
+                    manufacturer: sensor_doc.get_str("manufacturer").unwrap_or_default().to_string(),
+                }),
+                _ => Err(DbError::MalformedDocument(format!("sensor = {}", sensor_doc))),
+            }
+        })
+        .collect()
+}
+
+fn bson_as_f64(value: Option<&Bson>) -> Option<f64> {
+    match value {
+        Some(Bson::Double(value)) => Some(*value),
+        Some(Bson::Int64(value)) => Some(*value as f64),
+        Some(Bson::Int32(value)) => Some(*value as f64),
+        _ => None,
+    }
+}
@@ -0,0 +1,20 @@
+use sqlx::Postgres;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use tracing::info;
+
+use crate::db::sql::SqlSensorRepository;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// PostgreSQL storage, shared by many instances through a pool of connections
+pub type PostgresSensorRepository = SqlSensorRepository<Postgres>;
+
+impl SqlSensorRepository<Postgres> {
+    /// Create the pool, without connecting: connections are opened when needed.
+    /// Migrations are applied by `migrate`.
+    pub fn connect_lazy(options: PgConnectOptions, pool_options: PgPoolOptions, retention_days: Option<u64>) -> Self {
+        info!(target: "app", "PostgresSensorRepository - using {}:{}/{}", options.get_host(), options.get_port(), options.get_database().unwrap_or_default());
+        Self::new(pool_options.connect_lazy_with(options), &MIGRATOR, retention_days)
+    }
+}
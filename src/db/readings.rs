@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use mongodb::Database;
+use mongodb::bson::{Bson, doc};
+use mongodb::options::{TimeseriesGranularity, TimeseriesOptions};
+use tracing::{info, warn};
+
+use crate::db::collections;
+use crate::errors::db_error::DbError;
+
+/// Create the `readings` time-series collection, used to store the history of sensors values.
+/// Every reading has a `timestamp`, a `value` and a `meta` document with `deviceUuid`, `featureUuid` and `featureName`.
+/// Readings older than `retention_days` are removed by MongoDB, if None they are kept forever.
+pub async fn ensure_readings_collection(db: &Database, retention_days: Option<u64>) -> Result<(), DbError> {
+    info!(target: "app", "ensure_readings_collection - Verifying 'readings' collection...");
+    let retention: Option<Duration> = retention_days.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+
+    let collections = match db
+        .list_collections()
+        .filter(doc! {"name": collections::readings()})
+        .await
+    {
+        Ok(cursor) => match cursor.try_collect::<Vec<_>>().await {
+            Ok(collections) => collections,
+            Err(err) => return Err(DbError::from(err)),
+        },
+        Err(err) => return Err(DbError::from(err)),
+    };
+
+    match collections.first() {
+        Some(collection) => {
+            if collection.options.timeseries.is_none() {
+                warn!(target: "app", "ensure_readings_collection - drift: 'readings' is not a time-series collection");
+            }
+            if collection.options.expire_after_seconds != retention {
+                info!(target: "app", "ensure_readings_collection - Updating 'readings' retention to {:?} days", retention_days);
+                let expire_after_seconds: Bson = match retention {
+                    Some(retention) => Bson::Int64(retention.as_secs() as i64),
+                    None => Bson::String(String::from("off")),
+                };
+                let command = doc! {"collMod": collections::readings(), "expireAfterSeconds": expire_after_seconds};
+                if let Err(err) = db.run_command(command).await {
+                    return Err(DbError::from(err));
+                }
+            }
+        }
+        None => {
+            info!(target: "app", "ensure_readings_collection - Creating 'readings' time-series collection");
+            let timeseries = TimeseriesOptions::builder()
+                .time_field(String::from("timestamp"))
+                .meta_field(Some(String::from("meta")))
+                .granularity(Some(TimeseriesGranularity::Seconds))
+                .build();
+            let mut create_collection = db.create_collection(collections::readings()).timeseries(timeseries);
+            if let Some(retention) = retention {
+                create_collection = create_collection.expire_after_seconds(retention);
+            }
+            if let Err(err) = create_collection.await {
+                return Err(DbError::from(err));
+            }
+        }
+    }
+
+    info!(target: "app", "ensure_readings_collection - 'readings' collection verified");
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+
+use crate::errors::db_error::DbError;
+use crate::models::aggregation::{Agg, Bucket};
+use crate::models::health::HealthCheck;
+use crate::models::inputs::{RegisterInput, SortOrder};
+use crate::models::reading::{Reading, ReadingBucket};
+use crate::models::sensor::{RegisteredSensor, SensorSummary, SensorTypeStats, SensorValue};
+use crate::models::sensor_type::SensorType;
+
+/// Repository managed by Rocket, with the storage backend selected by `STORAGE_BACKEND`
+pub type DynSensorRepository = Box<dyn SensorRepository>;
+
+/// Storage of sensors and their readings.
+/// A sensor is identified by `device_uuid`, `feature_uuid` and `sensor_type`,
+/// and soft-deleted sensors are hidden, unless stated otherwise.
+#[rocket::async_trait]
+pub trait SensorRepository: Send + Sync {
+    /// Register a new sensor, or update the metadata of an already registered one,
+    /// restoring it if soft-deleted.
+    /// An already registered sensor is updated only if its current `apiToken` is the one of `input`
+    /// or, when `input` changes it, `api_token`, the bearer token of the caller,
+    /// otherwise it fails with `DbError::Forbidden`.
+    async fn upsert_sensor(
+        &self,
+        input: &RegisterInput,
+        sensor_type: SensorType,
+        api_token: Option<&str>,
+    ) -> Result<RegisteredSensor, DbError>;
+
+    /// Find the current value of a sensor, or None if the sensor doesn't exist.
+    async fn find_sensor_value(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+    ) -> Result<Option<SensorValue>, DbError>;
+
+    /// Update the value of a sensor appending a reading to its history,
+    /// or return None if the sensor doesn't exist.
+    async fn update_sensor_value(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        value: f64,
+    ) -> Result<Option<SensorValue>, DbError>;
+
+    /// Find at most `limit` readings of a sensor with `timestamp` in [from, to], sorted by `timestamp`.
+    #[allow(clippy::too_many_arguments)]
+    async fn find_readings(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        from: DateTime,
+        to: DateTime,
+        limit: i64,
+    ) -> Result<Vec<Reading>, DbError>;
+
+    /// Aggregate readings of a sensor with `timestamp` in [from, to) into time buckets,
+    /// returning every bucket, also the empty ones. `from` must be aligned to the bucket size.
+    #[allow(clippy::too_many_arguments)]
+    async fn aggregate_readings(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        from: DateTime,
+        to: DateTime,
+        bucket: Bucket,
+        agg: Agg,
+    ) -> Result<Vec<ReadingBucket>, DbError>;
+
+    /// Find `apiToken`s of all sensors of a device or, if `feature` is defined as `(feature_uuid, sensor_type)`,
+    /// of a single sensor.
+    async fn find_api_tokens(
+        &self,
+        device_uuid: &str,
+        feature: Option<(&str, SensorType)>,
+        include_deleted: bool,
+    ) -> Result<Vec<String>, DbError>;
+
+    /// Find `apiToken`s of all sensors of a profile
+    async fn find_profile_api_tokens(
+        &self,
+        profile_owner_id: ObjectId,
+        include_deleted: bool,
+    ) -> Result<Vec<String>, DbError>;
+
+    /// Find a page of sensors of a device, sorted by `modifiedAt`.
+    /// `after` is the (`modifiedAt`, `_id`) of the last sensor of the previous page.
+    async fn find_sensors_by_device(
+        &self,
+        device_uuid: &str,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Result<Vec<SensorSummary>, DbError>;
+
+    /// Find a page of sensors of a profile, sorted by `modifiedAt`.
+    /// `after` is the (`modifiedAt`, `_id`) of the last sensor of the previous page.
+    async fn find_sensors_by_profile(
+        &self,
+        profile_owner_id: ObjectId,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Result<Vec<SensorSummary>, DbError>;
+
+    /// Delete all sensors of a device owned by `api_token` or, if `feature` is defined, a single sensor,
+    /// returning the number of deleted sensors.
+    /// Sensors are soft-deleted, unless `purge` is true to remove them with their readings.
+    async fn delete_sensors(
+        &self,
+        device_uuid: &str,
+        feature: Option<(&str, SensorType)>,
+        api_token: &str,
+        purge: bool,
+    ) -> Result<u64, DbError>;
+
+    /// Count the registered sensors of every type, and the ones not modified since `stale_before`.
+    async fn sensors_stats(&self, stale_before: DateTime) -> Result<Vec<SensorTypeStats>, DbError>;
+
+    /// Check that the storage answers, used by the readiness probe.
+    async fn ping(&self) -> Result<(), DbError>;
+
+    /// Check that the schema of the storage (indexes, migrations...) is the expected one,
+    /// used by the readiness probe after a successful `ping`.
+    async fn check_schema(&self) -> Vec<HealthCheck> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+use tracing::{error, info, warn};
+
+use crate::config::Env;
+
+// defaults of the retry policy, if not defined in env
+const DEFAULT_INITIAL_DELAY_MS: u64 = 100;
+const DEFAULT_MULTIPLIER: f64 = 2.0;
+const DEFAULT_MAX_DELAY_MS: u64 = 10_000;
+const DEFAULT_JITTER: f64 = 0.2;
+const DEFAULT_MAX_ELAPSED_SECS: u64 = 60;
+
+/// Exponential backoff between attempts to connect to the database
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub initial_delay: Duration,
+    // delay growth after every failed attempt, at least 1
+    pub multiplier: f64,
+    pub max_delay: Duration,
+    // delays are randomly changed of +/- this fraction, between 0 and 1
+    pub jitter: f64,
+    // stop retrying when the next attempt would start after this time, never if None
+    pub max_elapsed: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Read the policy from `DB_RETRY_*` env vars, using defaults for the missing ones
+    pub fn from_env(env: &Env) -> Result<Self, String> {
+        let policy = Self {
+            initial_delay: Duration::from_millis(env.db_retry_initial_delay_ms.unwrap_or(DEFAULT_INITIAL_DELAY_MS)),
+            multiplier: env.db_retry_multiplier.unwrap_or(DEFAULT_MULTIPLIER),
+            max_delay: Duration::from_millis(env.db_retry_max_delay_ms.unwrap_or(DEFAULT_MAX_DELAY_MS)),
+            jitter: env.db_retry_jitter.unwrap_or(DEFAULT_JITTER),
+            max_elapsed: Some(Duration::from_secs(
+                env.db_retry_max_elapsed_secs.unwrap_or(DEFAULT_MAX_ELAPSED_SECS),
+            )),
+        };
+        if !(policy.multiplier >= 1.0 && policy.multiplier.is_finite()) {
+            return Err(format!(
+                "DB_RETRY_MULTIPLIER = {} must be at least 1",
+                policy.multiplier
+            ));
+        }
+        if !(0.0..=1.0).contains(&policy.jitter) {
+            return Err(format!("DB_RETRY_JITTER = {} must be between 0 and 1", policy.jitter));
+        }
+        if policy.initial_delay > policy.max_delay {
+            return Err(format!(
+                "DB_RETRY_INITIAL_DELAY_MS = {} must not be greater than DB_RETRY_MAX_DELAY_MS = {}",
+                policy.initial_delay.as_millis(),
+                policy.max_delay.as_millis()
+            ));
+        }
+        Ok(policy)
+    }
+
+    /// Same policy, retrying forever
+    pub fn forever(self) -> Self {
+        Self {
+            max_elapsed: None,
+            ..self
+        }
+    }
+
+    /// Delay after the failed `attempt` (starting from 0), with `random` between 0 and 1 to apply the jitter
+    pub fn delay(&self, attempt: u32, random: f64) -> Duration {
+        let exponential = self.initial_delay.as_secs_f64() * self.multiplier.powi(attempt.min(i32::MAX as u32) as i32);
+        let capped = exponential.min(self.max_delay.as_secs_f64());
+        // random between 0 and 1 becomes a factor between 1 - jitter and 1 + jitter
+        let jittered = capped * (1.0 + self.jitter * (2.0 * random - 1.0));
+        Duration::from_secs_f64(jittered.clamp(0.0, self.max_delay.as_secs_f64()))
+    }
+}
+
+/// Source of time for retries, replaced in tests by a fake one
+#[rocket::async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    async fn sleep(&self, duration: Duration);
+}
+
+/// Real time, sleeping with tokio
+pub struct SystemClock;
+
+#[rocket::async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        rocket::tokio::time::sleep(duration).await
+    }
+}
+
+/// Call `operation` until it succeeds, waiting between attempts as defined by `policy`.
+/// It returns the last error when `policy.max_elapsed` is reached.
+pub async fn retry_with_backoff<T, E, Fut, F>(
+    name: &str,
+    policy: &RetryPolicy,
+    clock: &impl Clock,
+    operation: F,
+) -> Result<T, E>
+where
+    E: Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    retry_transient_with_backoff(name, policy, clock, |_| true, operation).await
+}
+
+/// Like `retry_with_backoff`, but it returns immediately an error that `is_transient` rejects,
+/// because retrying cannot fix it
+pub async fn retry_transient_with_backoff<T, E, Fut, F>(
+    name: &str,
+    policy: &RetryPolicy,
+    clock: &impl Clock,
+    is_transient: impl Fn(&E) -> bool,
+    mut operation: F,
+) -> Result<T, E>
+where
+    E: Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let started_at = clock.now();
+    let mut attempt: u32 = 0;
+    loop {
+        let error = match operation().await {
+            Ok(value) => {
+                info!(target: "app", "{} - succeeded after {} retries", name, attempt);
+                return Ok(value);
+            }
+            Err(error) => error,
+        };
+        if !is_transient(&error) {
+            error!(target: "app", "{} - failed after {} retries, not retrying = {:?}", name, attempt, error);
+            return Err(error);
+        }
+        let delay = policy.delay(attempt, random_unit());
+        if let Some(max_elapsed) = policy.max_elapsed
+            && clock.now().duration_since(started_at) + delay > max_elapsed
+        {
+            error!(target: "app", "{} - failed after {} retries, giving up = {:?}", name, attempt, error);
+            return Err(error);
+        }
+        attempt += 1;
+        warn!(target: "app", "{} - failed (retry={}), retrying in {} ms = {:?}", name, attempt, delay.as_millis(), error);
+        clock.sleep(delay).await;
+    }
+}
+
+// random number between 0 and 1, good enough for jitter
+fn random_unit() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -1,60 +1,134 @@
+use std::collections::HashMap;
+
+use futures::TryStreamExt;
 use tracing::{debug, error, info};
 
 use mongodb::Database;
-use mongodb::bson::{Bson, Document, doc};
-use rocket::serde::json::Json;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{Bson, DateTime, Document, doc};
+use mongodb::options::ReturnDocument;
 
+use crate::db::collections;
 use crate::errors::db_error::DbError;
-use crate::models::inputs::RegisterInput;
-use crate::models::sensor::{FloatSensor, IntSensor, new_from_register_input};
+use crate::models::aggregation::{Agg, Bucket};
+use crate::models::inputs::{RegisterInput, SortOrder};
+use crate::models::sensor::{FloatSensor, IntSensor, RegisteredSensor, new_from_register_input};
+use crate::models::sensor_type::{SensorType, ValueKind};
 
-pub async fn insert_sensor(db: &Database, input: Json<RegisterInput>, sensor_type: &str) -> Result<String, DbError> {
-    info!(target: "app", "insert_sensor - Called with sensor_type = {}", sensor_type);
+// fields that identify a sensor feature, used as upsert key
+static SENSOR_KEY_FIELDS: &[&str] = &["deviceUuid", "featureUuid", "featureName"];
+// fields refreshed every time a sensor registers again, proving it owns its current apiToken
+static SENSOR_MUTABLE_FIELDS: &[&str] = &["apiToken", "mac", "model", "manufacturer", "modifiedAt"];
 
-    let collection = db.collection::<Document>("sensors");
+pub async fn upsert_sensor(
+    db: &Database,
+    input: &RegisterInput,
+    sensor_type: SensorType,
+    api_token: Option<&str>,
+) -> Result<RegisteredSensor, DbError> {
+    info!(target: "app", "upsert_sensor - Called with sensor_type = {}", sensor_type);
 
-    let serialized_input: Bson = match sensor_type {
-        "temperature" | "humidity" | "light" | "airpressure" => {
-            let result = new_from_register_input::<FloatSensor>(input, sensor_type);
-            match result {
-                Ok(res) => res,
-                Err(err) => return Err(DbError::new(err.to_string())),
-            }
-        }
-        "motion" | "airquality" | "online" => {
-            let result = new_from_register_input::<IntSensor>(input, sensor_type);
-            match result {
-                Ok(res) => res,
-                Err(err) => return Err(DbError::new(err.to_string())),
-            }
-        }
-        _ => {
-            error!(target: "app", "insert_sensor - Unknown sensor_type = {}", sensor_type);
-            return Err(DbError::new(format!("Unknown sensor_type = {}", sensor_type)));
-        }
+    let collection = db.collection::<Document>(collections::sensors());
+
+    let result = match sensor_type.kind() {
+        ValueKind::Float => new_from_register_input::<FloatSensor>(input, sensor_type),
+        ValueKind::Int => new_from_register_input::<IntSensor>(input, sensor_type),
+    };
+    let document = match result? {
+        Bson::Document(document) => document,
+        _ => return Err(DbError::MalformedDocument(String::from("Cannot serialize sensor"))),
+    };
+    let new_id = match document.get_object_id("_id") {
+        Ok(id) => id,
+        Err(err) => return Err(DbError::MalformedDocument(format!("sensor _id: {}", err))),
     };
 
-    debug!(target: "app", "insert_sensor - Adding sensor into db");
+    // split the new sensor document into:
+    // - the key used to find an already registered sensor
+    // - metadata to refresh on every registration
+    // - everything else (_id, value, createdAt...) written only when the sensor is created
+    let mut filter = Document::new();
+    let mut set = Document::new();
+    let mut set_on_insert = Document::new();
+    for (key, value) in document {
+        if SENSOR_KEY_FIELDS.contains(&key.as_str()) {
+            filter.insert(key, value);
+        } else if SENSOR_MUTABLE_FIELDS.contains(&key.as_str()) {
+            set.insert(key, value);
+        } else {
+            set_on_insert.insert(key, value);
+        }
+    }
 
-    let document = serialized_input.as_document().unwrap();
-    let insert_one_result = collection.insert_one(document.to_owned()).await.unwrap();
-    Ok(insert_one_result.inserted_id.as_object_id().unwrap().to_hex())
+    // an already registered sensor is updated only if its current 'apiToken' is the one of the input,
+    // or the bearer token of the caller, when the input changes it
+    debug!(target: "app", "upsert_sensor - Updating sensor into db");
+    let mut api_tokens = vec![input.apiToken.as_str()];
+    api_tokens.extend(api_token);
+    let mut token_filter = filter.clone();
+    token_filter.insert("apiToken", doc! {"$in": api_tokens});
+    let update = doc! {
+        "$set": set.clone(),
+        // registering again a soft-deleted sensor restores it
+        "$unset": {"deletedAt": ""},
+    };
+    let updated = collection
+        .find_one_and_update(token_filter, update)
+        .projection(doc! {"_id": 1})
+        .await?;
+    if let Some(doc) = updated {
+        return match doc.get_object_id("_id") {
+            Ok(id) => Ok(RegisteredSensor {
+                id: id.to_hex(),
+                created: false,
+            }),
+            Err(err) => Err(DbError::MalformedDocument(format!("sensor _id: {}", err))),
+        };
+    }
+
+    // otherwise the sensor is created, without changing a sensor registered in the meantime
+    debug!(target: "app", "upsert_sensor - Adding sensor into db");
+    set_on_insert.extend(set);
+    match collection
+        .find_one_and_update(filter, doc! {"$setOnInsert": set_on_insert})
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .projection(doc! {"_id": 1})
+        .await
+    {
+        // if the returned '_id' is the one generated here, the sensor has just been created
+        Ok(Some(doc)) => match doc.get_object_id("_id") {
+            Ok(id) if id == new_id => Ok(RegisteredSensor {
+                id: id.to_hex(),
+                created: true,
+            }),
+            Ok(_) => Err(DbError::Forbidden(String::from(
+                "apiToken doesn't match the registered sensor",
+            ))),
+            Err(err) => Err(DbError::MalformedDocument(format!("sensor _id: {}", err))),
+        },
+        Ok(None) => Err(DbError::Query(String::from("Cannot upsert sensor"))),
+        Err(err) => Err(DbError::from(err)),
+    }
 }
 
+/// Find the `value`, `createdAt` and `modifiedAt` of a registered sensor, or None if the sensor doesn't exist.
 pub async fn find_sensor_value_by_uuid(
     db: &Database,
     device_uuid: &str,
     sensor_uuid: &str,
-    sensor_type: &str,
-) -> Result<Document, DbError> {
+    sensor_type: SensorType,
+) -> Result<Option<Document>, DbError> {
     info!(target: "app", "find_sensor_value_by_uuid - Called with sensor_type = {}, device_uuid = {}, sensor_uuid = {}", sensor_type, device_uuid, sensor_uuid);
-    let collection = db.collection::<Document>("sensors");
+    let collection = db.collection::<Document>(collections::sensors());
 
     // find by uuid
     let filter = doc! {
         "deviceUuid": device_uuid,
         "featureUuid": sensor_uuid,
-        "featureName": sensor_type,
+        "featureName": sensor_type.as_str(),
+        // soft-deleted sensors are hidden
+        "deletedAt": {"$exists": false},
     };
     // limit the output to {"value", "createdAt" and "modifiedAt"}
     let projection = doc! {"_id": 0, "value": 1, "createdAt": 1, "modifiedAt": 1};
@@ -62,10 +136,376 @@ pub async fn find_sensor_value_by_uuid(
     debug!(target: "app", "find_sensor_value_by_uuid - Getting sensor value with device_uuid = {} and sensor_uuid = {} from db", device_uuid, sensor_uuid);
 
     match collection.find_one(filter).projection(projection).await {
-        Ok(doc_result) => match doc_result {
-            Some(doc) => Ok(doc),
-            None => Err(DbError::new(String::from("Cannot find sensor"))),
+        Ok(doc_result) => Ok(doc_result),
+        Err(err) => Err(DbError::from(err)),
+    }
+}
+
+/// Update the value of a registered sensor, returning the updated `value`, `createdAt` and `modifiedAt`,
+/// or None if the sensor doesn't exist.
+/// The reading is appended to the history after the update, without a transaction: if that fails,
+/// the error is logged and the updated value is returned anyway, with a missing reading in the history.
+pub async fn update_sensor_value(
+    db: &Database,
+    device_uuid: &str,
+    feature_uuid: &str,
+    sensor_type: SensorType,
+    value: Bson,
+) -> Result<Option<Document>, DbError> {
+    info!(target: "app", "update_sensor_value - Called with sensor_type = {}, device_uuid = {}, feature_uuid = {}", sensor_type, device_uuid, feature_uuid);
+    let collection = db.collection::<Document>(collections::sensors());
+
+    let filter = sensors_filter(device_uuid, Some((feature_uuid, sensor_type)), false);
+    let date_now = DateTime::now();
+    let update = doc! {"$set": {"value": value.clone(), "modifiedAt": date_now}};
+    // limit the output to {"value", "createdAt" and "modifiedAt"}
+    let projection = doc! {"_id": 0, "value": 1, "createdAt": 1, "modifiedAt": 1};
+
+    debug!(target: "app", "update_sensor_value - Updating sensor value with device_uuid = {} and feature_uuid = {} in db", device_uuid, feature_uuid);
+
+    let sensor_doc = match collection
+        .find_one_and_update(filter, update)
+        .return_document(ReturnDocument::After)
+        .projection(projection)
+        .await
+    {
+        Ok(Some(doc)) => doc,
+        Ok(None) => return Ok(None),
+        Err(err) => return Err(DbError::from(err)),
+    };
+
+    debug!(target: "app", "update_sensor_value - Appending reading to history in db");
+
+    let reading = doc! {
+        "timestamp": date_now,
+        "meta": readings_meta(device_uuid, feature_uuid, sensor_type),
+        "value": value,
+    };
+    if let Err(err) = db
+        .collection::<Document>(collections::readings())
+        .insert_one(reading)
+        .await
+    {
+        error!(target: "app", "update_sensor_value - cannot append reading to history, error = {:?}", err);
+    }
+    Ok(Some(sensor_doc))
+}
+
+/// Find readings of a sensor with `timestamp` in [from, to], sorted by `timestamp`,
+/// limiting the output to `value` and `timestamp`.
+pub async fn find_readings(
+    db: &Database,
+    device_uuid: &str,
+    feature_uuid: &str,
+    sensor_type: SensorType,
+    from: DateTime,
+    to: DateTime,
+    limit: i64,
+) -> Result<Vec<Document>, DbError> {
+    info!(target: "app", "find_readings - Called with sensor_type = {}, device_uuid = {}, feature_uuid = {}", sensor_type, device_uuid, feature_uuid);
+    let collection = db.collection::<Document>(collections::readings());
+
+    let mut filter = readings_filter(device_uuid, Some((feature_uuid, sensor_type)));
+    filter.insert("timestamp", doc! {"$gte": from, "$lte": to});
+    let projection = doc! {"_id": 0, "value": 1, "timestamp": 1};
+
+    debug!(target: "app", "find_readings - Getting readings from {} to {} from db", from, to);
+
+    match collection
+        .find(filter)
+        .projection(projection)
+        .sort(doc! {"timestamp": 1})
+        .limit(limit)
+        .await
+    {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(documents) => Ok(documents),
+            Err(err) => Err(DbError::from(err)),
+        },
+        Err(err) => Err(DbError::from(err)),
+    }
+}
+
+/// Aggregate readings of a sensor with `timestamp` in [from, to) into time buckets.
+/// The result has a document for every bucket with `timestamp` (the start of the bucket) and `value`,
+/// so empty buckets are included with a null `value` (or 0 when counting).
+/// `from` must be aligned to the bucket size.
+#[allow(clippy::too_many_arguments)]
+pub async fn aggregate_readings(
+    db: &Database,
+    device_uuid: &str,
+    feature_uuid: &str,
+    sensor_type: SensorType,
+    from: DateTime,
+    to: DateTime,
+    bucket: Bucket,
+    agg: Agg,
+) -> Result<Vec<Document>, DbError> {
+    info!(target: "app", "aggregate_readings - Called with sensor_type = {}, device_uuid = {}, feature_uuid = {}, bucket = {:?}, agg = {:?}", sensor_type, device_uuid, feature_uuid, bucket, agg);
+    let collection = db.collection::<Document>(collections::readings());
+
+    let mut filter = readings_filter(device_uuid, Some((feature_uuid, sensor_type)));
+    filter.insert("timestamp", doc! {"$gte": from, "$lt": to});
+    let pipeline = vec![
+        doc! {"$match": filter},
+        // sort is required by '$last'
+        doc! {"$sort": {"timestamp": 1}},
+        doc! {"$group": {
+            "_id": {"$dateTrunc": {"date": "$timestamp", "unit": bucket.unit(), "binSize": bucket.bin_size()}},
+            "value": agg.accumulator(),
+        }},
+        doc! {"$project": {"_id": 0, "timestamp": "$_id", "value": 1}},
+    ];
+
+    debug!(target: "app", "aggregate_readings - Aggregating readings from {} to {} in db", from, to);
+
+    let documents: Vec<Document> = match collection.aggregate(pipeline).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(documents) => documents,
+            Err(err) => return Err(DbError::from(err)),
+        },
+        Err(err) => return Err(DbError::from(err)),
+    };
+
+    // gap-fill adding a document for every empty bucket
+    let mut values: HashMap<i64, Bson> = HashMap::new();
+    for document in documents {
+        match (document.get_datetime("timestamp"), document.get("value")) {
+            (Ok(timestamp), Some(value)) => values.insert(timestamp.timestamp_millis(), value.clone()),
+            _ => return Err(DbError::MalformedDocument(format!("bucket = {}", document))),
+        };
+    }
+    let empty_value = match agg {
+        Agg::Count => Bson::Int32(0),
+        _ => Bson::Null,
+    };
+    let buckets = (from.timestamp_millis()..to.timestamp_millis())
+        .step_by(bucket.millis() as usize)
+        .map(|timestamp| {
+            doc! {
+                "timestamp": DateTime::from_millis(timestamp),
+                "value": values.remove(&timestamp).unwrap_or(empty_value.clone()),
+            }
+        })
+        .collect();
+    Ok(buckets)
+}
+
+/// Find `apiToken`s of all sensors of a device or, if `feature` is defined as `(feature_uuid, sensor_type)`,
+/// of a single sensor.
+pub async fn find_api_tokens(
+    db: &Database,
+    device_uuid: &str,
+    feature: Option<(&str, SensorType)>,
+    include_deleted: bool,
+) -> Result<Vec<String>, DbError> {
+    info!(target: "app", "find_api_tokens - Called with device_uuid = {}, feature = {:?}", device_uuid, feature);
+    find_api_tokens_by_filter(db, sensors_filter(device_uuid, feature, include_deleted)).await
+}
+
+/// Find `apiToken`s of all sensors of a profile
+pub async fn find_profile_api_tokens(
+    db: &Database,
+    profile_owner_id: ObjectId,
+    include_deleted: bool,
+) -> Result<Vec<String>, DbError> {
+    info!(target: "app", "find_profile_api_tokens - Called with profile_owner_id = {}", profile_owner_id);
+    let mut filter = doc! {"profileOwnerId": profile_owner_id};
+    if !include_deleted {
+        filter.insert("deletedAt", doc! {"$exists": false});
+    }
+    find_api_tokens_by_filter(db, filter).await
+}
+
+/// Find a page of sensors of a device, sorted by `modifiedAt`.
+/// `after` is the (`modifiedAt`, `_id`) of the last sensor of the previous page.
+pub async fn find_sensors_by_device(
+    db: &Database,
+    device_uuid: &str,
+    feature_name: Option<SensorType>,
+    sort_order: SortOrder,
+    limit: i64,
+    after: Option<(DateTime, ObjectId)>,
+) -> Result<Vec<Document>, DbError> {
+    info!(target: "app", "find_sensors_by_device - Called with device_uuid = {}, feature_name = {:?}", device_uuid, feature_name);
+    let filter = doc! {"deviceUuid": device_uuid};
+    find_sensors_page(db, filter, feature_name, sort_order, limit, after).await
+}
+
+/// Find a page of sensors of a profile, sorted by `modifiedAt`.
+/// `after` is the (`modifiedAt`, `_id`) of the last sensor of the previous page.
+pub async fn find_sensors_by_profile(
+    db: &Database,
+    profile_owner_id: ObjectId,
+    feature_name: Option<SensorType>,
+    sort_order: SortOrder,
+    limit: i64,
+    after: Option<(DateTime, ObjectId)>,
+) -> Result<Vec<Document>, DbError> {
+    info!(target: "app", "find_sensors_by_profile - Called with profile_owner_id = {}, feature_name = {:?}", profile_owner_id, feature_name);
+    let filter = doc! {"profileOwnerId": profile_owner_id};
+    find_sensors_page(db, filter, feature_name, sort_order, limit, after).await
+}
+
+/// Delete all sensors of a device owned by `api_token` or, if `feature` is defined as `(feature_uuid, sensor_type)`,
+/// a single sensor.
+/// Sensors are soft-deleted setting `deletedAt`, unless `purge` is true.
+pub async fn delete_sensors(
+    db: &Database,
+    device_uuid: &str,
+    feature: Option<(&str, SensorType)>,
+    api_token: &str,
+    purge: bool,
+) -> Result<u64, DbError> {
+    info!(target: "app", "delete_sensors - Called with device_uuid = {}, feature = {:?}, purge = {}", device_uuid, feature, purge);
+    let collection = db.collection::<Document>(collections::sensors());
+
+    // purge removes also soft-deleted sensors
+    let mut filter = sensors_filter(device_uuid, feature, purge);
+    filter.insert("apiToken", api_token);
+
+    if purge {
+        debug!(target: "app", "delete_sensors - Purging sensors and their readings from db");
+        let readings_filter = readings_filter(device_uuid, feature);
+        let deleted_count = match collection.delete_many(filter).await {
+            Ok(delete_result) => delete_result.deleted_count,
+            Err(err) => return Err(DbError::from(err)),
+        };
+        match db
+            .collection::<Document>(collections::readings())
+            .delete_many(readings_filter)
+            .await
+        {
+            Ok(_) => Ok(deleted_count),
+            Err(err) => Err(DbError::from(err)),
+        }
+    } else {
+        debug!(target: "app", "delete_sensors - Soft-deleting sensors in db");
+        let date_now = DateTime::now();
+        let update = doc! {"$set": {"deletedAt": date_now, "modifiedAt": date_now}};
+        match collection.update_many(filter, update).await {
+            Ok(update_result) => Ok(update_result.modified_count),
+            Err(err) => Err(DbError::from(err)),
+        }
+    }
+}
+
+async fn find_api_tokens_by_filter(db: &Database, filter: Document) -> Result<Vec<String>, DbError> {
+    let collection = db.collection::<Document>(collections::sensors());
+    let projection = doc! {"_id": 0, "apiToken": 1};
+
+    let documents: Vec<Document> = match collection.find(filter).projection(projection).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(documents) => documents,
+            Err(err) => return Err(DbError::from(err)),
+        },
+        Err(err) => return Err(DbError::from(err)),
+    };
+    documents
+        .iter()
+        .map(|document| match document.get_str("apiToken") {
+            Ok(api_token) => Ok(api_token.to_string()),
+            Err(err) => Err(DbError::MalformedDocument(format!("sensor apiToken: {}", err))),
+        })
+        .collect()
+}
+
+async fn find_sensors_page(
+    db: &Database,
+    mut filter: Document,
+    feature_name: Option<SensorType>,
+    sort_order: SortOrder,
+    limit: i64,
+    after: Option<(DateTime, ObjectId)>,
+) -> Result<Vec<Document>, DbError> {
+    let collection = db.collection::<Document>(collections::sensors());
+
+    filter.insert("deletedAt", doc! {"$exists": false});
+    if let Some(feature_name) = feature_name {
+        filter.insert("featureName", feature_name.as_str());
+    }
+    // sort by 'modifiedAt' and '_id', to have a stable order when two sensors have the same 'modifiedAt'
+    let (direction, operator) = match sort_order {
+        SortOrder::Asc => (1, "$gt"),
+        SortOrder::Desc => (-1, "$lt"),
+    };
+    if let Some((modified_at, id)) = after {
+        filter.insert(
+            "$or",
+            vec![
+                doc! {"modifiedAt": {operator: modified_at}},
+                doc! {"modifiedAt": modified_at, "_id": {operator: id}},
+            ],
+        );
+    }
+    let projection = doc! {"apiToken": 0, "profileOwnerId": 0};
+
+    debug!(target: "app", "find_sensors_page - Getting sensors from db with filter = {}", filter);
+
+    match collection
+        .find(filter)
+        .projection(projection)
+        .sort(doc! {"modifiedAt": direction, "_id": direction})
+        .limit(limit)
+        .await
+    {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(documents) => Ok(documents),
+            Err(err) => Err(DbError::from(err)),
+        },
+        Err(err) => Err(DbError::from(err)),
+    }
+}
+
+fn sensors_filter(device_uuid: &str, feature: Option<(&str, SensorType)>, include_deleted: bool) -> Document {
+    let mut filter = doc! {"deviceUuid": device_uuid};
+    if let Some((feature_uuid, sensor_type)) = feature {
+        filter.insert("featureUuid", feature_uuid);
+        filter.insert("featureName", sensor_type.as_str());
+    }
+    if !include_deleted {
+        filter.insert("deletedAt", doc! {"$exists": false});
+    }
+    filter
+}
+
+fn readings_meta(device_uuid: &str, feature_uuid: &str, sensor_type: SensorType) -> Document {
+    doc! {
+        "deviceUuid": device_uuid,
+        "featureUuid": feature_uuid,
+        "featureName": sensor_type.as_str(),
+    }
+}
+
+fn readings_filter(device_uuid: &str, feature: Option<(&str, SensorType)>) -> Document {
+    let mut filter = doc! {"meta.deviceUuid": device_uuid};
+    if let Some((feature_uuid, sensor_type)) = feature {
+        filter.insert("meta.featureUuid", feature_uuid);
+        filter.insert("meta.featureName", sensor_type.as_str());
+    }
+    filter
+}
+
+/// Count sensors by `featureName`, as `registered`, and the ones with `modifiedAt` before `stale_before`, as `stale`.
+/// Soft-deleted sensors are not counted.
+pub async fn sensors_stats(db: &Database, stale_before: DateTime) -> Result<Vec<Document>, DbError> {
+    debug!(target: "app", "sensors_stats - Called with stale_before = {}", stale_before);
+    let collection = db.collection::<Document>(collections::sensors());
+
+    let pipeline = vec![
+        doc! {"$match": {"deletedAt": {"$exists": false}}},
+        doc! {"$group": {
+            "_id": "$featureName",
+            "registered": {"$sum": 1},
+            "stale": {"$sum": {"$cond": [{"$lt": ["$modifiedAt", stale_before]}, 1, 0]}},
+        }},
+        doc! {"$project": {"_id": 0, "featureName": "$_id", "registered": 1, "stale": 1}},
+    ];
+    match collection.aggregate(pipeline).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(documents) => Ok(documents),
+            Err(err) => Err(DbError::from(err)),
         },
-        Err(err) => Err(DbError::new(err.to_string())),
+        Err(err) => Err(DbError::from(err)),
     }
 }
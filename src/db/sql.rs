@@ -0,0 +1,571 @@
+use std::str::FromStr;
+
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::{ColumnIndex, Database, Decode, Encode, Executor, IntoArguments, Pool, Row, Type};
+use tracing::{debug, info};
+
+use crate::db::repository::SensorRepository;
+use crate::errors::db_error::DbError;
+use crate::models::aggregation::{Agg, Bucket, aggregate_in_buckets};
+use crate::models::health::HealthCheck;
+use crate::models::inputs::{RegisterInput, SortOrder};
+use crate::models::reading::{Reading, ReadingBucket};
+use crate::models::sensor::{RegisteredSensor, SensorSummary, SensorTypeStats, SensorValue};
+use crate::models::sensor_type::{SensorType, ValueKind};
+
+const SENSOR_SUMMARY_COLUMNS: &str =
+    "id, device_uuid, feature_uuid, feature_name, value, created_at, modified_at, mac, model, manufacturer";
+
+/// SQL database usable by `SqlSensorRepository`, with what sqlx doesn't define for every database
+pub trait SqlDatabase: Database {
+    fn rows_affected(result: &Self::QueryResult) -> u64;
+}
+
+impl SqlDatabase for sqlx::Sqlite {
+    fn rows_affected(result: &Self::QueryResult) -> u64 {
+        result.rows_affected()
+    }
+}
+
+impl SqlDatabase for sqlx::Postgres {
+    fn rows_affected(result: &Self::QueryResult) -> u64 {
+        result.rows_affected()
+    }
+}
+
+/// SQL storage, with the same queries for every `SqlDatabase`: `sqlite` and `postgres` only open the pool
+/// and define the migrations creating the `sensors` and `readings` tables.
+/// Dates are stored as milliseconds and ids as `ObjectId` hex strings, like in MongoDB.
+/// Clones share the same pool.
+pub struct SqlSensorRepository<DB: Database> {
+    pool: Pool<DB>,
+    migrator: &'static Migrator,
+    // days to keep readings, forever if None
+    retention_days: Option<u64>,
+}
+
+impl<DB: Database> Clone for SqlSensorRepository<DB> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            migrator: self.migrator,
+            retention_days: self.retention_days,
+        }
+    }
+}
+
+impl<DB: SqlDatabase> SqlSensorRepository<DB>
+where
+    DB::Connection: Migrate,
+{
+    pub fn new(pool: Pool<DB>, migrator: &'static Migrator, retention_days: Option<u64>) -> Self {
+        Self {
+            pool,
+            migrator,
+            retention_days,
+        }
+    }
+
+    /// Apply pending migrations
+    pub async fn migrate(&self) -> Result<(), DbError> {
+        self.migrator.run(&self.pool).await?;
+        Ok(())
+    }
+}
+
+impl<DB> SqlSensorRepository<DB>
+where
+    DB: SqlDatabase,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> DB::Arguments<'q>: IntoArguments<'q, DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+{
+    /// Delete the readings of every sensor older than `READINGS_RETENTION_DAYS`, returning how many.
+    /// SQL databases have no TTL, so it's run periodically by `db::spawn_readings_retention`.
+    pub async fn delete_expired_readings(&self) -> Result<u64, DbError> {
+        let Some(retention_days) = self.retention_days else {
+            return Ok(0);
+        };
+        let expired = DateTime::now().timestamp_millis() - (retention_days as i64) * 24 * 60 * 60 * 1000;
+        let result = sqlx::query::<DB>("DELETE FROM readings WHERE timestamp < $1")
+            .bind(expired)
+            .execute(&self.pool)
+            .await?;
+        Ok(DB::rows_affected(&result))
+    }
+}
+
+#[rocket::async_trait]
+impl<DB> SensorRepository for SqlSensorRepository<DB>
+where
+    DB: SqlDatabase,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> DB::Arguments<'q>: IntoArguments<'q, DB>,
+    for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+    for<'q> Option<&'q str>: Encode<'q, DB> + Type<DB>,
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> f64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'r> String: Decode<'r, DB>,
+    for<'r> &'r str: ColumnIndex<DB::Row>,
+{
+    async fn upsert_sensor(
+        &self,
+        input: &RegisterInput,
+        sensor_type: SensorType,
+        api_token: Option<&str>,
+    ) -> Result<RegisteredSensor, DbError> {
+        info!(target: "app", "upsert_sensor - Called with sensor_type = {}", sensor_type);
+        let profile_owner_id = ObjectId::from_str(input.profileOwnerId.as_str())
+            .map_err(|err| DbError::Validation(format!("profileOwnerId: {}", err)))?;
+        let new_id = ObjectId::new().to_hex();
+        let date_now = DateTime::now().timestamp_millis();
+
+        // registering again a soft-deleted sensor restores it.
+        // An already registered sensor is updated only if its 'api_token' is the one of the input,
+        // or the bearer token of the caller, otherwise no row is returned
+        let row = sqlx::query::<DB>(
+            "INSERT INTO sensors (id, profile_owner_id, api_token, device_uuid, mac, model, manufacturer, feature_uuid, feature_name, value, created_at, modified_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            ON CONFLICT (device_uuid, feature_uuid, feature_name) DO UPDATE SET
+                api_token = excluded.api_token,
+                mac = excluded.mac,
+                model = excluded.model,
+                manufacturer = excluded.manufacturer,
+                modified_at = excluded.modified_at,
+                deleted_at = NULL
+            WHERE sensors.api_token = excluded.api_token OR sensors.api_token = $13
+            RETURNING id",
+        )
+        .bind(&new_id)
+        .bind(profile_owner_id.to_hex())
+        .bind(&input.apiToken)
+        .bind(&input.deviceUuid)
+        .bind(&input.mac)
+        .bind(&input.model)
+        .bind(&input.manufacturer)
+        .bind(&input.featureUuid)
+        .bind(sensor_type.as_str())
+        .bind(sensor_type.default_value())
+        .bind(date_now)
+        .bind(date_now)
+        .bind(api_token)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Err(DbError::Forbidden(String::from(
+                "apiToken doesn't match the registered sensor",
+            )));
+        };
+
+        let id: String = row.try_get("id")?;
+        // if the returned 'id' is the one generated here, the sensor has just been created
+        let created = id == new_id;
+        Ok(RegisteredSensor { id, created })
+    }
+
+    async fn find_sensor_value(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+    ) -> Result<Option<SensorValue>, DbError> {
+        let row = sqlx::query::<DB>(
+            "SELECT value, created_at, modified_at FROM sensors
+            WHERE device_uuid = $1 AND feature_uuid = $2 AND feature_name = $3 AND deleted_at IS NULL",
+        )
+        .bind(device_uuid)
+        .bind(feature_uuid)
+        .bind(sensor_type.as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(Self::sensor_value).transpose()
+    }
+
+    async fn update_sensor_value(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        value: f64,
+    ) -> Result<Option<SensorValue>, DbError> {
+        let value = match sensor_type.kind() {
+            ValueKind::Float => value,
+            ValueKind::Int => value.trunc(),
+        };
+        let date_now = DateTime::now().timestamp_millis();
+
+        let mut transaction = self.pool.begin().await?;
+        let row = sqlx::query::<DB>(
+            "UPDATE sensors SET value = $1, modified_at = $2
+            WHERE device_uuid = $3 AND feature_uuid = $4 AND feature_name = $5 AND deleted_at IS NULL
+            RETURNING value, created_at, modified_at",
+        )
+        .bind(value)
+        .bind(date_now)
+        .bind(device_uuid)
+        .bind(feature_uuid)
+        .bind(sensor_type.as_str())
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        debug!(target: "app", "update_sensor_value - Appending reading to history in db");
+        sqlx::query::<DB>(
+            "INSERT INTO readings (device_uuid, feature_uuid, feature_name, timestamp, value) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(device_uuid)
+        .bind(feature_uuid)
+        .bind(sensor_type.as_str())
+        .bind(date_now)
+        .bind(value)
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Self::sensor_value(&row).map(Some)
+    }
+
+    async fn find_readings(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        from: DateTime,
+        to: DateTime,
+        limit: i64,
+    ) -> Result<Vec<Reading>, DbError> {
+        let rows = sqlx::query::<DB>(
+            "SELECT timestamp, value FROM readings
+            WHERE device_uuid = $1 AND feature_uuid = $2 AND feature_name = $3 AND timestamp >= $4 AND timestamp <= $5
+            ORDER BY timestamp ASC LIMIT $6",
+        )
+        .bind(device_uuid)
+        .bind(feature_uuid)
+        .bind(sensor_type.as_str())
+        .bind(from.timestamp_millis())
+        .bind(to.timestamp_millis())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(Self::reading).collect()
+    }
+
+    async fn aggregate_readings(
+        &self,
+        device_uuid: &str,
+        feature_uuid: &str,
+        sensor_type: SensorType,
+        from: DateTime,
+        to: DateTime,
+        bucket: Bucket,
+        agg: Agg,
+    ) -> Result<Vec<ReadingBucket>, DbError> {
+        let rows = sqlx::query::<DB>(
+            "SELECT timestamp, value FROM readings
+            WHERE device_uuid = $1 AND feature_uuid = $2 AND feature_name = $3 AND timestamp >= $4 AND timestamp < $5
+            ORDER BY timestamp ASC",
+        )
+        .bind(device_uuid)
+        .bind(feature_uuid)
+        .bind(sensor_type.as_str())
+        .bind(from.timestamp_millis())
+        .bind(to.timestamp_millis())
+        .fetch_all(&self.pool)
+        .await?;
+        let readings: Vec<Reading> = rows.iter().map(Self::reading).collect::<Result<_, _>>()?;
+        Ok(aggregate_in_buckets(&readings, from, to, bucket, agg))
+    }
+
+    async fn find_api_tokens(
+        &self,
+        device_uuid: &str,
+        feature: Option<(&str, SensorType)>,
+        include_deleted: bool,
+    ) -> Result<Vec<String>, DbError> {
+        let sql = format!(
+            "SELECT api_token FROM sensors WHERE {}",
+            sensors_filter(1, feature.is_some(), include_deleted)
+        );
+        let mut query = sqlx::query::<DB>(&sql).bind(device_uuid);
+        if let Some((feature_uuid, sensor_type)) = feature {
+            query = query.bind(feature_uuid).bind(sensor_type.as_str());
+        }
+        let rows = query.fetch_all(&self.pool).await?;
+        rows.iter()
+            .map(|row| row.try_get("api_token").map_err(DbError::from))
+            .collect()
+    }
+
+    async fn find_profile_api_tokens(
+        &self,
+        profile_owner_id: ObjectId,
+        include_deleted: bool,
+    ) -> Result<Vec<String>, DbError> {
+        let sql = if include_deleted {
+            "SELECT api_token FROM sensors WHERE profile_owner_id = $1"
+        } else {
+            "SELECT api_token FROM sensors WHERE profile_owner_id = $1 AND deleted_at IS NULL"
+        };
+        let rows = sqlx::query::<DB>(sql)
+            .bind(profile_owner_id.to_hex())
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| row.try_get("api_token").map_err(DbError::from))
+            .collect()
+    }
+
+    async fn find_sensors_by_device(
+        &self,
+        device_uuid: &str,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Result<Vec<SensorSummary>, DbError> {
+        self.find_sensors_page(
+            "device_uuid",
+            device_uuid.to_string(),
+            feature_name,
+            sort_order,
+            limit,
+            after,
+        )
+        .await
+    }
+
+    async fn find_sensors_by_profile(
+        &self,
+        profile_owner_id: ObjectId,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Result<Vec<SensorSummary>, DbError> {
+        self.find_sensors_page(
+            "profile_owner_id",
+            profile_owner_id.to_hex(),
+            feature_name,
+            sort_order,
+            limit,
+            after,
+        )
+        .await
+    }
+
+    async fn delete_sensors(
+        &self,
+        device_uuid: &str,
+        feature: Option<(&str, SensorType)>,
+        api_token: &str,
+        purge: bool,
+    ) -> Result<u64, DbError> {
+        info!(target: "app", "delete_sensors - Called with device_uuid = {}, feature = {:?}, purge = {}", device_uuid, feature, purge);
+        let mut transaction = self.pool.begin().await?;
+        // purge removes also soft-deleted sensors
+        let sql = if purge {
+            format!(
+                "DELETE FROM sensors WHERE api_token = $1 AND {}",
+                sensors_filter(2, feature.is_some(), true)
+            )
+        } else {
+            format!(
+                "UPDATE sensors SET deleted_at = $2, modified_at = $2 WHERE api_token = $1 AND {}",
+                sensors_filter(3, feature.is_some(), false)
+            )
+        };
+        let mut query = sqlx::query::<DB>(&sql).bind(api_token);
+        if !purge {
+            query = query.bind(DateTime::now().timestamp_millis());
+        }
+        query = query.bind(device_uuid);
+        if let Some((feature_uuid, sensor_type)) = feature {
+            query = query.bind(feature_uuid).bind(sensor_type.as_str());
+        }
+        let result = query.execute(&mut *transaction).await?;
+        let deleted_count = DB::rows_affected(&result);
+
+        if purge {
+            debug!(target: "app", "delete_sensors - Purging readings from db");
+            let sql = format!(
+                "DELETE FROM readings WHERE {}",
+                sensors_filter(1, feature.is_some(), true)
+            );
+            let mut query = sqlx::query::<DB>(&sql).bind(device_uuid);
+            if let Some((feature_uuid, sensor_type)) = feature {
+                query = query.bind(feature_uuid).bind(sensor_type.as_str());
+            }
+            query.execute(&mut *transaction).await?;
+        }
+        transaction.commit().await?;
+        Ok(deleted_count)
+    }
+
+    async fn sensors_stats(&self, stale_before: DateTime) -> Result<Vec<SensorTypeStats>, DbError> {
+        let rows = sqlx::query::<DB>(
+            "SELECT feature_name, COUNT(*) AS registered, SUM(CASE WHEN modified_at < $1 THEN 1 ELSE 0 END) AS stale
+            FROM sensors WHERE deleted_at IS NULL GROUP BY feature_name",
+        )
+        .bind(stale_before.timestamp_millis())
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(Self::sensor_type_stats).collect()
+    }
+
+    async fn ping(&self) -> Result<(), DbError> {
+        sqlx::query::<DB>("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(DbError::from)
+    }
+
+    async fn check_schema(&self) -> Vec<HealthCheck> {
+        let applied_versions: Vec<i64> = match sqlx::query::<DB>("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(&self.pool)
+            .await
+            .and_then(|rows| rows.iter().map(|row| row.try_get("version")).collect())
+        {
+            Ok(versions) => versions,
+            Err(err) => return vec![HealthCheck::down("migrations", err.to_string())],
+        };
+        let pending: Vec<&str> = self
+            .migrator
+            .iter()
+            .filter(|migration| !applied_versions.contains(&migration.version))
+            .map(|migration| migration.description.as_ref())
+            .collect();
+        if pending.is_empty() {
+            vec![HealthCheck::up("migrations")]
+        } else {
+            vec![HealthCheck::down(
+                "migrations",
+                format!("pending migrations: {}", pending.join(", ")),
+            )]
+        }
+    }
+}
+
+impl<DB> SqlSensorRepository<DB>
+where
+    DB: SqlDatabase,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> DB::Arguments<'q>: IntoArguments<'q, DB>,
+    for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+    for<'q> Option<&'q str>: Encode<'q, DB> + Type<DB>,
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> f64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'r> String: Decode<'r, DB>,
+    for<'r> &'r str: ColumnIndex<DB::Row>,
+{
+    async fn find_sensors_page(
+        &self,
+        column: &str,
+        id: String,
+        feature_name: Option<SensorType>,
+        sort_order: SortOrder,
+        limit: i64,
+        after: Option<(DateTime, ObjectId)>,
+    ) -> Result<Vec<SensorSummary>, DbError> {
+        // sort by 'modified_at' and 'id', to have a stable order when two sensors have the same 'modified_at'
+        let (direction, operator) = match sort_order {
+            SortOrder::Asc => ("ASC", ">"),
+            SortOrder::Desc => ("DESC", "<"),
+        };
+        let mut sql = format!(
+            "SELECT {} FROM sensors WHERE deleted_at IS NULL AND {} = $1",
+            SENSOR_SUMMARY_COLUMNS, column
+        );
+        let mut param = 2;
+        if feature_name.is_some() {
+            sql.push_str(&format!(" AND feature_name = ${}", param));
+            param += 1;
+        }
+        if after.is_some() {
+            sql.push_str(&format!(
+                " AND (modified_at, id) {} (${}, ${})",
+                operator,
+                param,
+                param + 1
+            ));
+            param += 2;
+        }
+        sql.push_str(&format!(
+            " ORDER BY modified_at {0}, id {0} LIMIT ${1}",
+            direction, param
+        ));
+
+        let mut query = sqlx::query::<DB>(&sql).bind(id);
+        if let Some(feature_name) = feature_name {
+            query = query.bind(feature_name.as_str());
+        }
+        if let Some((modified_at, id)) = after {
+            query = query.bind(modified_at.timestamp_millis()).bind(id.to_hex());
+        }
+        let rows = query.bind(limit).fetch_all(&self.pool).await?;
+        rows.iter().map(Self::sensor_summary).collect()
+    }
+
+    fn sensor_value(row: &DB::Row) -> Result<SensorValue, DbError> {
+        Ok(SensorValue {
+            value: row.try_get("value")?,
+            created_at: DateTime::from_millis(row.try_get("created_at")?),
+            modified_at: DateTime::from_millis(row.try_get("modified_at")?),
+        })
+    }
+
+    fn reading(row: &DB::Row) -> Result<Reading, DbError> {
+        Ok(Reading {
+            timestamp: DateTime::from_millis(row.try_get("timestamp")?),
+            value: row.try_get("value")?,
+        })
+    }
+
+    fn sensor_summary(row: &DB::Row) -> Result<SensorSummary, DbError> {
+        let id: String = row.try_get("id")?;
+        Ok(SensorSummary {
+            id: ObjectId::from_str(&id).map_err(|err| DbError::MalformedDocument(format!("sensor id: {}", err)))?,
+            device_uuid: row.try_get("device_uuid")?,
+            feature_uuid: row.try_get("feature_uuid")?,
+            feature_name: row.try_get("feature_name")?,
+            value: row.try_get("value")?,
+            created_at: DateTime::from_millis(row.try_get("created_at")?),
+            modified_at: DateTime::from_millis(row.try_get("modified_at")?),
+            mac: row.try_get("mac")?,
+            model: row.try_get("model")?,
+            manufacturer: row.try_get("manufacturer")?,
+        })
+    }
+
+    fn sensor_type_stats(row: &DB::Row) -> Result<SensorTypeStats, DbError> {
+        let registered: i64 = row.try_get("registered")?;
+        let stale: i64 = row.try_get("stale")?;
+        Ok(SensorTypeStats {
+            feature_name: row.try_get("feature_name")?,
+            registered: registered as u64,
+            stale: stale as u64,
+        })
+    }
+}
+
+/// Condition selecting the sensors of a device, or a feature of it, with placeholders numbered from `first_param`
+/// for the device uuid, then the feature uuid and the feature name
+fn sensors_filter(first_param: usize, with_feature: bool, include_deleted: bool) -> String {
+    let mut filter = format!("device_uuid = ${}", first_param);
+    if with_feature {
+        filter.push_str(&format!(
+            " AND feature_uuid = ${} AND feature_name = ${}",
+            first_param + 1,
+            first_param + 2
+        ));
+    }
+    if !include_deleted {
+        filter.push_str(" AND deleted_at IS NULL");
+    }
+    filter
+}
@@ -0,0 +1,29 @@
+use sqlx::Sqlite;
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use tracing::info;
+
+use crate::db::sql::SqlSensorRepository;
+use crate::errors::db_error::DbError;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// SQLite storage, in a single file, for small installs
+pub type SqliteSensorRepository = SqlSensorRepository<Sqlite>;
+
+impl SqlSensorRepository<Sqlite> {
+    /// Open the database file, creating it if missing, and apply pending migrations
+    pub async fn connect(path: &str, retention_days: Option<u64>) -> Result<Self, DbError> {
+        info!(target: "app", "SqliteSensorRepository - opening {}", path);
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        let repository = Self::new(pool, &MIGRATOR, retention_days);
+
+        info!(target: "app", "SqliteSensorRepository - applying migrations");
+        repository.migrate().await?;
+        Ok(repository)
+    }
+}
@@ -1,9 +1,20 @@
+use std::fmt;
+
 use rocket::http::{ContentType, Status};
 use rocket::request::Request;
 use rocket::response::{Responder, Response, Result};
-use rocket::serde::json::Value;
+use rocket::serde::json::{Json, Value};
 use rocket::serde::{Deserialize, Serialize};
 
+use crate::errors::db_error::DbError;
+use crate::request_id::RequestId;
+
+/// Header with the id of a request, echoed in responses and in the body of errors
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+// prefix of the `type` of problems, followed by the error code
+const PROBLEM_TYPE_PREFIX: &str = "urn:register:error:";
+
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ApiResponse {
     pub json: Value,
@@ -20,19 +31,213 @@ impl<'r> Responder<'r, 'r> for ApiResponse {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Stable identifier of an error, that clients can use to handle it.
+/// Every code has always the same http status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    UnprocessableEntity,
+    InternalError,
+    ServiceUnavailable,
+    InvalidSensorType,
+    InvalidValue,
+    InvalidRange,
+    InvalidCursor,
+    InvalidProfile,
+    ValidationFailed,
+    SensorNotFound,
+    SensorAlreadyExists,
+    DatabaseUnavailable,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BadRequest => "bad_request",
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden => "forbidden",
+            Self::NotFound => "not_found",
+            Self::UnprocessableEntity => "unprocessable_entity",
+            Self::InternalError => "internal_error",
+            Self::ServiceUnavailable => "service_unavailable",
+            Self::InvalidSensorType => "invalid_sensor_type",
+            Self::InvalidValue => "invalid_value",
+            Self::InvalidRange => "invalid_range",
+            Self::InvalidCursor => "invalid_cursor",
+            Self::InvalidProfile => "invalid_profile",
+            Self::ValidationFailed => "validation_failed",
+            Self::SensorNotFound => "sensor_not_found",
+            Self::SensorAlreadyExists => "sensor_already_exists",
+            Self::DatabaseUnavailable => "database_unavailable",
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        match self {
+            Self::BadRequest
+            | Self::InvalidSensorType
+            | Self::InvalidValue
+            | Self::InvalidRange
+            | Self::InvalidCursor
+            | Self::InvalidProfile => Status::BadRequest,
+            Self::Unauthorized => Status::Unauthorized,
+            Self::Forbidden => Status::Forbidden,
+            Self::NotFound | Self::SensorNotFound => Status::NotFound,
+            Self::SensorAlreadyExists => Status::Conflict,
+            Self::UnprocessableEntity | Self::ValidationFailed => Status::UnprocessableEntity,
+            Self::InternalError => Status::InternalServerError,
+            Self::ServiceUnavailable | Self::DatabaseUnavailable => Status::ServiceUnavailable,
+        }
+    }
+
+    /// Short summary of the error, the same for every occurrence
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::BadRequest => "Bad request",
+            Self::Unauthorized => "Unauthorized",
+            Self::Forbidden => "Forbidden",
+            Self::NotFound => "Not found",
+            Self::UnprocessableEntity => "Unprocessable entity",
+            Self::InternalError => "Internal server error",
+            Self::ServiceUnavailable => "Service unavailable",
+            Self::InvalidSensorType => "Invalid sensor type",
+            Self::InvalidValue => "Invalid value",
+            Self::InvalidRange => "Invalid range",
+            Self::InvalidCursor => "Invalid cursor or limit",
+            Self::InvalidProfile => "Invalid profile",
+            Self::ValidationFailed => "Invalid input",
+            Self::SensorNotFound => "Sensor not found",
+            Self::SensorAlreadyExists => "Sensor already exists",
+            Self::DatabaseUnavailable => "Database unavailable",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Error of a single field of the input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Error returned by routes and catchers, sent as RFC 7807 `application/problem+json`
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ApiError {
+    pub code: ErrorCode,
     pub message: String,
-    pub code: u16,
+    pub errors: Vec<FieldError>,
+}
+
+impl ApiError {
+    /// Error with the title of `code` as message
+    pub fn new(code: ErrorCode) -> Self {
+        Self::with_message(code, code.title())
+    }
+
+    pub fn with_message(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Error with details of every invalid field
+    pub fn with_errors(code: ErrorCode, errors: Vec<FieldError>) -> Self {
+        Self {
+            errors,
+            ..Self::new(code)
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        self.code.status()
+    }
+}
+
+// codes of database failures, each one with a different status
+const DB_ERROR_CODES: [ErrorCode; 5] = [
+    ErrorCode::DatabaseUnavailable,
+    ErrorCode::SensorAlreadyExists,
+    ErrorCode::SensorNotFound,
+    ErrorCode::Forbidden,
+    ErrorCode::ValidationFailed,
+];
+
+impl From<&DbError> for ApiError {
+    fn from(error: &DbError) -> Self {
+        // the code has the status of the error, details of database failures are logged, not returned to clients
+        let code = DB_ERROR_CODES
+            .into_iter()
+            .find(|code| code.status() == error.status())
+            .unwrap_or(ErrorCode::InternalError);
+        match code {
+            ErrorCode::ValidationFailed => Self::with_message(code, format!("Invalid input: {}", error.message())),
+            _ => Self::new(code),
+        }
+    }
+}
+
+/// Body of error responses, as defined by RFC 7807 with the extension members
+/// `code`, `errors` and `requestId`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "camelCase")]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub instance: String,
+    pub code: ErrorCode,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl Problem {
+    pub fn new(error: ApiError, req: &Request<'_>) -> Self {
+        Self {
+            problem_type: format!("{}{}", PROBLEM_TYPE_PREFIX, error.code),
+            title: error.code.title().to_string(),
+            status: error.status().code,
+            detail: error.message,
+            instance: req.uri().path().to_string(),
+            code: error.code,
+            errors: error.errors,
+            request_id: RequestId::of(req).map(String::from),
+        }
+    }
 }
 
 #[rocket::async_trait]
-impl<'r> Responder<'r, 'r> for ApiError {
+impl<'r> Responder<'r, 'static> for ApiError {
     fn respond_to(self, req: &'r Request<'_>) -> Result<'static> {
-        Response::build_from(self.message.respond_to(req).unwrap())
-            .status(Status { code: self.code })
-            .header(ContentType::JSON)
+        let status = self.status();
+        Response::build_from(Json(Problem::new(self, req)).respond_to(req)?)
+            .status(status)
+            .header(ContentType::new("application", "problem+json"))
             .ok()
     }
 }
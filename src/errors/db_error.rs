@@ -1,12 +1,126 @@
-use rocket::serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct DbError {
-    pub message: String,
+use mongodb::error::{ErrorKind, WriteFailure};
+use rocket::http::Status;
+
+// code of MongoDB errors caused by a unique index
+const MONGO_DUPLICATE_KEY_CODE: i32 = 11000;
+
+/// Error of a storage operation, whatever the storage backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbError {
+    /// the database is unreachable or it didn't answer in time
+    Connection(String),
+    /// a unique index or constraint rejected the write
+    DuplicateKey(String),
+    /// the document to read or change doesn't exist
+    NotFound(String),
+    /// the document exists, but the caller's `apiToken` doesn't allow changing it
+    Forbidden(String),
+    /// a stored document hasn't the expected fields or types
+    MalformedDocument(String),
+    /// the input cannot be stored, e.g. an invalid `ObjectId`
+    Validation(String),
+    /// any other error of the database
+    Query(String),
 }
 
 impl DbError {
-    pub fn new(message: String) -> Self {
-        Self { message }
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Connection(message)
+            | Self::DuplicateKey(message)
+            | Self::NotFound(message)
+            | Self::Forbidden(message)
+            | Self::MalformedDocument(message)
+            | Self::Validation(message)
+            | Self::Query(message) => message,
+        }
+    }
+
+    /// True if the operation may succeed when retried, e.g. once the database is reachable again
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Connection(_))
+    }
+
+    /// Http status of a request failed because of this error
+    pub fn status(&self) -> Status {
+        match self {
+            Self::Connection(_) => Status::ServiceUnavailable,
+            Self::DuplicateKey(_) => Status::Conflict,
+            Self::NotFound(_) => Status::NotFound,
+            Self::Forbidden(_) => Status::Forbidden,
+            Self::Validation(_) => Status::UnprocessableEntity,
+            Self::MalformedDocument(_) | Self::Query(_) => Status::InternalServerError,
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Connection(_) => "connection error",
+            Self::DuplicateKey(_) => "duplicate key",
+            Self::NotFound(_) => "not found",
+            Self::Forbidden(_) => "forbidden",
+            Self::MalformedDocument(_) => "malformed document",
+            Self::Validation(_) => "validation error",
+            Self::Query(_) => "query error",
+        };
+        write!(f, "{}: {}", kind, self.message())
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<mongodb::error::Error> for DbError {
+    fn from(err: mongodb::error::Error) -> Self {
+        let message = err.to_string();
+        match *err.kind {
+            ErrorKind::Io(_)
+            | ErrorKind::ServerSelection { .. }
+            | ErrorKind::ConnectionPoolCleared { .. }
+            | ErrorKind::DnsResolve { .. } => Self::Connection(message),
+            ErrorKind::Write(WriteFailure::WriteError(ref write_error))
+                if write_error.code == MONGO_DUPLICATE_KEY_CODE =>
+            {
+                Self::DuplicateKey(message)
+            }
+            ErrorKind::Command(ref command_error) if command_error.code == MONGO_DUPLICATE_KEY_CODE => {
+                Self::DuplicateKey(message)
+            }
+            ErrorKind::BsonDeserialization(_) | ErrorKind::BsonSerialization(_) => Self::MalformedDocument(message),
+            _ => Self::Query(message),
+        }
+    }
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(err: sqlx::Error) -> Self {
+        let message = err.to_string();
+        match err {
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => Self::Connection(message),
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => Self::DuplicateKey(message),
+            sqlx::Error::Database(ref db_error) if db_error.is_check_violation() => Self::Validation(message),
+            sqlx::Error::RowNotFound => Self::NotFound(message),
+            sqlx::Error::ColumnNotFound(_)
+            | sqlx::Error::ColumnDecode { .. }
+            | sqlx::Error::Decode(_)
+            | sqlx::Error::TypeNotFound { .. } => Self::MalformedDocument(message),
+            _ => Self::Query(message),
+        }
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for DbError {
+    fn from(err: sqlx::migrate::MigrateError) -> Self {
+        match err {
+            sqlx::migrate::MigrateError::Execute(err) => Self::from(err),
+            _ => Self::Query(err.to_string()),
+        }
     }
 }
@@ -0,0 +1,213 @@
+use std::str::FromStr;
+
+use mongodb::bson::oid::ObjectId;
+use rocket::State;
+use rocket::data::{self, Data, FromData};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::DeserializeOwned;
+use rocket::serde::json::Json;
+use subtle::ConstantTimeEq;
+use tracing::{debug, error};
+
+use crate::config::Env;
+use crate::db::repository::DynSensorRepository;
+use crate::errors::api_error::FieldError;
+use crate::errors::db_error::DbError;
+use crate::models::sensor_type::SensorType;
+use crate::models::validation::Validate;
+
+/// Bearer token sent by the client in the `Authorization` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BearerToken(pub String);
+
+impl BearerToken {
+    /// True if the token is `api_token`
+    pub fn matches(&self, api_token: &str) -> bool {
+        tokens_match(&self.0, api_token)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BearerToken {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.headers().get_one("Authorization").and_then(parse_bearer_token) {
+            Some(token) => Outcome::Success(BearerToken(token)),
+            None => {
+                error!(target: "app", "BearerToken - missing or invalid Authorization header");
+                Outcome::Error((Status::Unauthorized, ()))
+            }
+        }
+    }
+}
+
+/// Caller authorized to read sensors data.
+/// The bearer token must match the `apiToken` of the sensor identified by the route, using the second
+/// segment as device uuid and, if defined, the fourth and fifth ones as feature uuid and type
+/// (`/sensors/<device_uuid>/features/<feature_uuid>/<sensor_type>` or `/devices/<device_uuid>/sensors`).
+/// Without a feature, the token must match the `apiToken` of any sensor of the device:
+/// a sensor can be added to a registered device only with the `apiToken` of one of its sensors.
+/// Callers in the `TRUSTED_CALLERS` allow-list are always authorized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SensorAuth {
+    // true if the caller bypassed the token check
+    pub trusted: bool,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SensorAuth {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if is_trusted_caller(req) {
+            return Outcome::Success(SensorAuth { trusted: true });
+        }
+
+        let (token, repository) = match token_and_repository(req).await {
+            Outcome::Success(token_and_repository) => token_and_repository,
+            Outcome::Error(error) => return Outcome::Error(error),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+        let device_uuid = match req.param::<&str>(1) {
+            Some(Ok(device_uuid)) => device_uuid,
+            _ => return Outcome::Error((Status::BadRequest, ())),
+        };
+        let feature = match (req.param::<&str>(3), req.param::<SensorType>(4)) {
+            (Some(Ok(feature_uuid)), Some(Ok(sensor_type))) => Some((feature_uuid, sensor_type)),
+            (_, Some(Err(_))) => return Outcome::Error((Status::BadRequest, ())),
+            _ => None,
+        };
+
+        let api_tokens = repository.find_api_tokens(device_uuid, feature, false).await;
+        check_api_tokens(api_tokens, &token).map(|_| SensorAuth { trusted: false })
+    }
+}
+
+/// Caller authorized to read sensors of the profile in the second segment of the route (`/profiles/<profile_owner_id>/sensors`).
+/// The bearer token must match the `apiToken` of any sensor of the profile:
+/// a sensor can be added to a registered profile only with the `apiToken` of one of its sensors.
+/// Callers in the `TRUSTED_CALLERS` allow-list are always authorized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileAuth {
+    // true if the caller bypassed the token check
+    pub trusted: bool,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ProfileAuth {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if is_trusted_caller(req) {
+            return Outcome::Success(ProfileAuth { trusted: true });
+        }
+
+        let (token, repository) = match token_and_repository(req).await {
+            Outcome::Success(token_and_repository) => token_and_repository,
+            Outcome::Error(error) => return Outcome::Error(error),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+        let profile_owner_id = match req.param::<&str>(1).map(|param| param.map(ObjectId::from_str)) {
+            Some(Ok(Ok(profile_owner_id))) => profile_owner_id,
+            _ => return Outcome::Error((Status::BadRequest, ())),
+        };
+
+        let api_tokens = repository.find_profile_api_tokens(profile_owner_id, false).await;
+        check_api_tokens(api_tokens, &token).map(|_| ProfileAuth { trusted: false })
+    }
+}
+
+/// JSON body checked with its `Validate` rules, with normalized values.
+/// Invalid inputs fail with 422, saving the errors as `ValidationErrors` in the request cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validated<T>(pub T);
+
+impl<T> Validated<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// Errors of the fields of the last input rejected by `Validated`
+#[derive(Debug, Clone, Default)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned + Validate + Send> FromData<'r> for Validated<T> {
+    type Error = Vec<FieldError>;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let mut input: T = match Json::<T>::from_data(req, data).await {
+            data::Outcome::Success(input) => input.into_inner(),
+            data::Outcome::Error((status, error)) => {
+                error!(target: "app", "Validated - cannot parse json body, error = {:?}", error);
+                return data::Outcome::Error((status, Vec::new()));
+            }
+            data::Outcome::Forward(forward) => return data::Outcome::Forward(forward),
+        };
+        match input.validate() {
+            Ok(()) => data::Outcome::Success(Validated(input)),
+            Err(errors) => {
+                error!(target: "app", "Validated - invalid input, errors = {:?}", errors);
+                req.local_cache(|| ValidationErrors(errors.clone()));
+                data::Outcome::Error((Status::UnprocessableEntity, errors))
+            }
+        }
+    }
+}
+
+fn is_trusted_caller(req: &Request<'_>) -> bool {
+    // use the socket address and not `client_ip()`, because the latter can be spoofed via `X-Real-IP` header
+    match (req.rocket().state::<Env>(), req.remote()) {
+        (Some(env), Some(remote)) if env.trusted_callers.contains(&remote.ip()) => {
+            debug!(target: "app", "is_trusted_caller - trusted caller = {}", remote.ip());
+            true
+        }
+        _ => false,
+    }
+}
+
+async fn token_and_repository<'r>(req: &'r Request<'_>) -> Outcome<(BearerToken, &'r State<DynSensorRepository>), ()> {
+    let token = match req.guard::<BearerToken>().await {
+        Outcome::Success(token) => token,
+        Outcome::Error(error) => return Outcome::Error(error),
+        Outcome::Forward(status) => return Outcome::Forward(status),
+    };
+    match req.guard::<&State<DynSensorRepository>>().await {
+        Outcome::Success(repository) => Outcome::Success((token, repository)),
+        _ => Outcome::Error((Status::InternalServerError, ())),
+    }
+}
+
+/// Check that `token` is one of the `apiToken`s of the requested sensors
+fn check_api_tokens(api_tokens: Result<Vec<String>, DbError>, token: &BearerToken) -> Outcome<(), ()> {
+    match api_tokens {
+        Ok(api_tokens) if api_tokens.is_empty() => Outcome::Error((Status::NotFound, ())),
+        Ok(api_tokens) if api_tokens.iter().any(|api_token| token.matches(api_token)) => Outcome::Success(()),
+        Ok(_) => {
+            error!(target: "app", "check_api_tokens - apiToken doesn't match");
+            Outcome::Error((Status::Forbidden, ()))
+        }
+        Err(err) => {
+            error!(target: "app", "check_api_tokens - cannot find sensors, error = {:?}", err);
+            Outcome::Error((err.status(), ()))
+        }
+    }
+}
+
+/// Compare two tokens in constant time, to not reveal with response times how many bytes of a guessed token are right
+pub fn tokens_match(token: &str, api_token: &str) -> bool {
+    token.as_bytes().ct_eq(api_token.as_bytes()).into()
+}
+
+fn parse_bearer_token(header: &str) -> Option<String> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    let token = token.trim();
+    if scheme.eq_ignore_ascii_case("bearer") && !token.is_empty() {
+        Some(token.to_string())
+    } else {
+        None
+    }
+}
@@ -5,5 +5,10 @@ pub mod catchers;
 pub mod config;
 pub mod db;
 pub mod errors;
+pub mod guards;
+pub mod logging;
+pub mod metrics;
 pub mod models;
+pub mod request_id;
 pub mod routes;
+pub mod telemetry;
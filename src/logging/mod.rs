@@ -0,0 +1,111 @@
+//! Logging of the server, configured by the `LOG_*` and `RUST_LOG` settings.
+//!
+//! Events are written as compact text or as JSON lines, to stdout, to log files or to both.
+//! Log files are `all` with every event and `error` with only errors, rotated by time
+//! (`minutely`, `hourly`, `daily` or `never`) or by size (`size`, every `LOG_MAX_SIZE_KB`).
+
+use std::io;
+use std::sync::Mutex;
+
+use tracing::Subscriber;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriterExt, TestWriter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use crate::config::{Env, LogFormat, LogOutput, LogRotation};
+use crate::telemetry;
+
+pub mod size_rolling;
+
+use size_rolling::SizeRollingFile;
+
+/// Directives used if `RUST_LOG` is not defined
+pub const DEFAULT_LOG_FILTER: &str = "info,sqlx=warn";
+
+/// Filter of events from `RUST_LOG`-style directives, like `info,register=debug,rocket=warn`
+pub fn log_filter(directives: Option<&str>) -> Result<EnvFilter, String> {
+    let directives = directives.unwrap_or(DEFAULT_LOG_FILTER);
+    EnvFilter::try_new(directives).map_err(|err| format!("RUST_LOG = {}: {}", directives, err))
+}
+
+/// Subscriber writing the events accepted by `filter` to `writer`, in `format`
+pub fn subscriber<W>(format: LogFormat, filter: EnvFilter, writer: W) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    subscriber_with_layers(format, filter, writer, Vec::new())
+}
+
+/// Subscriber like `subscriber`, with other `layers` receiving the same events and spans, like the export of spans
+pub fn subscriber_with_layers<W>(
+    format: LogFormat,
+    filter: EnvFilter,
+    writer: W,
+    mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>>,
+) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let fmt_layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(false);
+    layers.push(match format {
+        LogFormat::Text => fmt_layer.compact().boxed(),
+        LogFormat::Json => fmt_layer.json().flatten_event(true).boxed(),
+    });
+    Box::new(tracing_subscriber::registry().with(layers).with(filter))
+}
+
+/// Writer of the `LOG_OUTPUT` destinations, with `all` and `error` log files in `LOG_DIR`
+pub fn writer(env: &Env) -> io::Result<BoxMakeWriter> {
+    if env.log_output == LogOutput::Stdout {
+        return Ok(BoxMakeWriter::new(io::stdout));
+    }
+    let all_file = log_file(env, "all")?;
+    let error_file = log_file(env, "error")?.with_max_level(tracing::Level::ERROR);
+    let files = all_file.and(error_file);
+    Ok(match env.log_output {
+        LogOutput::File => BoxMakeWriter::new(files),
+        _ => BoxMakeWriter::new(files.and(io::stdout)),
+    })
+}
+
+fn log_file(env: &Env, prefix: &str) -> io::Result<BoxMakeWriter> {
+    let log_dir = env.log_dir.as_deref().unwrap_or("./logs");
+    let max_files = env.log_max_files.unwrap_or(5);
+    let rotation = match env.log_rotation {
+        LogRotation::Size => {
+            let max_bytes = env.log_max_size_kb.unwrap_or(10240) * 1024;
+            let file = SizeRollingFile::new(log_dir, prefix, max_bytes, max_files)?;
+            return Ok(BoxMakeWriter::new(Mutex::new(file)));
+        }
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+    let appender = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(prefix)
+        .filename_suffix("log")
+        .max_log_files(max_files)
+        .build(log_dir)
+        .map_err(io::Error::other)?;
+    Ok(BoxMakeWriter::new(appender))
+}
+
+/// Init the global subscriber, if not already done.
+/// In testing environment events are captured by the test harness, and never written to files.
+pub fn init(env: &Env, is_testing: bool) -> Result<(), String> {
+    let filter = log_filter(env.rust_log.as_deref())?;
+    let writer = if is_testing {
+        BoxMakeWriter::new(TestWriter::new())
+    } else {
+        writer(env).map_err(|err| format!("cannot open log files: {}", err))?
+    };
+    let layers = telemetry::layers(env)?;
+    // it fails if a subscriber is already set, like in tests
+    let _ = subscriber_with_layers(env.log_format, filter, writer, layers).try_init();
+    Ok(())
+}
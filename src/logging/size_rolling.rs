@@ -0,0 +1,83 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Log file rotated when it grows beyond `max_bytes`.
+/// The current file is `<prefix>.log`, the rotated ones are `<prefix>.1.log` (the newest),
+/// `<prefix>.2.log` and so on, keeping at most `max_files` files, the current one included.
+#[derive(Debug)]
+pub struct SizeRollingFile {
+    dir: PathBuf,
+    prefix: String,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl SizeRollingFile {
+    pub fn new(dir: impl AsRef<Path>, prefix: &str, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.log", prefix));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            dir,
+            prefix: prefix.to_string(),
+            max_bytes,
+            max_files: max_files.max(1),
+            file,
+            size,
+        })
+    }
+
+    /// Path of the current file if `index` is 0, otherwise of a rotated one
+    pub fn path(&self, index: usize) -> PathBuf {
+        match index {
+            0 => self.dir.join(format!("{}.log", self.prefix)),
+            index => self.dir.join(format!("{}.{}.log", self.prefix, index)),
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let last = self.max_files - 1;
+        if last > 0 {
+            if let Err(err) = fs::remove_file(self.path(last))
+                && err.kind() != io::ErrorKind::NotFound
+            {
+                return Err(err);
+            }
+            for index in (1..last).rev() {
+                if self.path(index).exists() {
+                    fs::rename(self.path(index), self.path(index + 1))?;
+                }
+            }
+            fs::rename(self.path(0), self.path(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(self.path(0))?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // a line longer than `max_bytes` is written anyway, in a file on its own
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
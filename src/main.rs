@@ -1,15 +1,86 @@
 #[macro_use]
 extern crate rocket;
 
+use std::sync::Arc;
+use std::{env, process};
+
 use rocket::{Build, Rocket};
-use tracing::info;
+use tracing::{error, info};
 
 use register::catchers;
-use register::config::{Env, init};
+use register::config::{Env, StorageBackend, init};
 use register::db;
+use register::db::migrations::{MigrationStatus, run_migrations};
+use register::metrics::{Metrics, MetricsFairing};
+use register::request_id::{RequestIdFairing, traced_catchers, traced_routes};
 use register::routes;
+use register::telemetry;
+
+const USAGE: &str = "usage: register [migrate [--dry-run]]";
+
+#[rocket::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => {
+            let result = rocket().launch().await;
+            telemetry::shutdown();
+            if let Err(error) = result {
+                error!(target: "app", "Rocket failed: {}", error);
+                process::exit(1);
+            }
+        }
+        Some("migrate") => {
+            let dry_run = match &args[1..] {
+                [] => false,
+                [flag] if flag == "--dry-run" => true,
+                _ => exit_with_usage(),
+            };
+            process::exit(migrate(init(), dry_run).await);
+        }
+        Some(_) => exit_with_usage(),
+    }
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+// apply pending migrations of MongoDB documents, returning the exit code
+async fn migrate(env: Env, dry_run: bool) -> i32 {
+    if env.storage_backend != StorageBackend::Mongodb {
+        eprintln!("migrate only applies to STORAGE_BACKEND=mongodb, SQL backends apply their migrations at startup");
+        return 1;
+    }
+    let database = match db::connect(env).await {
+        Ok(database) => database,
+        Err(error) => {
+            eprintln!("Cannot connect to MongoDB: {}", error);
+            return 1;
+        }
+    };
+    match run_migrations(&database, dry_run).await {
+        Ok(outcomes) => {
+            for outcome in outcomes.iter() {
+                println!("{}", outcome);
+            }
+            let pending = outcomes
+                .iter()
+                .filter(|outcome| outcome.status == MigrationStatus::Pending)
+                .count();
+            if dry_run {
+                println!("dry-run: {} pending migrations, nothing changed", pending);
+            }
+            0
+        }
+        Err(error) => {
+            eprintln!("Cannot apply migrations: {}", error);
+            1
+        }
+    }
+}
 
-#[launch]
 fn rocket() -> Rocket<Build> {
     // 1. Init logger and env
     let env: Env = init();
@@ -19,24 +90,50 @@ fn rocket() -> Rocket<Build> {
     // b) define APIs
     // c) define error handlers
     info!(target: "app", "Starting Rocket...");
-    rocket::build()
-        .attach(db::init(env))
+    let metrics = Arc::new(Metrics::new());
+    let metrics_enabled = env.metrics_enabled.unwrap_or(true);
+    let rocket = rocket::build()
+        .manage(env.clone())
+        .manage(metrics.clone())
+        .attach(RequestIdFairing)
+        .attach(db::init(env, metrics.clone()))
         .mount(
             "/",
-            routes![
+            traced_routes(routes![
+                routes::api::get_sensor_types,
                 routes::api::post_register,
                 routes::api::get_sensor_value,
+                routes::api::put_sensor_value,
+                routes::api::get_sensor_history,
+                routes::api::get_sensor_aggregate,
+                routes::api::get_device_sensors,
+                routes::api::get_profile_sensors,
+                routes::api::delete_sensor,
+                routes::api::delete_device_sensors,
                 routes::api::keep_alive,
-            ],
+                routes::health::get_health_live,
+                routes::health::get_health_ready,
+            ]),
         )
         .register(
             "/",
-            catchers![
+            traced_catchers(catchers![
                 catchers::bad_request,
+                catchers::unauthorized,
+                catchers::forbidden,
                 catchers::not_found,
+                catchers::unprocessable_entity,
                 catchers::internal_server_error,
-            ],
-        )
+                catchers::service_unavailable,
+            ]),
+        );
+    if metrics_enabled {
+        rocket
+            .attach(MetricsFairing::new(metrics))
+            .mount("/", traced_routes(routes![routes::metrics::get_metrics]))
+    } else {
+        rocket
+    }
 }
 
 // testing
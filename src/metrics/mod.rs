@@ -0,0 +1,174 @@
+//! Prometheus metrics, exposed by `GET /metrics` in text format.
+//!
+//! | name | type | labels | description |
+//! |------|------|--------|-------------|
+//! | `register_http_requests_total` | counter | `method`, `route`, `status` | http requests, by route template (`unmatched` if no route matches) |
+//! | `register_http_request_duration_seconds` | histogram | `method`, `route` | http requests latency |
+//! | `register_db_operation_duration_seconds` | histogram | `operation` | storage operations latency |
+//! | `register_db_operation_errors_total` | counter | `operation` | failed storage operations |
+//! | `register_sensor_registrations_total` | counter | `sensor_type`, `result` | registrations, with `result` = `created`, `updated` or `failed` |
+//! | `register_sensors_registered` | gauge | `sensor_type` | registered sensors, not soft-deleted, refreshed on every scrape |
+//! | `register_sensors_stale` | gauge | `sensor_type` | registered sensors without new values for `METRICS_STALE_AFTER_SECS`, refreshed on every scrape |
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+
+use crate::models::sensor::SensorTypeStats;
+
+// label of requests without a matching route, to don't create a series for every unknown url
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Metrics of the server, managed by Rocket as `Arc<Metrics>`.
+/// Every Rocket instance has its own registry.
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests_total: IntCounterVec,
+    pub http_request_duration_seconds: HistogramVec,
+    pub db_operation_duration_seconds: HistogramVec,
+    pub db_operation_errors_total: IntCounterVec,
+    pub sensor_registrations_total: IntCounterVec,
+    pub sensors_registered: IntGaugeVec,
+    pub sensors_stale: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let http_requests_total = IntCounterVec::new(
+            Opts::new("register_http_requests_total", "HTTP requests"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new("register_http_request_duration_seconds", "HTTP requests latency"),
+            &["method", "route"],
+        )
+        .unwrap();
+        let db_operation_duration_seconds = HistogramVec::new(
+            HistogramOpts::new("register_db_operation_duration_seconds", "Storage operations latency"),
+            &["operation"],
+        )
+        .unwrap();
+        let db_operation_errors_total = IntCounterVec::new(
+            Opts::new("register_db_operation_errors_total", "Failed storage operations"),
+            &["operation"],
+        )
+        .unwrap();
+        let sensor_registrations_total = IntCounterVec::new(
+            Opts::new("register_sensor_registrations_total", "Sensor registrations"),
+            &["sensor_type", "result"],
+        )
+        .unwrap();
+        let sensors_registered = IntGaugeVec::new(
+            Opts::new("register_sensors_registered", "Registered sensors"),
+            &["sensor_type"],
+        )
+        .unwrap();
+        let sensors_stale = IntGaugeVec::new(
+            Opts::new("register_sensors_stale", "Registered sensors without new values"),
+            &["sensor_type"],
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(http_requests_total.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_operation_duration_seconds.clone()))
+            .unwrap();
+        registry.register(Box::new(db_operation_errors_total.clone())).unwrap();
+        registry.register(Box::new(sensor_registrations_total.clone())).unwrap();
+        registry.register(Box::new(sensors_registered.clone())).unwrap();
+        registry.register(Box::new(sensors_stale.clone())).unwrap();
+
+        Self {
+            registry,
+            http_requests_total,
+            http_request_duration_seconds,
+            db_operation_duration_seconds,
+            db_operation_errors_total,
+            sensor_registrations_total,
+            sensors_registered,
+            sensors_stale,
+        }
+    }
+
+    /// Replace the sensors gauges with the current stats
+    pub fn set_sensors_stats(&self, stats: &[SensorTypeStats]) {
+        // types without sensors anymore must disappear
+        self.sensors_registered.reset();
+        self.sensors_stale.reset();
+        for type_stats in stats.iter() {
+            self.sensors_registered
+                .with_label_values(&[&type_stats.feature_name])
+                .set(type_stats.registered as i64);
+            self.sensors_stale
+                .with_label_values(&[&type_stats.feature_name])
+                .set(type_stats.stale as i64);
+        }
+    }
+
+    /// All metrics in Prometheus text format
+    pub fn encode(&self) -> String {
+        let mut buffer: Vec<u8> = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// time when rocket received the request
+struct RequestStart(Option<Instant>);
+
+/// Fairing counting http requests and measuring their latency
+pub struct MetricsFairing {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsFairing {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for MetricsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Prometheus metrics of http requests",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        req.local_cache(|| RequestStart(Some(Instant::now())));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let method = req.method().as_str();
+        let route = req
+            .route()
+            .map(|route| route.uri.to_string())
+            .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+        self.metrics
+            .http_requests_total
+            .with_label_values(&[method, &route, &res.status().code.to_string()])
+            .inc();
+        if let RequestStart(Some(started_at)) = req.local_cache(|| RequestStart(None)) {
+            self.metrics
+                .http_request_duration_seconds
+                .with_label_values(&[method, &route])
+                .observe(started_at.elapsed().as_secs_f64());
+        }
+    }
+}
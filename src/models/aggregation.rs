@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use mongodb::bson::{DateTime, Document, doc};
+use rocket::form::FromFormField;
+
+use crate::models::reading::{Reading, ReadingBucket};
+
+/// Size of the time buckets used to aggregate readings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum Bucket {
+    #[field(value = "1m")]
+    OneMinute,
+    #[field(value = "5m")]
+    FiveMinutes,
+    #[field(value = "1h")]
+    OneHour,
+    #[field(value = "1d")]
+    OneDay,
+}
+
+impl Bucket {
+    /// Time unit, as defined by MongoDB `$dateTrunc`
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Bucket::OneMinute | Bucket::FiveMinutes => "minute",
+            Bucket::OneHour => "hour",
+            Bucket::OneDay => "day",
+        }
+    }
+
+    /// Number of units in a bucket
+    pub fn bin_size(&self) -> i64 {
+        match self {
+            Bucket::FiveMinutes => 5,
+            Bucket::OneMinute | Bucket::OneHour | Bucket::OneDay => 1,
+        }
+    }
+
+    pub fn millis(&self) -> i64 {
+        match self {
+            Bucket::OneMinute => 60 * 1000,
+            Bucket::FiveMinutes => 5 * 60 * 1000,
+            Bucket::OneHour => 60 * 60 * 1000,
+            Bucket::OneDay => 24 * 60 * 60 * 1000,
+        }
+    }
+}
+
+/// Function used to aggregate readings in a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum Agg {
+    #[field(value = "min")]
+    Min,
+    #[field(value = "max")]
+    Max,
+    #[field(value = "avg")]
+    Avg,
+    #[field(value = "last")]
+    Last,
+    #[field(value = "count")]
+    Count,
+}
+
+impl Agg {
+    /// MongoDB `$group` accumulator on readings `value`
+    pub fn accumulator(&self) -> Document {
+        match self {
+            Agg::Min => doc! {"$min": "$value"},
+            Agg::Max => doc! {"$max": "$value"},
+            Agg::Avg => doc! {"$avg": "$value"},
+            Agg::Last => doc! {"$last": "$value"},
+            Agg::Count => doc! {"$sum": 1},
+        }
+    }
+
+    /// Aggregate the values of a bucket, sorted by timestamp, for backends without an aggregation pipeline.
+    /// An empty bucket has no value, or 0 when counting.
+    pub fn apply(&self, values: &[f64]) -> Option<f64> {
+        match self {
+            Agg::Count => Some(values.len() as f64),
+            _ if values.is_empty() => None,
+            Agg::Min => values.iter().copied().reduce(f64::min),
+            Agg::Max => values.iter().copied().reduce(f64::max),
+            Agg::Avg => Some(values.iter().sum::<f64>() / values.len() as f64),
+            Agg::Last => values.last().copied(),
+        }
+    }
+}
+
+/// Aggregate readings with `timestamp` in [from, to), sorted by `timestamp`, into time buckets,
+/// for backends without an aggregation pipeline.
+/// The result has every bucket, also the empty ones. `from` must be aligned to the bucket size.
+pub fn aggregate_in_buckets(
+    readings: &[Reading],
+    from: DateTime,
+    to: DateTime,
+    bucket: Bucket,
+    agg: Agg,
+) -> Vec<ReadingBucket> {
+    let from = from.timestamp_millis();
+    let mut values: HashMap<i64, Vec<f64>> = HashMap::new();
+    for reading in readings {
+        let timestamp = reading.timestamp.timestamp_millis();
+        let bucket_start = timestamp - (timestamp - from).rem_euclid(bucket.millis());
+        values.entry(bucket_start).or_default().push(reading.value);
+    }
+    (from..to.timestamp_millis())
+        .step_by(bucket.millis() as usize)
+        .map(|timestamp| ReadingBucket {
+            timestamp: DateTime::from_millis(timestamp),
+            value: agg.apply(values.get(&timestamp).map(Vec::as_slice).unwrap_or_default()),
+        })
+        .collect()
+}
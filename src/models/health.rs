@@ -0,0 +1,42 @@
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+/// Status of a dependency checked by the readiness probe
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+    #[serde(skip)]
+    pub name: &'static str,
+    pub status: HealthStatus,
+    // why the dependency is down, or extra info if up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+impl HealthCheck {
+    pub fn up(name: &'static str) -> Self {
+        Self {
+            name,
+            status: HealthStatus::Up,
+            details: None,
+        }
+    }
+
+    pub fn down(name: &'static str, details: String) -> Self {
+        Self {
+            name,
+            status: HealthStatus::Down,
+            details: Some(details),
+        }
+    }
+
+    pub fn with_details(mut self, details: String) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
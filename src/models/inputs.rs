@@ -1,4 +1,13 @@
+use rocket::form::{FromForm, FromFormField};
 use serde::{Deserialize, Serialize};
+use serde_json::Number;
+
+use crate::errors::api_error::FieldError;
+use crate::models::validation::{Rule, Validate, Validator};
+
+// max length of free-text fields of inputs
+const MAX_TEXT_LENGTH: usize = 100;
+const MAX_API_TOKEN_LENGTH: usize = 256;
 
 #[allow(non_snake_case)]
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -14,3 +23,51 @@ pub struct RegisterInput {
     // feature info
     pub featureUuid: String,
 }
+
+impl Validate for RegisterInput {
+    fn validate(&mut self) -> Result<(), Vec<FieldError>> {
+        let text = Rule::Length {
+            min: 1,
+            max: MAX_TEXT_LENGTH,
+        };
+        let api_token = Rule::Length {
+            min: 1,
+            max: MAX_API_TOKEN_LENGTH,
+        };
+        Validator::new()
+            .field("profileOwnerId", &mut self.profileOwnerId, &[Rule::ObjectId])
+            .field("apiToken", &mut self.apiToken, &[api_token])
+            .field("deviceUuid", &mut self.deviceUuid, &[Rule::Uuid])
+            .field("mac", &mut self.mac, &[Rule::Mac])
+            .field("model", &mut self.model, &[text])
+            .field("manufacturer", &mut self.manufacturer, &[text])
+            .field("featureUuid", &mut self.featureUuid, &[Rule::Uuid])
+            .finish()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ValueInput {
+    // validated against the sensor type, because it can be either an integer or a float
+    pub value: Number,
+}
+
+/// Sort order by `modifiedAt`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum SortOrder {
+    #[field(value = "asc")]
+    Asc,
+    #[field(value = "desc")]
+    Desc,
+}
+
+/// Query parameters to list sensors
+#[derive(Debug, Clone, FromForm)]
+pub struct ListSensorsQuery {
+    #[field(name = "featureName")]
+    pub feature_name: Option<String>,
+    pub sort: Option<SortOrder>,
+    pub limit: Option<i64>,
+    // 'nextCursor' returned by the previous page
+    pub cursor: Option<String>,
+}
@@ -1,2 +1,7 @@
+pub mod aggregation;
+pub mod health;
 pub mod inputs;
+pub mod reading;
 pub mod sensor;
+pub mod sensor_type;
+pub mod validation;
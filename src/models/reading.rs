@@ -0,0 +1,18 @@
+use mongodb::bson::DateTime;
+
+/// Value of a sensor at a point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub timestamp: DateTime,
+    // always a f64, even if the sensor type has int values
+    pub value: f64,
+}
+
+/// Readings of a sensor aggregated in a time bucket
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadingBucket {
+    // start of the bucket
+    pub timestamp: DateTime,
+    // None if the bucket is empty, unless counting readings
+    pub value: Option<f64>,
+}
@@ -1,18 +1,27 @@
 use std::str::FromStr;
 
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{Bson, DateTime, oid, to_bson};
-use oid::Error;
-use rocket::serde::json::Json;
+use mongodb::bson::{Bson, DateTime, to_bson};
 use serde::{Deserialize, Serialize};
 
+use crate::errors::db_error::DbError;
 use crate::models::inputs::RegisterInput;
+use crate::models::sensor_type::{SensorType, ValueKind};
+
+/// Version of the shape of documents in the `sensors` collection, stored as `schemaVersion`.
+/// Increase it when the shape changes, adding a migration in `db::migrations`:
+/// - 1: `schemaVersion` is stored
+/// - 2: `value` is stored as double or i64, based on the kind of the sensor type
+pub const SENSOR_SCHEMA_VERSION: i32 = 2;
 
 #[allow(non_snake_case)]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct IntSensor {
     #[serde(rename = "_id")]
     pub id: ObjectId,
+    // 0 for documents created before versioning
+    #[serde(default)]
+    pub schemaVersion: i32,
     // profile info
     pub profileOwnerId: ObjectId,
     pub apiToken: String,
@@ -28,6 +37,9 @@ pub struct IntSensor {
     // dates
     pub createdAt: DateTime,
     pub modifiedAt: DateTime,
+    // set when the sensor is soft-deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deletedAt: Option<DateTime>,
 }
 
 #[allow(non_snake_case)]
@@ -35,6 +47,9 @@ pub struct IntSensor {
 pub struct FloatSensor {
     #[serde(rename = "_id")]
     pub id: ObjectId,
+    // 0 for documents created before versioning
+    #[serde(default)]
+    pub schemaVersion: i32,
     // profile info
     pub profileOwnerId: ObjectId,
     pub apiToken: String,
@@ -50,9 +65,21 @@ pub struct FloatSensor {
     // dates
     pub createdAt: DateTime,
     pub modifiedAt: DateTime,
+    // set when the sensor is soft-deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deletedAt: Option<DateTime>,
+}
+
+/// Outcome of a sensor registration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisteredSensor {
+    pub id: String,
+    // true if the sensor has been created, false if an existing one has been updated
+    pub created: bool,
 }
 
 pub trait Sensor {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         // profile info
         profile_owner_id: ObjectId,
@@ -123,6 +150,7 @@ impl Sensor for FloatSensor {
 }
 
 impl IntSensor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         // profile info
         profile_owner_id: ObjectId,
@@ -139,6 +167,7 @@ impl IntSensor {
         let date_now: DateTime = DateTime::now();
         Self {
             id: ObjectId::new(),
+            schemaVersion: SENSOR_SCHEMA_VERSION,
             profileOwnerId: profile_owner_id,
             apiToken: api_token,
             deviceUuid: device_uuid,
@@ -150,11 +179,13 @@ impl IntSensor {
             value: 0,
             createdAt: date_now,
             modifiedAt: date_now,
+            deletedAt: None,
         }
     }
 }
 
 impl FloatSensor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         // profile info
         profile_owner_id: ObjectId,
@@ -171,6 +202,7 @@ impl FloatSensor {
         let date_now: DateTime = DateTime::now();
         Self {
             id: ObjectId::new(),
+            schemaVersion: SENSOR_SCHEMA_VERSION,
             profileOwnerId: profile_owner_id,
             apiToken: api_token,
             deviceUuid: device_uuid,
@@ -182,29 +214,75 @@ impl FloatSensor {
             value: 0.0,
             createdAt: date_now,
             modifiedAt: date_now,
+            deletedAt: None,
         }
     }
 }
 
+/// Build the document of a new sensor from `input`, failing with `DbError::Validation` if `profileOwnerId`
+/// is not a valid `ObjectId`.
 pub fn new_from_register_input<T: Sensor + Serialize>(
-    input: Json<RegisterInput>,
-    sensor_type: &str,
-) -> Result<Bson, Error> {
-    let profile_owner_id = ObjectId::from_str(input.profileOwnerId.as_str());
-    match profile_owner_id {
-        Ok(profile_id) => {
-            let result = T::new(
-                profile_id,
-                input.apiToken.clone(),
-                input.deviceUuid.clone(),
-                input.mac.clone(),
-                input.model.clone(),
-                input.manufacturer.clone(),
-                input.featureUuid.clone(),
-                sensor_type.to_string(), // featureName
-            );
-            Ok(to_bson(&result).unwrap())
-        }
-        Err(err) => Err(err),
+    input: &RegisterInput,
+    sensor_type: SensorType,
+) -> Result<Bson, DbError> {
+    let profile_owner_id = match ObjectId::from_str(input.profileOwnerId.as_str()) {
+        Ok(profile_owner_id) => profile_owner_id,
+        Err(err) => return Err(DbError::Validation(format!("profileOwnerId: {}", err))),
+    };
+    let result = T::new(
+        profile_owner_id,
+        input.apiToken.clone(),
+        input.deviceUuid.clone(),
+        input.mac.clone(),
+        input.model.clone(),
+        input.manufacturer.clone(),
+        input.featureUuid.clone(),
+        sensor_type.to_string(), // featureName
+    );
+    let mut document = match to_bson(&result) {
+        Ok(document) => document,
+        Err(err) => return Err(DbError::MalformedDocument(err.to_string())),
+    };
+    // a new sensor starts with the default value of its type
+    if let Bson::Document(ref mut document) = document {
+        let default_value = match sensor_type.kind() {
+            ValueKind::Float => Bson::Double(sensor_type.default_value()),
+            ValueKind::Int => Bson::Int64(sensor_type.default_value() as i64),
+        };
+        document.insert("value", default_value);
     }
+    Ok(document)
+}
+
+/// Current value of a sensor
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorValue {
+    // always a f64, even if the sensor type has int values
+    pub value: f64,
+    pub created_at: DateTime,
+    pub modified_at: DateTime,
+}
+
+/// Sensor returned by list apis, without `apiToken` and `profileOwnerId`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorSummary {
+    pub id: ObjectId,
+    pub device_uuid: String,
+    pub feature_uuid: String,
+    pub feature_name: String,
+    pub value: Option<f64>,
+    pub created_at: DateTime,
+    pub modified_at: DateTime,
+    pub mac: String,
+    pub model: String,
+    pub manufacturer: String,
+}
+
+/// Number of registered sensors of a type, used by metrics
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SensorTypeStats {
+    pub feature_name: String,
+    pub registered: u64,
+    // sensors without new values for a while
+    pub stale: u64,
 }
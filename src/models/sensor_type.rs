@@ -0,0 +1,203 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use rocket::request::FromParam;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Numeric kind of the value of a sensor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueKind {
+    Int,
+    Float,
+}
+
+/// Definition of a sensor type in the catalogue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SensorTypeDef {
+    // used as `featureName` in db and in routes
+    pub name: String,
+    pub kind: ValueKind,
+    // unit of measurement of the value, empty if the value is a state or an index
+    #[serde(default)]
+    pub unit: String,
+    // valid range of the value, both inclusive
+    pub min: f64,
+    pub max: f64,
+    // value of a sensor just registered
+    pub default: f64,
+}
+
+/// Content of a catalogue file, with a `[[types]]` table for every sensor type
+#[derive(Debug, Deserialize)]
+pub struct SensorTypesFile {
+    pub types: Vec<SensorTypeDef>,
+}
+
+/// Catalogue used when `SENSOR_TYPES_FILE` is not defined,
+/// built from `sensor_types.toml` shipped with the project
+pub fn default_sensor_types() -> Result<Vec<SensorTypeDef>, Vec<String>> {
+    let file: SensorTypesFile = toml::from_str(include_str!("../../sensor_types.toml"))
+        .map_err(|err| vec![format!("built-in sensor_types.toml is invalid TOML: {}", err)])?;
+    validate_sensor_types(&file.types)?;
+    Ok(file.types)
+}
+
+/// Check a catalogue of sensor types, returning all its errors
+pub fn validate_sensor_types(defs: &[SensorTypeDef]) -> Result<(), Vec<String>> {
+    let mut errors: Vec<String> = Vec::new();
+    if defs.is_empty() {
+        errors.push("at least a sensor type must be defined".to_string());
+    }
+    let mut names: HashSet<&str> = HashSet::new();
+    for def in defs.iter() {
+        let valid_name = !def.name.is_empty()
+            && def
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid_name {
+            errors.push(format!(
+                "sensor type '{}' must have a name of lowercase letters, digits or '_'",
+                def.name
+            ));
+        }
+        if !names.insert(def.name.as_str()) {
+            errors.push(format!("sensor type '{}' is defined more than once", def.name));
+        }
+        if !def.min.is_finite() || !def.max.is_finite() || def.min > def.max {
+            errors.push(format!(
+                "sensor type '{}' has an invalid range {}..{}",
+                def.name, def.min, def.max
+            ));
+        } else if !(def.min..=def.max).contains(&def.default) {
+            errors.push(format!(
+                "sensor type '{}' has default = {} out of range {}..{}",
+                def.name, def.default, def.min, def.max
+            ));
+        }
+        if def.kind == ValueKind::Int && [def.min, def.max, def.default].iter().any(|v| v.fract() != 0.0) {
+            errors.push(format!(
+                "sensor type '{}' has kind int, but min, max or default is not an integer",
+                def.name
+            ));
+        }
+    }
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+static CATALOGUE: OnceLock<Vec<SensorTypeDef>> = OnceLock::new();
+
+/// Set the catalogue of sensor types accepted by the server.
+/// It can be set only once, before any sensor type is used,
+/// otherwise the default catalogue is used.
+pub fn init_sensor_types(defs: Vec<SensorTypeDef>) -> Result<(), Vec<String>> {
+    validate_sensor_types(&defs)?;
+    let catalogue = CATALOGUE.get_or_init(|| defs.clone());
+    if *catalogue == defs {
+        Ok(())
+    } else {
+        Err(vec!["sensor types catalogue is already initialized".to_string()])
+    }
+}
+
+fn catalogue() -> &'static [SensorTypeDef] {
+    // an invalid built-in catalogue is already reported by `config::init`
+    CATALOGUE.get_or_init(|| default_sensor_types().unwrap_or_default())
+}
+
+/// Type of a sensor feature in the catalogue, used as `featureName` in db
+#[derive(Debug, Clone, Copy)]
+pub struct SensorType(&'static SensorTypeDef);
+
+impl SensorType {
+    /// All sensor types in the catalogue
+    pub fn all() -> impl Iterator<Item = SensorType> {
+        catalogue().iter().map(SensorType)
+    }
+
+    pub fn def(self) -> &'static SensorTypeDef {
+        self.0
+    }
+
+    pub fn as_str(self) -> &'static str {
+        self.0.name.as_str()
+    }
+
+    pub fn kind(self) -> ValueKind {
+        self.0.kind
+    }
+
+    pub fn unit(self) -> &'static str {
+        self.0.unit.as_str()
+    }
+
+    /// Valid range of the value, both inclusive
+    pub fn range(self) -> (f64, f64) {
+        (self.0.min, self.0.max)
+    }
+
+    pub fn default_value(self) -> f64 {
+        self.0.default
+    }
+
+    pub fn is_valid_value(self, value: f64) -> bool {
+        let (min, max) = self.range();
+        value >= min && value <= max
+    }
+}
+
+impl PartialEq for SensorType {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SensorType {}
+
+impl Hash for SensorType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl FromStr for SensorType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SensorType::all()
+            .find(|sensor_type| sensor_type.as_str() == s)
+            .ok_or_else(|| format!("Unknown sensor_type = {}", s))
+    }
+}
+
+impl fmt::Display for SensorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// serialized by name, as `featureName`
+impl Serialize for SensorType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SensorType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        SensorType::from_str(&name).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<'a> FromParam<'a> for SensorType {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        SensorType::from_str(param).map_err(|_| param)
+    }
+}
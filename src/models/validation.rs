@@ -0,0 +1,113 @@
+use std::str::FromStr;
+
+use mongodb::bson::oid::ObjectId;
+use rocket::serde::uuid::Uuid;
+
+use crate::errors::api_error::FieldError;
+
+/// Check applied to a string field of an input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// number of characters between `min` and `max`, both included
+    Length { min: usize, max: usize },
+    /// hyphenated UUID, like `b5a3f0b4-9e8f-4d1e-8f0a-3c2b1d0e9f8a`
+    Uuid,
+    /// MongoDB ObjectId, as 24 hex characters
+    ObjectId,
+    /// MAC address separated by `:`, `-` or nothing, normalized to the upper-case colon form
+    Mac,
+}
+
+impl Rule {
+    // check `value`, returning its normalized form if it must be replaced
+    fn check(&self, value: &str) -> Result<Option<String>, String> {
+        match *self {
+            Rule::Length { min, max } => {
+                let length = value.chars().count();
+                if length < min || length > max {
+                    Err(format!("must be between {} and {} characters", min, max))
+                } else {
+                    Ok(None)
+                }
+            }
+            Rule::Uuid => match Uuid::try_parse(value) {
+                // 'try_parse' accepts also the simple form without hyphens
+                Ok(_) if value.len() == 36 => Ok(None),
+                _ => Err(String::from("must be a UUID")),
+            },
+            Rule::ObjectId => match ObjectId::from_str(value) {
+                Ok(_) => Ok(None),
+                Err(_) => Err(String::from("must be an ObjectId of 24 hex characters")),
+            },
+            Rule::Mac => match normalize_mac(value) {
+                Some(mac) => Ok(Some(mac)),
+                None => Err(String::from("must be a MAC address, like AA:BB:CC:DD:EE:FF")),
+            },
+        }
+    }
+}
+
+/// MAC address in upper-case colon form (`AA:BB:CC:DD:EE:FF`), from 6 hex bytes separated
+/// by `:`, `-` or nothing. None if `value` is not a MAC address.
+pub fn normalize_mac(value: &str) -> Option<String> {
+    let bytes: Vec<&str> = if value.contains(':') {
+        value.split(':').collect()
+    } else if value.contains('-') {
+        value.split('-').collect()
+    } else if value.len() == 12 && value.is_ascii() {
+        (0..12).step_by(2).map(|index| &value[index..index + 2]).collect()
+    } else {
+        return None;
+    };
+    let is_valid = bytes.len() == 6
+        && bytes
+            .iter()
+            .all(|byte| byte.len() == 2 && byte.chars().all(|c| c.is_ascii_hexdigit()));
+    if is_valid {
+        Some(bytes.join(":").to_ascii_uppercase())
+    } else {
+        None
+    }
+}
+
+/// Input with validation rules for its fields
+pub trait Validate {
+    /// Check every field, normalizing the values when required by the rules.
+    /// It fails with the errors of all invalid fields.
+    fn validate(&mut self) -> Result<(), Vec<FieldError>>;
+}
+
+/// Collector of the errors of the fields of an input
+#[derive(Debug, Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply `rules` to the field `name`, stopping at the first failed one
+    pub fn field(mut self, name: &str, value: &mut String, rules: &[Rule]) -> Self {
+        for rule in rules {
+            match rule.check(value) {
+                Ok(Some(normalized)) => *value = normalized,
+                Ok(None) => {}
+                Err(message) => {
+                    self.errors.push(FieldError::new(name, message));
+                    break;
+                }
+            }
+        }
+        self
+    }
+
+    pub fn finish(self) -> Result<(), Vec<FieldError>> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Correlation of requests with their logs.
+//!
+//! `RequestIdFairing` accepts the `X-Request-Id` of every request, or generates a new one,
+//! and echoes it in the response. Handlers of routes and catchers wrapped by `traced_routes`
+//! and `traced_catchers` run in a `request` span with the id, so every event logged while
+//! handling the request, also by guards and storage, carries `request_id`.
+//! The span is also exported to OpenTelemetry, see `telemetry`.
+
+use rocket::catcher::{self, Catcher};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::route::{self, Route};
+use rocket::serde::uuid::Uuid;
+use rocket::{Data, Request, Response};
+use tracing::field::Empty;
+use tracing::{Instrument, Span, info_span};
+
+use crate::errors::api_error::REQUEST_ID_HEADER;
+use crate::telemetry;
+
+// longest id accepted from clients
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Id of a request, from its `X-Request-Id` header or generated by `RequestIdFairing`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Id of `req`, None if `RequestIdFairing` isn't attached
+    pub fn of<'r>(req: &'r Request<'_>) -> Option<&'r str> {
+        req.local_cache(|| None::<RequestId>).as_ref().map(|id| id.0.as_str())
+    }
+
+    // id sent by the client, if it has only letters, digits and `-_.:`, otherwise a new UUID
+    fn from_header(value: Option<&str>) -> Self {
+        match value {
+            Some(value)
+                if !value.is_empty()
+                    && value.len() <= MAX_REQUEST_ID_LENGTH
+                    && value
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')) =>
+            {
+                Self(value.to_string())
+            }
+            _ => Self(Uuid::new_v4().to_string()),
+        }
+    }
+}
+
+// span of a request, cached in the request
+struct RequestSpan(Span);
+
+/// Fairing that assigns an id and a span to every request, and echoes the id in the `X-Request-Id` header
+pub struct RequestIdFairing;
+
+#[rocket::async_trait]
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "X-Request-Id of requests",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let id = RequestId::from_header(req.headers().get_one(REQUEST_ID_HEADER));
+        let span = info_span!(
+            target: "app",
+            "request",
+            request_id = %id.0,
+            method = %req.method(),
+            path = %req.uri().path(),
+            otel.kind = "server",
+            otel.status_code = Empty,
+        );
+        telemetry::set_remote_parent(&span, req.headers());
+        req.local_cache(|| Some(id));
+        req.local_cache(|| RequestSpan(span));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if let Some(id) = RequestId::of(req) {
+            res.set_header(Header::new(REQUEST_ID_HEADER, id.to_string()));
+        }
+        telemetry::record_response(&request_span(req), req, res.status());
+    }
+}
+
+// span of `req`, disabled if `RequestIdFairing` isn't attached
+fn request_span(req: &Request<'_>) -> Span {
+    req.local_cache(|| RequestSpan(Span::none())).0.clone()
+}
+
+// handler of a route, run in the span of the request
+#[derive(Clone)]
+struct TracedRoute(Box<dyn route::Handler>);
+
+#[rocket::async_trait]
+impl route::Handler for TracedRoute {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        self.0.handle(req, data).instrument(request_span(req)).await
+    }
+}
+
+// handler of a catcher, run in the span of the request
+#[derive(Clone)]
+struct TracedCatcher(Box<dyn catcher::Handler>);
+
+#[rocket::async_trait]
+impl catcher::Handler for TracedCatcher {
+    async fn handle<'r>(&self, status: Status, req: &'r Request<'_>) -> catcher::Result<'r> {
+        self.0.handle(status, req).instrument(request_span(req)).await
+    }
+}
+
+/// `routes`, with handlers that run in the span of the request
+pub fn traced_routes(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(TracedRoute(route.handler));
+            route
+        })
+        .collect()
+}
+
+/// `catchers`, with handlers that run in the span of the request
+pub fn traced_catchers(catchers: Vec<Catcher>) -> Vec<Catcher> {
+    catchers
+        .into_iter()
+        .map(|mut catcher| {
+            catcher.handler = Box::new(TracedCatcher(catcher.handler));
+            catcher
+        })
+        .collect()
+}
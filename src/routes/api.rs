@@ -1,25 +1,33 @@
-use mongodb::Database;
-use mongodb::bson::doc;
+use std::str::FromStr;
+
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
 use rocket::State;
 use rocket::http::Status;
-use rocket::serde::json::{Json, json};
+use rocket::serde::json::{Json, Value, json};
 use tracing::{debug, error, info};
 
-use crate::db::sensor;
-use crate::errors::api_error::{ApiError, ApiResponse};
-use crate::models::inputs::RegisterInput;
-
-pub static VALID_SENSOR_TYPES: &[&str] = &[
-    "temperature",
-    "humidity",
-    "light",
-    "motion",
-    "airquality",
-    "airpressure",
-    "online",
-];
-
-/// keepalive
+use crate::db::repository::DynSensorRepository;
+use crate::errors::api_error::{ApiError, ApiResponse, ErrorCode};
+use crate::errors::db_error::DbError;
+use crate::guards::{BearerToken, ProfileAuth, SensorAuth, Validated, tokens_match};
+use crate::models::aggregation::{Agg, Bucket};
+use crate::models::inputs::{ListSensorsQuery, RegisterInput, SortOrder, ValueInput};
+use crate::models::sensor::{SensorSummary, SensorValue};
+use crate::models::sensor_type::{SensorType, SensorTypeDef, ValueKind};
+
+// default and max number of readings returned by history api
+const DEFAULT_HISTORY_LIMIT: i64 = 1000;
+const MAX_HISTORY_LIMIT: i64 = 10000;
+// default and max number of sensors in a page of list apis
+const DEFAULT_LIST_LIMIT: i64 = 50;
+const MAX_LIST_LIMIT: i64 = 500;
+// max number of buckets returned by aggregate api
+const MAX_AGGREGATE_BUCKETS: i64 = 10000;
+// default time range of history and aggregate apis (24 hours)
+const DEFAULT_HISTORY_RANGE_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// keepalive, kept for compatibility: probes should use `/health/live` and `/health/ready`
 #[get("/keepalive")]
 pub async fn keep_alive() -> ApiResponse {
     ApiResponse {
@@ -28,105 +36,570 @@ pub async fn keep_alive() -> ApiResponse {
     }
 }
 
-/// register a new sensor
+/// list the sensor types accepted by the server, with their value kind, unit, range and default value
+#[get("/sensors/types")]
+pub async fn get_sensor_types() -> ApiResponse {
+    info!(target: "app", "REST - GET - get_sensor_types");
+    let types: Vec<&SensorTypeDef> = SensorType::all().map(SensorType::def).collect();
+    ApiResponse {
+        json: serde_json::to_value(types).unwrap(),
+        code: Status::Ok.code,
+    }
+}
+
+/// register a new sensor, or update the metadata of an already registered one.
+/// Registering again a sensor requires its current `apiToken` as bearer token, and adding a sensor
+/// to a registered device or profile the `apiToken` of one of their sensors.
 #[post("/sensors/register/<sensor_type>", data = "<input>")]
-pub async fn post_register(db: &State<Database>, input: Json<RegisterInput>, sensor_type: &str) -> ApiResponse {
-    if VALID_SENSOR_TYPES.contains(&sensor_type) {
-        info!(target: "app", "REST - POST - post_register sensor_type = {}", sensor_type);
-        insert_register(db, input, sensor_type).await
-    } else {
-        ApiResponse {
-            json: serde_json::to_value(ApiError {
-                message: "Invalid sensor type".to_string(),
-                code: Status::BadRequest.code,
-            })
-            .unwrap(),
-            code: Status::BadRequest.code,
+pub async fn post_register(
+    repository: &State<DynSensorRepository>,
+    token: Option<BearerToken>,
+    input: Validated<RegisterInput>,
+    sensor_type: Result<SensorType, &str>,
+) -> Result<ApiResponse, ApiError> {
+    match sensor_type {
+        Ok(sensor_type) => {
+            info!(target: "app", "REST - POST - post_register sensor_type = {}", sensor_type);
+            insert_register(repository, token.as_ref(), &input.into_inner(), sensor_type).await
         }
+        Err(_) => Err(invalid_sensor_type()),
     }
 }
 
 /// get sensor value by device and feature UUIDs and type
 #[get("/sensors/<device_uuid>/features/<feature_uuid>/<sensor_type>")]
 pub async fn get_sensor_value(
-    db: &State<Database>,
+    repository: &State<DynSensorRepository>,
+    _auth: SensorAuth,
     device_uuid: &str,
     feature_uuid: &str,
-    sensor_type: &str,
-) -> ApiResponse {
+    sensor_type: Result<SensorType, &str>,
+) -> Result<ApiResponse, ApiError> {
+    let Ok(sensor_type) = sensor_type else {
+        return Err(invalid_sensor_type());
+    };
     info!(target: "app", "REST - GET - get_sensor_value sensor_type = {}, device_uuid = {}, feature_uuid = {}", sensor_type, device_uuid, feature_uuid);
-    find_sensor_value(db, device_uuid, feature_uuid, sensor_type).await
+    find_sensor_value(repository, device_uuid, feature_uuid, sensor_type).await
+}
+
+/// set the value of a registered sensor
+#[put(
+    "/sensors/<device_uuid>/features/<feature_uuid>/<sensor_type>/value",
+    data = "<input>"
+)]
+pub async fn put_sensor_value(
+    repository: &State<DynSensorRepository>,
+    _auth: SensorAuth,
+    device_uuid: &str,
+    feature_uuid: &str,
+    sensor_type: Result<SensorType, &str>,
+    input: Json<ValueInput>,
+) -> Result<ApiResponse, ApiError> {
+    match sensor_type {
+        Ok(sensor_type) => {
+            info!(target: "app", "REST - PUT - put_sensor_value sensor_type = {}, device_uuid = {}, feature_uuid = {}", sensor_type, device_uuid, feature_uuid);
+            update_value(repository, device_uuid, feature_uuid, sensor_type, input).await
+        }
+        Err(_) => Err(invalid_sensor_type()),
+    }
+}
+
+/// get the history of a sensor value, sorted by timestamp.
+/// `from` and `to` are timestamps in milliseconds, by default the last 24 hours.
+#[get("/sensors/<device_uuid>/features/<feature_uuid>/<sensor_type>/history?<from>&<to>&<limit>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_sensor_history(
+    repository: &State<DynSensorRepository>,
+    _auth: SensorAuth,
+    device_uuid: &str,
+    feature_uuid: &str,
+    sensor_type: Result<SensorType, &str>,
+    from: Option<i64>,
+    to: Option<i64>,
+    limit: Option<i64>,
+) -> Result<ApiResponse, ApiError> {
+    let Ok(sensor_type) = sensor_type else {
+        return Err(invalid_sensor_type());
+    };
+    let to = to.unwrap_or_else(|| DateTime::now().timestamp_millis());
+    let from = from.unwrap_or(to.saturating_sub(DEFAULT_HISTORY_RANGE_MS));
+    let limit = limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    if from > to || limit <= 0 || limit > MAX_HISTORY_LIMIT {
+        return Err(ApiError::with_message(
+            ErrorCode::InvalidRange,
+            "Invalid history range or limit",
+        ));
+    }
+    info!(target: "app", "REST - GET - get_sensor_history sensor_type = {}, device_uuid = {}, feature_uuid = {}, from = {}, to = {}, limit = {}", sensor_type, device_uuid, feature_uuid, from, to, limit);
+    find_history(repository, device_uuid, feature_uuid, sensor_type, from, to, limit).await
+}
+
+/// get readings of a sensor aggregated in time buckets (by default the hourly average), sorted by timestamp.
+/// `from` and `to` are timestamps in milliseconds, by default the last 24 hours.
+#[get("/sensors/<device_uuid>/features/<feature_uuid>/<sensor_type>/aggregate?<bucket>&<agg>&<from>&<to>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_sensor_aggregate(
+    repository: &State<DynSensorRepository>,
+    _auth: SensorAuth,
+    device_uuid: &str,
+    feature_uuid: &str,
+    sensor_type: Result<SensorType, &str>,
+    bucket: Option<Bucket>,
+    agg: Option<Agg>,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<ApiResponse, ApiError> {
+    let Ok(sensor_type) = sensor_type else {
+        return Err(invalid_sensor_type());
+    };
+    let bucket = bucket.unwrap_or(Bucket::OneHour);
+    let agg = agg.unwrap_or(Agg::Avg);
+    let to = to.unwrap_or_else(|| DateTime::now().timestamp_millis());
+    let from = from.unwrap_or(to.saturating_sub(DEFAULT_HISTORY_RANGE_MS));
+    let Some(from) = aggregate_range_start(from, to, bucket) else {
+        return Err(ApiError::with_message(
+            ErrorCode::InvalidRange,
+            "Invalid aggregate range",
+        ));
+    };
+    info!(target: "app", "REST - GET - get_sensor_aggregate sensor_type = {}, device_uuid = {}, feature_uuid = {}, bucket = {:?}, agg = {:?}, from = {}, to = {}", sensor_type, device_uuid, feature_uuid, bucket, agg, from, to);
+    aggregate_history(
+        repository,
+        device_uuid,
+        feature_uuid,
+        sensor_type,
+        from,
+        to,
+        bucket,
+        agg,
+    )
+    .await
+}
+
+/// list sensors of a device, sorted by `modifiedAt`
+#[get("/devices/<device_uuid>/sensors?<query..>")]
+pub async fn get_device_sensors(
+    repository: &State<DynSensorRepository>,
+    _auth: SensorAuth,
+    device_uuid: &str,
+    query: ListSensorsQuery,
+) -> Result<ApiResponse, ApiError> {
+    info!(target: "app", "REST - GET - get_device_sensors device_uuid = {}, query = {:?}", device_uuid, query);
+    let params = parse_list_query(&query)?;
+    let result = repository
+        .find_sensors_by_device(
+            device_uuid,
+            params.feature_name,
+            params.sort_order,
+            params.limit + 1,
+            params.after,
+        )
+        .await;
+    sensors_page_response(result, params.limit)
+}
+
+/// list sensors of a profile, sorted by `modifiedAt`
+#[get("/profiles/<profile_owner_id>/sensors?<query..>")]
+pub async fn get_profile_sensors(
+    repository: &State<DynSensorRepository>,
+    _auth: ProfileAuth,
+    profile_owner_id: &str,
+    query: ListSensorsQuery,
+) -> Result<ApiResponse, ApiError> {
+    info!(target: "app", "REST - GET - get_profile_sensors profile_owner_id = {}, query = {:?}", profile_owner_id, query);
+    let profile_owner_id = match ObjectId::from_str(profile_owner_id) {
+        Ok(profile_owner_id) => profile_owner_id,
+        Err(_) => {
+            return Err(ApiError::new(ErrorCode::InvalidProfile));
+        }
+    };
+    let params = parse_list_query(&query)?;
+    let result = repository
+        .find_sensors_by_profile(
+            profile_owner_id,
+            params.feature_name,
+            params.sort_order,
+            params.limit + 1,
+            params.after,
+        )
+        .await;
+    sensors_page_response(result, params.limit)
+}
+
+/// delete a sensor, soft-deleting it by default or removing it when `purge` is true
+#[delete("/sensors/<device_uuid>/features/<feature_uuid>/<sensor_type>?<purge>")]
+pub async fn delete_sensor(
+    repository: &State<DynSensorRepository>,
+    token: BearerToken,
+    device_uuid: &str,
+    feature_uuid: &str,
+    sensor_type: Result<SensorType, &str>,
+    purge: Option<bool>,
+) -> Result<ApiResponse, ApiError> {
+    match sensor_type {
+        Ok(sensor_type) => {
+            info!(target: "app", "REST - DELETE - delete_sensor sensor_type = {}, device_uuid = {}, feature_uuid = {}, purge = {:?}", sensor_type, device_uuid, feature_uuid, purge);
+            remove_sensors(
+                repository,
+                &token,
+                device_uuid,
+                Some((feature_uuid, sensor_type)),
+                purge.unwrap_or(false),
+            )
+            .await
+        }
+        Err(_) => Err(invalid_sensor_type()),
+    }
 }
 
-async fn insert_register(db: &State<Database>, input: Json<RegisterInput>, sensor_type: &str) -> ApiResponse {
+/// delete all sensors of a device, soft-deleting them by default or removing them when `purge` is true
+#[delete("/sensors/<device_uuid>?<purge>")]
+pub async fn delete_device_sensors(
+    repository: &State<DynSensorRepository>,
+    token: BearerToken,
+    device_uuid: &str,
+    purge: Option<bool>,
+) -> Result<ApiResponse, ApiError> {
+    info!(target: "app", "REST - DELETE - delete_device_sensors device_uuid = {}, purge = {:?}", device_uuid, purge);
+    remove_sensors(repository, &token, device_uuid, None, purge.unwrap_or(false)).await
+}
+
+async fn insert_register(
+    repository: &State<DynSensorRepository>,
+    token: Option<&BearerToken>,
+    input: &RegisterInput,
+    sensor_type: SensorType,
+) -> Result<ApiResponse, ApiError> {
     debug!(target: "app", "insert_register - called with sensor_type = {}", sensor_type);
-    match sensor::insert_sensor(db, input, sensor_type).await {
-        Ok(register_doc_id) => {
-            debug!(target: "app", "insert_register - document inserted with id = {}", register_doc_id);
-            ApiResponse {
-                json: json!({ "id": register_doc_id }),
-                code: Status::Ok.code,
-            }
+    check_register_token(repository, token, input).await?;
+    let api_token = token.map(|token| token.0.as_str());
+    match repository.upsert_sensor(input, sensor_type, api_token).await {
+        Ok(registered) => {
+            debug!(target: "app", "insert_register - document upserted with id = {}, created = {}", registered.id, registered.created);
+            Ok(ApiResponse {
+                json: json!({ "id": registered.id }),
+                code: if registered.created {
+                    Status::Created.code
+                } else {
+                    Status::Ok.code
+                },
+            })
         }
         Err(error) => {
             error!(target: "app", "insert_register - error = {:?}", error);
-            ApiResponse {
-                json: serde_json::to_value(ApiError {
-                    message: "Invalid input".to_string(),
-                    code: Status::BadRequest.code,
-                })
-                .unwrap(),
-                code: Status::BadRequest.code,
+            Err(ApiError::from(&error))
+        }
+    }
+}
+
+/// Check that the caller can add a sensor to the device and to the profile of `input`.
+/// Sensors of a registered device or profile can be read with the `apiToken` of any of them,
+/// so a sensor can join them only if the `apiToken` of `input`, or the bearer token of the caller,
+/// is one of their `apiToken`s, soft-deleted sensors included.
+async fn check_register_token(
+    repository: &State<DynSensorRepository>,
+    token: Option<&BearerToken>,
+    input: &RegisterInput,
+) -> Result<(), ApiError> {
+    let Ok(profile_owner_id) = ObjectId::from_str(&input.profileOwnerId) else {
+        // invalid inputs are rejected by `Validated`
+        return Err(ApiError::new(ErrorCode::InvalidProfile));
+    };
+    let device_api_tokens = repository.find_api_tokens(&input.deviceUuid, None, true).await;
+    let profile_api_tokens = repository.find_profile_api_tokens(profile_owner_id, true).await;
+    for api_tokens in [device_api_tokens, profile_api_tokens] {
+        match api_tokens {
+            Ok(api_tokens) if api_tokens.is_empty() => {}
+            Ok(api_tokens)
+                if api_tokens.iter().any(|api_token| {
+                    tokens_match(&input.apiToken, api_token) || token.is_some_and(|token| token.matches(api_token))
+                }) => {}
+            Ok(_) => {
+                error!(target: "app", "check_register_token - apiToken doesn't match the registered device or profile, device_uuid = {}", input.deviceUuid);
+                return Err(ApiError::new(ErrorCode::Forbidden));
+            }
+            Err(error) => {
+                error!(target: "app", "check_register_token - cannot find sensors, error = {:?}", error);
+                return Err(ApiError::from(&error));
             }
         }
     }
+    Ok(())
 }
 
 async fn find_sensor_value(
-    db: &State<Database>,
+    repository: &State<DynSensorRepository>,
     device_uuid: &str,
     feature_uuid: &str,
-    sensor_type: &str,
-) -> ApiResponse {
-    match sensor::find_sensor_value_by_uuid(db, device_uuid, feature_uuid, sensor_type).await {
-        Ok(sensor_doc) => {
-            info!(target: "app", "find_sensor_value - result sensor_doc = {}", sensor_doc);
-            let value: f64 = match sensor_type {
-                "temperature" | "humidity" | "light" | "airpressure" => sensor_doc.get_f64("value").unwrap(),
-                "motion" | "airquality" | "online" => sensor_doc.get_i64("value").unwrap() as f64,
-                _ => {
-                    return ApiResponse {
-                        json: serde_json::to_value(ApiError {
-                            message: "Unknown sensor type".to_string(),
-                            code: Status::InternalServerError.code,
-                        })
-                        .unwrap(),
-                        code: Status::InternalServerError.code,
-                    };
-                }
-            };
-            let created_at = sensor_doc.get_datetime("createdAt").unwrap().timestamp_millis();
-            let modified_at = sensor_doc.get_datetime("modifiedAt").unwrap().timestamp_millis();
-            ApiResponse {
-                json: json!({
-                    // in json response, 'value' is always a f64, even if in db it's a i64
-                    "value": value,
-                    "createdAt": created_at,
-                    "modifiedAt": modified_at,
-                }),
-                code: Status::Ok.code,
-            }
+    sensor_type: SensorType,
+) -> Result<ApiResponse, ApiError> {
+    match repository
+        .find_sensor_value(device_uuid, feature_uuid, sensor_type)
+        .await
+    {
+        Ok(Some(sensor_value)) => {
+            info!(target: "app", "find_sensor_value - result sensor_value = {:?}", sensor_value);
+            Ok(sensor_value_response(&sensor_value))
         }
+        Ok(None) => Err(ApiError::new(ErrorCode::SensorNotFound)),
         Err(error) => {
             error!(target: "app", "find_sensor_value - error {:?}", error);
-            ApiResponse {
-                json: serde_json::to_value(ApiError {
-                    message: "Internal server error".to_string(),
-                    code: Status::InternalServerError.code,
+            Err(ApiError::from(&error))
+        }
+    }
+}
+
+async fn update_value(
+    repository: &State<DynSensorRepository>,
+    device_uuid: &str,
+    feature_uuid: &str,
+    sensor_type: SensorType,
+    input: Json<ValueInput>,
+) -> Result<ApiResponse, ApiError> {
+    // float sensors accept any number, int sensors only integers
+    let value: Option<f64> = match sensor_type.kind() {
+        ValueKind::Float => input.value.as_f64(),
+        ValueKind::Int => input.value.as_i64().map(|value| value as f64),
+    };
+    // the value must be also in the range of the sensor type
+    let value: f64 = match value {
+        Some(value) if sensor_type.is_valid_value(value) => value,
+        _ => {
+            error!(target: "app", "update_value - invalid value = {} for sensor_type = {}", input.value, sensor_type);
+            return Err(ApiError::new(ErrorCode::InvalidValue));
+        }
+    };
+    match repository
+        .update_sensor_value(device_uuid, feature_uuid, sensor_type, value)
+        .await
+    {
+        Ok(Some(sensor_value)) => {
+            info!(target: "app", "update_value - result sensor_value = {:?}", sensor_value);
+            Ok(sensor_value_response(&sensor_value))
+        }
+        Ok(None) => Err(ApiError::new(ErrorCode::SensorNotFound)),
+        Err(error) => {
+            error!(target: "app", "update_value - error {:?}", error);
+            Err(ApiError::from(&error))
+        }
+    }
+}
+
+fn sensor_value_response(sensor_value: &SensorValue) -> ApiResponse {
+    ApiResponse {
+        json: json!({
+            // in json response, 'value' is always a f64, even if in db it's a i64
+            "value": sensor_value.value,
+            "createdAt": sensor_value.created_at.timestamp_millis(),
+            "modifiedAt": sensor_value.modified_at.timestamp_millis(),
+        }),
+        code: Status::Ok.code,
+    }
+}
+
+async fn find_history(
+    repository: &State<DynSensorRepository>,
+    device_uuid: &str,
+    feature_uuid: &str,
+    sensor_type: SensorType,
+    from: i64,
+    to: i64,
+    limit: i64,
+) -> Result<ApiResponse, ApiError> {
+    let from = DateTime::from_millis(from);
+    let to = DateTime::from_millis(to);
+    match repository
+        .find_readings(device_uuid, feature_uuid, sensor_type, from, to, limit)
+        .await
+    {
+        Ok(readings) => {
+            debug!(target: "app", "find_history - found {} readings", readings.len());
+            let readings: Vec<Value> = readings
+                .iter()
+                .map(|reading| {
+                    json!({
+                        // in json response, 'value' is always a f64, even if in db it's a i64
+                        "value": reading.value,
+                        "timestamp": reading.timestamp.timestamp_millis(),
+                    })
                 })
-                .unwrap(),
-                code: Status::InternalServerError.code,
-            }
+                .collect();
+            Ok(ApiResponse {
+                json: Value::Array(readings),
+                code: Status::Ok.code,
+            })
+        }
+        Err(error) => {
+            error!(target: "app", "find_history - error {:?}", error);
+            Err(ApiError::from(&error))
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn aggregate_history(
+    repository: &State<DynSensorRepository>,
+    device_uuid: &str,
+    feature_uuid: &str,
+    sensor_type: SensorType,
+    from: i64,
+    to: i64,
+    bucket: Bucket,
+    agg: Agg,
+) -> Result<ApiResponse, ApiError> {
+    let from = DateTime::from_millis(from);
+    let to = DateTime::from_millis(to);
+    match repository
+        .aggregate_readings(device_uuid, feature_uuid, sensor_type, from, to, bucket, agg)
+        .await
+    {
+        Ok(buckets) => {
+            debug!(target: "app", "aggregate_history - found {} buckets", buckets.len());
+            let buckets: Vec<Value> = buckets
+                .iter()
+                .map(|bucket| {
+                    json!({
+                        // in json response, 'value' is always a f64 or null for empty buckets
+                        "value": bucket.value,
+                        "timestamp": bucket.timestamp.timestamp_millis(),
+                    })
+                })
+                .collect();
+            Ok(ApiResponse {
+                json: Value::Array(buckets),
+                code: Status::Ok.code,
+            })
+        }
+        Err(error) => {
+            error!(target: "app", "aggregate_history - error {:?}", error);
+            Err(ApiError::from(&error))
         }
     }
 }
+
+struct ListParams {
+    feature_name: Option<SensorType>,
+    sort_order: SortOrder,
+    limit: i64,
+    after: Option<(DateTime, ObjectId)>,
+}
+
+fn parse_list_query(query: &ListSensorsQuery) -> Result<ListParams, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    let feature_name = match query.feature_name.as_deref().map(SensorType::from_str) {
+        None => None,
+        Some(Ok(feature_name)) => Some(feature_name),
+        Some(Err(_)) => return Err(invalid_sensor_type()),
+    };
+    // cursor is defined as '<modifiedAt in milliseconds>_<_id>'
+    let after = match query.cursor.as_deref().map(|cursor| cursor.split_once('_')) {
+        None => Ok(None),
+        Some(Some((modified_at, id))) => match (modified_at.parse::<i64>(), ObjectId::from_str(id)) {
+            (Ok(modified_at), Ok(id)) => Ok(Some((DateTime::from_millis(modified_at), id))),
+            _ => Err(()),
+        },
+        Some(None) => Err(()),
+    };
+    match after {
+        Ok(after) if limit > 0 && limit <= MAX_LIST_LIMIT => Ok(ListParams {
+            feature_name,
+            sort_order: query.sort.unwrap_or(SortOrder::Desc),
+            limit,
+            after,
+        }),
+        _ => Err(ApiError::new(ErrorCode::InvalidCursor)),
+    }
+}
+
+/// Build a page of sensors from the result of a query limited to `limit` + 1 sensors,
+/// where the additional one is used only to know if there is a next page.
+fn sensors_page_response(result: Result<Vec<SensorSummary>, DbError>, limit: i64) -> Result<ApiResponse, ApiError> {
+    let mut sensors = match result {
+        Ok(sensors) => sensors,
+        Err(error) => {
+            error!(target: "app", "sensors_page_response - error {:?}", error);
+            return Err(ApiError::from(&error));
+        }
+    };
+    let has_next_page = sensors.len() as i64 > limit;
+    sensors.truncate(limit as usize);
+
+    let next_cursor: Option<String> = match sensors.last() {
+        Some(last) if has_next_page => Some(format!("{}_{}", last.modified_at.timestamp_millis(), last.id.to_hex())),
+        _ => None,
+    };
+    let sensors: Vec<Value> = sensors
+        .iter()
+        .map(|sensor| {
+            json!({
+                "deviceUuid": sensor.device_uuid,
+                "featureUuid": sensor.feature_uuid,
+                "featureName": sensor.feature_name,
+                // in json response, 'value' is always a f64, even if in db it's a i64
+                "value": sensor.value,
+                "createdAt": sensor.created_at.timestamp_millis(),
+                "modifiedAt": sensor.modified_at.timestamp_millis(),
+                "mac": sensor.mac,
+                "model": sensor.model,
+                "manufacturer": sensor.manufacturer,
+            })
+        })
+        .collect();
+    Ok(ApiResponse {
+        json: json!({
+            "sensors": sensors,
+            "nextCursor": next_cursor,
+        }),
+        code: Status::Ok.code,
+    })
+}
+
+async fn remove_sensors(
+    repository: &State<DynSensorRepository>,
+    token: &BearerToken,
+    device_uuid: &str,
+    feature: Option<(&str, SensorType)>,
+    purge: bool,
+) -> Result<ApiResponse, ApiError> {
+    debug!(target: "app", "remove_sensors - called with device_uuid = {}, feature = {:?}, purge = {}", device_uuid, feature, purge);
+    // sensors can be deleted only with the apiToken used to register them
+    let api_tokens = match repository.find_api_tokens(device_uuid, feature, purge).await {
+        Ok(api_tokens) => api_tokens,
+        Err(error) => {
+            error!(target: "app", "remove_sensors - cannot find sensors, error = {:?}", error);
+            return Err(ApiError::from(&error));
+        }
+    };
+    if api_tokens.is_empty() {
+        return Err(ApiError::new(ErrorCode::SensorNotFound));
+    }
+    if api_tokens.iter().any(|api_token| !token.matches(api_token)) {
+        error!(target: "app", "remove_sensors - apiToken doesn't match, device_uuid = {}", device_uuid);
+        return Err(ApiError::new(ErrorCode::Forbidden));
+    }
+    match repository.delete_sensors(device_uuid, feature, &token.0, purge).await {
+        Ok(deleted_count) => {
+            debug!(target: "app", "remove_sensors - deleted {} sensors", deleted_count);
+            Ok(ApiResponse {
+                json: json!({ "deleted": deleted_count }),
+                code: Status::Ok.code,
+            })
+        }
+        Err(error) => {
+            error!(target: "app", "remove_sensors - error = {:?}", error);
+            Err(ApiError::from(&error))
+        }
+    }
+}
+
+/// Start of the aggregate range, with 'from' aligned to the start of its bucket,
+/// or None if the range is empty, has too many buckets or overflows
+fn aggregate_range_start(from: i64, to: i64, bucket: Bucket) -> Option<i64> {
+    let from = from.checked_sub(from.rem_euclid(bucket.millis()))?;
+    let buckets = to.checked_sub(from)? / bucket.millis();
+    (from < to && buckets <= MAX_AGGREGATE_BUCKETS).then_some(from)
+}
+
+fn invalid_sensor_type() -> ApiError {
+    ApiError::new(ErrorCode::InvalidSensorType)
+}
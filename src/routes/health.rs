@@ -0,0 +1,86 @@
+use std::time::{Duration, Instant};
+
+use rocket::State;
+use rocket::http::Status;
+use rocket::serde::json::{Value, json};
+use rocket::tokio::time::timeout;
+use serde_json::Map;
+use tracing::{info, warn};
+
+use crate::config::Env;
+use crate::db::SetupFailure;
+use crate::db::repository::DynSensorRepository;
+use crate::errors::api_error::ApiResponse;
+use crate::models::health::{HealthCheck, HealthStatus};
+
+// max time to wait for the storage in the readiness probe, if HEALTH_TIMEOUT_MS is not defined
+const DEFAULT_HEALTH_TIMEOUT_MS: u64 = 2000;
+
+/// liveness probe, the server is running
+#[get("/health/live")]
+pub async fn get_health_live() -> ApiResponse {
+    ApiResponse {
+        json: json!({ "status": HealthStatus::Up }),
+        code: Status::Ok.code,
+    }
+}
+
+/// readiness probe, the storage answers and its schema is the expected one.
+/// It returns the status of every dependency, with 503 if any of them is down.
+#[get("/health/ready")]
+pub async fn get_health_ready(
+    repository: &State<DynSensorRepository>,
+    setup_failure: &State<SetupFailure>,
+    env: &State<Env>,
+) -> ApiResponse {
+    let max_wait = Duration::from_millis(env.health_timeout_ms.unwrap_or(DEFAULT_HEALTH_TIMEOUT_MS));
+
+    let mut checks: Vec<HealthCheck> = Vec::new();
+    // the storage cannot be ready until the server is restarted
+    if let Some(error) = setup_failure.get() {
+        checks.push(HealthCheck::down(
+            "setup",
+            format!("{}, restart the server after fixing it", error),
+        ));
+    }
+    let started_at = Instant::now();
+    let database_check = match timeout(max_wait, repository.ping()).await {
+        Ok(Ok(())) => HealthCheck::up("database").with_details(format!("{} ms", started_at.elapsed().as_millis())),
+        Ok(Err(err)) => HealthCheck::down("database", err.to_string()),
+        Err(_) => HealthCheck::down("database", format!("no answer in {} ms", max_wait.as_millis())),
+    };
+    let database_up = database_check.status == HealthStatus::Up;
+    checks.push(database_check);
+    // schema can be checked only if the database answers
+    if database_up {
+        match timeout(max_wait, repository.check_schema()).await {
+            Ok(schema_checks) => checks.extend(schema_checks),
+            Err(_) => checks.push(HealthCheck::down(
+                "schema",
+                format!("no answer in {} ms", max_wait.as_millis()),
+            )),
+        }
+    }
+
+    let ready = checks.iter().all(|check| check.status == HealthStatus::Up);
+    let checks_json: Map<String, Value> = checks
+        .iter()
+        .map(|check| (check.name.to_string(), json!(check)))
+        .collect();
+    if ready {
+        info!(target: "app", "REST - GET - get_health_ready - ready");
+    } else {
+        warn!(target: "app", "REST - GET - get_health_ready - degraded, checks = {:?}", checks);
+    }
+    ApiResponse {
+        json: json!({
+            "status": if ready { "ready" } else { "degraded" },
+            "checks": checks_json,
+        }),
+        code: if ready {
+            Status::Ok.code
+        } else {
+            Status::ServiceUnavailable.code
+        },
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use mongodb::bson::DateTime;
+use rocket::State;
+use rocket::http::ContentType;
+use tracing::error;
+
+use crate::config::Env;
+use crate::db::repository::DynSensorRepository;
+use crate::metrics::Metrics;
+
+// a sensor is stale if it has no new values for this time, if METRICS_STALE_AFTER_SECS is not defined
+const DEFAULT_STALE_AFTER_SECS: u64 = 60 * 60;
+
+/// metrics in Prometheus text format
+#[get("/metrics")]
+pub async fn get_metrics(
+    repository: &State<DynSensorRepository>,
+    metrics: &State<Arc<Metrics>>,
+    env: &State<Env>,
+) -> (ContentType, String) {
+    let stale_after_secs = env.metrics_stale_after_secs.unwrap_or(DEFAULT_STALE_AFTER_SECS);
+    let stale_before = DateTime::from_millis(DateTime::now().timestamp_millis() - (stale_after_secs as i64) * 1000);
+    // sensors gauges keep the previous values if the storage doesn't answer
+    match repository.sensors_stats(stale_before).await {
+        Ok(stats) => metrics.set_sensors_stats(&stats),
+        Err(err) => error!(target: "app", "REST - GET - get_metrics - cannot refresh sensors stats = {:?}", err),
+    }
+    (
+        ContentType::new("text", "plain").with_params([("version", "0.0.4"), ("charset", "utf-8")]),
+        metrics.encode(),
+    )
+}
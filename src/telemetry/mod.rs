@@ -0,0 +1,89 @@
+//! Distributed tracing of requests, exported to an OpenTelemetry collector.
+//!
+//! With the `otel` feature and `OTEL_EXPORTER_OTLP_ENDPOINT` defined, the `request` spans of
+//! `RequestIdFairing` and the spans of MongoDB operations are exported with OTLP over http.
+//! Requests with a W3C `traceparent` header continue the trace of the caller.
+//! Without the feature every function here does nothing.
+
+use rocket::Request;
+use rocket::http::{HeaderMap, Status};
+use tracing::Span;
+use tracing_subscriber::{Layer, Registry};
+
+use crate::config::Env;
+
+#[cfg(feature = "otel")]
+pub mod otlp;
+
+/// Layers exporting spans, empty if `OTEL_EXPORTER_OTLP_ENDPOINT` isn't defined.
+/// The tracer provider is kept for `shutdown`.
+#[cfg(feature = "otel")]
+pub fn layers(env: &Env) -> Result<Vec<Box<dyn Layer<Registry> + Send + Sync>>, String> {
+    if env.otel_exporter_otlp_endpoint.is_none() {
+        return Ok(Vec::new());
+    }
+    let provider = otlp::tracer_provider(env)?;
+    let layer = otlp::layer(&provider).boxed();
+    let _ = otlp::PROVIDER.set(provider);
+    Ok(vec![layer])
+}
+
+/// Layers exporting spans, always empty without the `otel` feature
+#[cfg(not(feature = "otel"))]
+pub fn layers(_env: &Env) -> Result<Vec<Box<dyn Layer<Registry> + Send + Sync>>, String> {
+    Ok(Vec::new())
+}
+
+/// Make `span` a child of the remote span in the `traceparent` header of a request, if any
+#[allow(unused_variables)]
+pub fn set_remote_parent(span: &Span, headers: &HeaderMap<'_>) {
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::propagation::TextMapPropagator;
+        use opentelemetry_sdk::propagation::TraceContextPropagator;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        if headers.contains(otlp::TRACEPARENT_HEADER) {
+            let cx = TraceContextPropagator::new().extract(&otlp::HeaderExtractor(headers));
+            // it fails only if the span is disabled
+            let _ = span.set_parent(cx);
+        }
+    }
+}
+
+/// Record the route and the response status of `req` in its `span`, as OpenTelemetry http attributes
+#[allow(unused_variables)]
+pub fn record_response(span: &Span, req: &Request<'_>, status: Status) {
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::trace::TraceContextExt;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let route = req.route().map(|route| route.uri.path().to_string());
+        let name = match &route {
+            Some(route) => format!("{} {}", req.method(), route),
+            None => req.method().to_string(),
+        };
+        // `otel.name` can't rename a span once started
+        span.context().span().update_name(name);
+        if status.code >= 500 {
+            span.record("otel.status_code", "error");
+        }
+        span.set_attribute("http.request.method", req.method().as_str());
+        span.set_attribute("url.path", req.uri().path().to_string());
+        if let Some(route) = route {
+            span.set_attribute("http.route", route);
+        }
+        span.set_attribute("http.response.status_code", i64::from(status.code));
+    }
+}
+
+/// Export the spans not yet sent, before the server exits
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    if let Some(provider) = otlp::PROVIDER.get() {
+        // the export blocks, so it runs out of the async runtime
+        let provider = provider.clone();
+        let _ = std::thread::spawn(move || provider.shutdown()).join();
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::OnceLock;
+
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::{Sampler, SdkTracer, SdkTracerProvider};
+use rocket::http::HeaderMap;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::config::{Env, OtlpProtocol};
+
+/// Header of the W3C trace context
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+// name of the service if `OTEL_SERVICE_NAME` isn't defined
+const DEFAULT_SERVICE_NAME: &str = "register";
+
+// provider of the global subscriber, flushed by `shutdown`
+pub(super) static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+/// Provider of tracers exporting spans in batches to `OTEL_EXPORTER_OTLP_ENDPOINT`.
+/// Traces started by callers are sampled like the callers did, the other ones with
+/// the ratio `OTEL_TRACES_SAMPLER_ARG`.
+pub fn tracer_provider(env: &Env) -> Result<SdkTracerProvider, String> {
+    let endpoint = env.otel_exporter_otlp_endpoint.clone().unwrap_or_default();
+    let protocol = match env.otel_exporter_otlp_protocol {
+        OtlpProtocol::HttpProtobuf => Protocol::HttpBinary,
+        OtlpProtocol::HttpJson => Protocol::HttpJson,
+    };
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_protocol(protocol)
+        .with_endpoint(format!("{}/v1/traces", endpoint.expose().trim_end_matches('/')))
+        .build()
+        .map_err(|err| format!("OTEL_EXPORTER_OTLP_ENDPOINT = {}: {}", endpoint, err))?;
+    let ratio = env.otel_traces_sampler_arg.unwrap_or(1.0);
+    let service_name = env.otel_service_name.as_deref().unwrap_or(DEFAULT_SERVICE_NAME);
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(ratio))))
+        .with_resource(Resource::builder().with_service_name(service_name.to_string()).build())
+        .build())
+}
+
+/// Layer exporting the spans of a subscriber with a tracer of `provider`
+pub fn layer<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, SdkTracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(DEFAULT_SERVICE_NAME))
+}
+
+// headers of a request, read by the propagator of the trace context
+pub(super) struct HeaderExtractor<'a, 'h>(pub &'a HeaderMap<'h>);
+
+impl opentelemetry::propagation::Extractor for HeaderExtractor<'_, '_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get_one(key)
+    }
+
+    // names of headers can't be borrowed from `HeaderMap`, the W3C propagator reads only known keys
+    fn keys(&self) -> Vec<&str> {
+        Vec::new()
+    }
+}
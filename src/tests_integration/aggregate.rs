@@ -0,0 +1,247 @@
+use std::env;
+use std::time::Duration;
+
+use super::rocket;
+use mongodb::Database;
+use mongodb::bson::Bson;
+use rocket::http::Status;
+use rocket::local::asynchronous::{Client, LocalResponse};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use register::errors::api_error::ErrorCode;
+
+use crate::tests_integration::backends::backend_client;
+use crate::tests_integration::db_utils::{connect, drop_all_collections, find_readings_collection, insert_reading};
+use crate::tests_integration::test_utils::{API_TOKEN, bearer, problem, register_sensor};
+
+// 2024-01-01T00:00:00Z
+const START: i64 = 1704067200000;
+const MINUTE: i64 = 60 * 1000;
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_sensor_aggregate() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "temperature";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+    // readings in minutes 0, 1 and 3, nothing in minutes 2 and 4
+    for (timestamp, value) in [
+        (START, 20.0),
+        (START + 30 * 1000, 22.0),
+        (START + 90 * 1000, 25.5),
+        (START + 200 * 1000, 18.0),
+        (START + 210 * 1000, 19.0),
+    ] {
+        insert_reading(
+            &db,
+            &device_uuid,
+            &feature_uuid,
+            sensor_type,
+            timestamp,
+            Bson::Double(value),
+        )
+        .await
+        .unwrap();
+    }
+    let aggregate_url = format!(
+        "/sensors/{}/features/{}/{}/aggregate",
+        device_uuid, feature_uuid, sensor_type
+    );
+
+    // run tests for every aggregation function
+    let expected_values: Vec<(&str, Vec<Value>)> = vec![
+        (
+            "avg",
+            vec![json!(21.0), json!(25.5), json!(null), json!(18.5), json!(null)],
+        ),
+        (
+            "min",
+            vec![json!(20.0), json!(25.5), json!(null), json!(18.0), json!(null)],
+        ),
+        (
+            "max",
+            vec![json!(22.0), json!(25.5), json!(null), json!(19.0), json!(null)],
+        ),
+        (
+            "last",
+            vec![json!(22.0), json!(25.5), json!(null), json!(19.0), json!(null)],
+        ),
+        (
+            "count",
+            vec![json!(2.0), json!(1.0), json!(0.0), json!(2.0), json!(0.0)],
+        ),
+    ];
+    for (agg, values) in expected_values {
+        // test api
+        let res: LocalResponse = client
+            .get(format!(
+                "{}?bucket=1m&agg={}&from={}&to={}",
+                aggregate_url,
+                agg,
+                START,
+                START + 5 * MINUTE
+            ))
+            .header(bearer(API_TOKEN))
+            .dispatch()
+            .await;
+
+        // check results
+        assert_eq!(res.status(), Status::Ok);
+        let expected: Vec<Value> = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| json!({ "value": value, "timestamp": START + i as i64 * MINUTE }))
+            .collect();
+        assert_eq!(res.into_json::<Vec<Value>>().await.unwrap(), expected);
+    }
+
+    // bigger buckets, with 'from' not aligned to the bucket size
+    let res: LocalResponse = client
+        .get(format!(
+            "{}?bucket=5m&agg=count&from={}&to={}",
+            aggregate_url,
+            START + MINUTE,
+            START + 10 * MINUTE
+        ))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(
+        res.into_json::<Vec<Value>>().await.unwrap(),
+        vec![
+            json!({ "value": 5.0, "timestamp": START }),
+            json!({ "value": 0.0, "timestamp": START + 5 * MINUTE }),
+        ]
+    );
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_sensor_aggregate_errors() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "humidity";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+    let aggregate_url = format!(
+        "/sensors/{}/features/{}/{}/aggregate",
+        device_uuid, feature_uuid, sensor_type
+    );
+
+    // unknown bucket or aggregation function
+    for query in ["bucket=2m", "agg=median"] {
+        let res: LocalResponse = client
+            .get(format!("{}?{}", aggregate_url, query))
+            .header(bearer(API_TOKEN))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+    }
+    // empty range
+    let res: LocalResponse = client
+        .get(format!("{}?from={}&to={}", aggregate_url, START, START))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::BadRequest);
+    // too many buckets
+    let res: LocalResponse = client
+        .get(format!(
+            "{}?bucket=1m&from={}&to={}",
+            aggregate_url,
+            START,
+            START + 10001 * MINUTE
+        ))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::BadRequest);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_sensor_aggregate_overflow_error() {
+    // init
+    let client: Client = backend_client("memory", None).await;
+
+    // inputs
+    let sensor_type = "temperature";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+    let aggregate_url = format!(
+        "/sensors/{}/features/{}/{}/aggregate",
+        device_uuid, feature_uuid, sensor_type
+    );
+
+    // ranges wider than i64, or starting in a bucket before i64::MIN
+    for query in [
+        format!(
+            "bucket=1d&from={}&to={}",
+            -9000000000000000000_i64, 9000000000000000000_i64
+        ),
+        format!("bucket=1d&from={}&to=0", i64::MIN),
+        format!("bucket=1d&to={}", i64::MIN + 1000),
+    ] {
+        let res: LocalResponse = client
+            .get(format!("{}?{}", aggregate_url, query))
+            .header(bearer(API_TOKEN))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::BadRequest, "{}", query);
+        assert_eq!(problem(res).await.code, ErrorCode::InvalidRange);
+    }
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn readings_retention() {
+    // init
+    let db: Database = connect().await.unwrap();
+
+    // SAFETY: integration tests run on a single thread (`--test-threads 1`)
+    unsafe { env::set_var("READINGS_RETENTION_DAYS", "30") };
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    unsafe { env::remove_var("READINGS_RETENTION_DAYS") };
+    let collection = find_readings_collection(&db).await.unwrap().unwrap();
+    assert!(collection.options.timeseries.is_some());
+    assert_eq!(
+        collection.options.expire_after_seconds,
+        Some(Duration::from_secs(30 * 24 * 60 * 60))
+    );
+    drop(client);
+
+    // without retention, readings are kept forever
+    let _client: Client = Client::tracked(rocket()).await.unwrap();
+    let collection = find_readings_collection(&db).await.unwrap().unwrap();
+    assert_eq!(collection.options.expire_after_seconds, None);
+}
@@ -0,0 +1,101 @@
+use mongodb::bson::oid::ObjectId;
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use uuid::Uuid;
+
+use register::errors::api_error::{ApiError, ErrorCode, Problem};
+use register::errors::db_error::DbError;
+
+use crate::tests_integration::retry::unreachable_postgres_client;
+use crate::tests_integration::test_utils::{API_TOKEN, bearer, build_register_input, get_random_mac, problem};
+
+#[test]
+fn db_errors_status() {
+    let cases = [
+        (DbError::Connection(String::from("timeout")), Status::ServiceUnavailable),
+        (DbError::DuplicateKey(String::from("id")), Status::Conflict),
+        (DbError::NotFound(String::from("sensor")), Status::NotFound),
+        (DbError::Forbidden(String::from("apiToken")), Status::Forbidden),
+        (
+            DbError::MalformedDocument(String::from("value")),
+            Status::InternalServerError,
+        ),
+        (
+            DbError::Validation(String::from("profileOwnerId")),
+            Status::UnprocessableEntity,
+        ),
+        (DbError::Query(String::from("syntax")), Status::InternalServerError),
+    ];
+    for (error, status) in cases {
+        assert_eq!(error.status(), status);
+        assert_eq!(ApiError::from(&error).status(), status);
+    }
+}
+
+#[test]
+fn db_errors_messages() {
+    let error = DbError::Validation(String::from("profileOwnerId: invalid"));
+    assert_eq!(error.to_string(), "validation error: profileOwnerId: invalid");
+    assert_eq!(ApiError::from(&error).message, "Invalid input: profileOwnerId: invalid");
+    // details of database failures are not returned to clients
+    let error = DbError::MalformedDocument(String::from("sensor = {value: \"abc\"}"));
+    assert_eq!(ApiError::from(&error).message, "Internal server error");
+    let error = DbError::Connection(String::from("connection refused"));
+    assert_eq!(ApiError::from(&error).message, "Database unavailable");
+}
+
+#[test]
+fn db_errors_from_sqlx() {
+    assert!(matches!(
+        DbError::from(sqlx::Error::PoolTimedOut),
+        DbError::Connection(_)
+    ));
+    assert!(matches!(DbError::from(sqlx::Error::RowNotFound), DbError::NotFound(_)));
+    assert!(matches!(
+        DbError::from(sqlx::Error::ColumnNotFound(String::from("value"))),
+        DbError::MalformedDocument(_)
+    ));
+    assert!(matches!(
+        DbError::from(sqlx::Error::Protocol(String::from("unexpected message"))),
+        DbError::Query(_)
+    ));
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn database_unavailable() {
+    let client: Client = unreachable_postgres_client(true).await.unwrap();
+
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+
+    // storage errors of routes
+    let register_body = build_register_input(
+        &ObjectId::new().to_hex(),
+        &device_uuid,
+        &get_random_mac(),
+        &feature_uuid,
+    );
+    let res: LocalResponse = client
+        .post("/sensors/register/temperature")
+        .header(ContentType::JSON)
+        .body(register_body)
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::ServiceUnavailable);
+    let body: Problem = problem(res).await;
+    assert_eq!(body.code, ErrorCode::DatabaseUnavailable);
+    assert_eq!(body.detail, "Database unavailable");
+
+    // storage errors of auth guards
+    let res: LocalResponse = client
+        .get(format!(
+            "/sensors/{}/features/{}/temperature",
+            device_uuid, feature_uuid
+        ))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::ServiceUnavailable);
+    assert_eq!(problem(res).await.code, ErrorCode::ServiceUnavailable);
+}
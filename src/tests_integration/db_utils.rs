@@ -1,12 +1,16 @@
 use std::env;
+use std::str::FromStr;
 
-use mongodb::bson::{Bson, Document, doc};
+use futures::TryStreamExt;
+use mongodb::bson::{Bson, DateTime, Document, doc};
 use mongodb::options::ClientOptions;
-use mongodb::{Client, Database};
+use mongodb::results::CollectionSpecification;
+use mongodb::{Client, Database, IndexModel};
 use rocket::serde::json::Json as RocketJson;
 
 use register::models::inputs::RegisterInput;
 use register::models::sensor::{FloatSensor, IntSensor, new_from_register_input};
+use register::models::sensor_type::{SensorType, ValueKind};
 
 pub async fn connect() -> mongodb::error::Result<Database> {
     let mongo_uri = env::var("MONGO_URI").expect("MONGO_URI is not found.");
@@ -27,6 +31,69 @@ pub async fn drop_all_collections(db: &Database) -> () {
         .drop()
         .await
         .expect("drop 'sensors' collection");
+    db.collection::<Document>("migrations")
+        .drop()
+        .await
+        .expect("drop 'migrations' collection");
+    // don't drop 'readings', because it's a time-series collection created at startup
+    db.collection::<Document>("readings")
+        .delete_many(doc! {})
+        .await
+        .expect("delete all 'readings'");
+}
+
+pub async fn insert_reading(
+    db: &Database,
+    device_uuid: &String,
+    feature_uuid: &String,
+    sensor_type: &str,
+    timestamp: i64,
+    value: Bson,
+) -> mongodb::error::Result<()> {
+    let collection = db.collection::<Document>("readings");
+    let reading = doc! {
+        "timestamp": DateTime::from_millis(timestamp),
+        "meta": {
+            "deviceUuid": device_uuid,
+            "featureUuid": feature_uuid,
+            "featureName": sensor_type,
+        },
+        "value": value,
+    };
+    collection.insert_one(reading).await.map(|_| ())
+}
+
+pub async fn find_readings_collection(db: &Database) -> mongodb::error::Result<Option<CollectionSpecification>> {
+    let mut collections: Vec<CollectionSpecification> = db
+        .list_collections()
+        .filter(doc! {"name": "readings"})
+        .await?
+        .try_collect()
+        .await?;
+    Ok(collections.pop())
+}
+
+pub async fn count_readings_by_uuid(
+    db: &Database,
+    device_uuid: &String,
+    feature_uuid: &String,
+    sensor_type: &str,
+) -> mongodb::error::Result<u64> {
+    let collection = db.collection::<Document>("readings");
+    let filter = doc! {
+        "meta.deviceUuid": device_uuid,
+        "meta.featureUuid": feature_uuid,
+        "meta.featureName": sensor_type,
+    };
+    collection.count_documents(filter).await
+}
+
+pub async fn find_sensors_indexes(db: &Database) -> mongodb::error::Result<Vec<IndexModel>> {
+    db.collection::<Document>("sensors")
+        .list_indexes()
+        .await?
+        .try_collect()
+        .await
 }
 
 pub async fn find_sensor_by_uuid(
@@ -44,18 +111,31 @@ pub async fn find_sensor_by_uuid(
     collection.find_one(filter).await
 }
 
+pub async fn count_sensors_by_uuid(
+    db: &Database,
+    device_uuid: &String,
+    feature_uuid: &String,
+    sensor_type: &str,
+) -> mongodb::error::Result<u64> {
+    let collection = db.collection::<Document>("sensors");
+    let filter = doc! {
+        "deviceUuid": device_uuid,
+        "featureUuid": feature_uuid,
+        "featureName": sensor_type,
+    };
+    collection.count_documents(filter).await
+}
+
 pub async fn insert_sensor(
     db: &Database,
     input: RocketJson<RegisterInput>,
     sensor_type: &str,
 ) -> mongodb::error::Result<String> {
     let collection = db.collection::<Document>("sensors");
-    let serialized_data: Bson = match sensor_type {
-        "temperature" | "humidity" | "light" => new_from_register_input::<FloatSensor>(input, sensor_type).unwrap(),
-        "motion" | "airquality" | "airpressure" => new_from_register_input::<IntSensor>(input, sensor_type).unwrap(),
-        _ => {
-            panic!("Unknown type")
-        }
+    let sensor_type = SensorType::from_str(sensor_type).expect("Unknown type");
+    let serialized_data: Bson = match sensor_type.kind() {
+        ValueKind::Float => new_from_register_input::<FloatSensor>(&input, sensor_type).unwrap(),
+        ValueKind::Int => new_from_register_input::<IntSensor>(&input, sensor_type).unwrap(),
     };
     let document = serialized_data.as_document().unwrap();
     let insert_one_result = collection.insert_one(document.to_owned()).await?;
@@ -0,0 +1,274 @@
+use super::rocket;
+use mongodb::Database;
+use rocket::http::Status;
+use rocket::local::asynchronous::{Client, LocalRequest, LocalResponse};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use register::models::sensor_type::SensorType;
+
+use crate::tests_integration::db_utils::{connect, drop_all_collections, find_sensor_by_uuid};
+use crate::tests_integration::test_utils::{API_TOKEN, bearer, register_sensor};
+
+#[rocket::async_test]
+#[test_log::test]
+async fn delete_sensor() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    for sensor_type in SensorType::all().map(SensorType::as_str) {
+        // inputs
+        let device_uuid: String = Uuid::new_v4().to_string();
+        let feature_uuid: String = Uuid::new_v4().to_string();
+        assert_eq!(
+            register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+            Status::Created
+        );
+
+        // test api
+        let req: LocalRequest = client
+            .delete(format!(
+                "/sensors/{}/features/{}/{}",
+                device_uuid, feature_uuid, sensor_type
+            ))
+            .header(bearer(API_TOKEN));
+        let res: LocalResponse = req.dispatch().await;
+
+        // check results
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.into_json::<Value>().await.unwrap(), json!({ "deleted": 1 }));
+        // sensor is still in db, but soft-deleted
+        let document = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(document.get_datetime("deletedAt").is_ok());
+        // and it's hidden
+        let req: LocalRequest = client
+            .get(format!(
+                "/sensors/{}/features/{}/{}",
+                device_uuid, feature_uuid, sensor_type
+            ))
+            .header(bearer(API_TOKEN));
+        let res: LocalResponse = req.dispatch().await;
+        assert_eq!(res.status(), Status::NotFound);
+    }
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn purge_sensor() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    for sensor_type in SensorType::all().map(SensorType::as_str) {
+        // inputs
+        let device_uuid: String = Uuid::new_v4().to_string();
+        let feature_uuid: String = Uuid::new_v4().to_string();
+        assert_eq!(
+            register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+            Status::Created
+        );
+
+        // test api
+        let req: LocalRequest = client
+            .delete(format!(
+                "/sensors/{}/features/{}/{}?purge=true",
+                device_uuid, feature_uuid, sensor_type
+            ))
+            .header(bearer(API_TOKEN));
+        let res: LocalResponse = req.dispatch().await;
+
+        // check results
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.into_json::<Value>().await.unwrap(), json!({ "deleted": 1 }));
+        let document = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+            .await
+            .unwrap();
+        assert!(document.is_none());
+    }
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn delete_and_purge_device_sensors() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let mut feature_uuids: Vec<(String, &str)> = Vec::new();
+    for sensor_type in SensorType::all().map(SensorType::as_str) {
+        let feature_uuid: String = Uuid::new_v4().to_string();
+        assert_eq!(
+            register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+            Status::Created
+        );
+        feature_uuids.push((feature_uuid, sensor_type));
+    }
+    let sensors_count = SensorType::all().count();
+
+    // soft-delete all sensors of the device
+    let req: LocalRequest = client
+        .delete(format!("/sensors/{}", device_uuid))
+        .header(bearer(API_TOKEN));
+    let res: LocalResponse = req.dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(
+        res.into_json::<Value>().await.unwrap(),
+        json!({ "deleted": sensors_count })
+    );
+    for (feature_uuid, sensor_type) in feature_uuids.iter() {
+        let document = find_sensor_by_uuid(&db, &device_uuid, feature_uuid, sensor_type)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(document.get_datetime("deletedAt").is_ok());
+    }
+
+    // soft-deleted sensors cannot be deleted again
+    let req: LocalRequest = client
+        .delete(format!("/sensors/{}", device_uuid))
+        .header(bearer(API_TOKEN));
+    let res: LocalResponse = req.dispatch().await;
+    assert_eq!(res.status(), Status::NotFound);
+
+    // but they can be purged
+    let req: LocalRequest = client
+        .delete(format!("/sensors/{}?purge=true", device_uuid))
+        .header(bearer(API_TOKEN));
+    let res: LocalResponse = req.dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(
+        res.into_json::<Value>().await.unwrap(),
+        json!({ "deleted": sensors_count })
+    );
+    for (feature_uuid, sensor_type) in feature_uuids.iter() {
+        let document = find_sensor_by_uuid(&db, &device_uuid, feature_uuid, sensor_type)
+            .await
+            .unwrap();
+        assert!(document.is_none());
+    }
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn register_deleted_sensor_again() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "temperature";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+    let req: LocalRequest = client
+        .delete(format!(
+            "/sensors/{}/features/{}/{}",
+            device_uuid, feature_uuid, sensor_type
+        ))
+        .header(bearer(API_TOKEN));
+    assert_eq!(req.dispatch().await.status(), Status::Ok);
+
+    // test api
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Ok
+    );
+
+    // check results
+    let document = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(document.get("deletedAt").is_none());
+    let req: LocalRequest = client
+        .get(format!(
+            "/sensors/{}/features/{}/{}",
+            device_uuid, feature_uuid, sensor_type
+        ))
+        .header(bearer(API_TOKEN));
+    assert_eq!(req.dispatch().await.status(), Status::Ok);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn delete_sensor_errors() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "temperature";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+    let sensor_url = format!("/sensors/{}/features/{}/{}", device_uuid, feature_uuid, sensor_type);
+
+    // missing token
+    let res: LocalResponse = client.delete(sensor_url.clone()).dispatch().await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    // wrong token
+    let res: LocalResponse = client
+        .delete(sensor_url.clone())
+        .header(bearer("2e8b2bd8-1d5f-4c3a-9b8e-0f6d7c5a4b3e"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Forbidden);
+    // unknown sensor
+    let res: LocalResponse = client
+        .delete(format!(
+            "/sensors/{}/features/{}/{}",
+            device_uuid,
+            Uuid::new_v4(),
+            sensor_type
+        ))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::NotFound);
+    // wrong sensor type
+    let res: LocalResponse = client
+        .delete(format!("/sensors/{}/features/{}/unknown", device_uuid, feature_uuid))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::BadRequest);
+
+    // the sensor is still there
+    let document = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(document.get("deletedAt").is_none());
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
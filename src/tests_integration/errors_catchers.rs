@@ -1,7 +1,12 @@
 use super::rocket;
-use rocket::http::{ContentType, Status};
+use rocket::http::{ContentType, Header, Status};
 use rocket::local::asynchronous::{Client, LocalRequest, LocalResponse};
 
+use register::errors::api_error::{ErrorCode, Problem};
+
+use crate::tests_integration::backends::backend_client;
+use crate::tests_integration::test_utils::{API_TOKEN, bearer, build_register_input, get_random_mac, problem};
+
 #[rocket::async_test]
 #[test_log::test]
 async fn error_catcher_not_found() {
@@ -10,7 +15,9 @@ async fn error_catcher_not_found() {
     let req: LocalRequest = client.get("/unknownpath");
     let res: LocalResponse = req.dispatch().await;
     assert_eq!(res.status(), Status::NotFound);
-    assert_eq!(res.into_string().await.unwrap(), String::from("Not found"));
+    let body: Problem = problem(res).await;
+    assert_eq!(body.code, ErrorCode::NotFound);
+    assert_eq!(body.title, "Not found");
 }
 
 #[rocket::async_test]
@@ -24,5 +31,89 @@ async fn error_catcher_bad_request() {
         .body("bad-input");
     let res: LocalResponse = req.dispatch().await;
     assert_eq!(res.status(), Status::BadRequest);
-    assert_eq!(res.into_string().await.unwrap(), String::from("Bad request"));
+    assert_eq!(problem(res).await.code, ErrorCode::BadRequest);
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn error_catcher_unauthorized() {
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+
+    let req: LocalRequest = client.delete("/sensors/b5a3f0b4-9e8f-4d1e-8f0a-3c2b1d0e9f8a");
+    let res: LocalResponse = req.dispatch().await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    assert_eq!(problem(res).await.code, ErrorCode::Unauthorized);
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn error_problem_details() {
+    let client: Client = backend_client("memory", None).await;
+
+    let res: LocalResponse = client
+        .get("/unknown/path?query=1")
+        .header(Header::new("X-Request-Id", "test-request-id"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::NotFound);
+    assert_eq!(
+        problem(res).await,
+        Problem {
+            problem_type: String::from("urn:register:error:not_found"),
+            title: String::from("Not found"),
+            status: 404,
+            detail: String::from("Not found"),
+            instance: String::from("/unknown/path"),
+            code: ErrorCode::NotFound,
+            errors: Vec::new(),
+            request_id: Some(String::from("test-request-id")),
+        }
+    );
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn error_problem_of_routes() {
+    let client: Client = backend_client("memory", None).await;
+
+    // inputs
+    let device_uuid = "b5a3f0b4-9e8f-4d1e-8f0a-3c2b1d0e9f8a";
+    let feature_uuid = "f3c8e1a2-5b7d-4e9f-8a6c-2d1b0e9f8a7c";
+    let register_body = build_register_input("63963ce7c7fd6d463c6c77a3", device_uuid, &get_random_mac(), feature_uuid);
+
+    // unknown sensor type
+    let res: LocalResponse = client
+        .post("/sensors/register/unknown")
+        .header(ContentType::JSON)
+        .body(register_body.clone())
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::BadRequest);
+    let body: Problem = problem(res).await;
+    assert_eq!(body.code, ErrorCode::InvalidSensorType);
+    assert_eq!(body.title, "Invalid sensor type");
+
+    // invalid history range
+    let res: LocalResponse = client
+        .post("/sensors/register/temperature")
+        .header(ContentType::JSON)
+        .body(register_body)
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Created);
+    let history_url = format!("/sensors/{}/features/{}/temperature/history", device_uuid, feature_uuid);
+    let res: LocalResponse = client
+        .get(format!("{}?from=2000&to=1000", history_url))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::BadRequest);
+    let body: Problem = problem(res).await;
+    assert_eq!(body.code, ErrorCode::InvalidRange);
+    assert_eq!(body.title, "Invalid range");
+    assert_eq!(body.detail, "Invalid history range or limit");
+    assert_eq!(body.status, 400);
+    assert_eq!(body.instance, history_url);
+    // generated if not sent by the client
+    assert!(body.request_id.is_some());
 }
@@ -0,0 +1,126 @@
+use super::rocket;
+use mongodb::Database;
+use mongodb::bson::Document;
+use rocket::http::Status;
+use rocket::local::asynchronous::{Client, LocalResponse};
+use serde_json::{Value, json};
+use sqlx::sqlite::SqlitePool;
+
+use crate::tests_integration::backends::{backend_client, remove_sqlite_files, temp_sqlite_path};
+use crate::tests_integration::db_utils::{connect, drop_all_collections};
+
+#[rocket::async_test]
+#[test_log::test]
+async fn health_live() {
+    let client: Client = backend_client("memory", None).await;
+
+    let res: LocalResponse = client.get("/health/live").dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.into_json::<Value>().await.unwrap(), json!({ "status": "up" }));
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn health_ready() {
+    // init
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+
+    // test api
+    let res: LocalResponse = client.get("/health/ready").dispatch().await;
+
+    // check results
+    assert_eq!(res.status(), Status::Ok);
+    let body = res.into_json::<Value>().await.unwrap();
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert_eq!(body["checks"]["indexes"], json!({ "status": "up" }));
+    assert_eq!(body["checks"]["migrations"], json!({ "status": "up" }));
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn health_ready_missing_index() {
+    // init
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    db.collection::<Document>("sensors")
+        .drop_index("mac_1")
+        .await
+        .expect("drop 'mac_1' index");
+
+    // test api
+    let res: LocalResponse = client.get("/health/ready").dispatch().await;
+
+    // check results
+    assert_eq!(res.status(), Status::ServiceUnavailable);
+    let body = res.into_json::<Value>().await.unwrap();
+    assert_eq!(body["status"], "degraded");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert_eq!(
+        body["checks"]["indexes"],
+        json!({ "status": "down", "details": "missing indexes: mac_1" })
+    );
+    assert_eq!(body["checks"]["migrations"], json!({ "status": "up" }));
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn health_ready_memory() {
+    let client: Client = backend_client("memory", None).await;
+
+    let res: LocalResponse = client.get("/health/ready").dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let body = res.into_json::<Value>().await.unwrap();
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"].as_object().unwrap().len(), 1);
+    assert_eq!(body["checks"]["database"]["status"], "up");
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn health_ready_pending_sqlite_migrations() {
+    // init
+    let sqlite_path = temp_sqlite_path();
+    let client: Client = backend_client("sqlite", Some(&sqlite_path)).await;
+
+    let res: LocalResponse = client.get("/health/ready").dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let body = res.into_json::<Value>().await.unwrap();
+    assert_eq!(body["checks"]["migrations"], json!({ "status": "up" }));
+
+    // forget the applied migration
+    let pool = SqlitePool::connect(&format!("sqlite://{}", sqlite_path.display()))
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM _sqlx_migrations")
+        .execute(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+
+    // test api
+    let res: LocalResponse = client.get("/health/ready").dispatch().await;
+
+    // check results
+    assert_eq!(res.status(), Status::ServiceUnavailable);
+    let body = res.into_json::<Value>().await.unwrap();
+    assert_eq!(body["status"], "degraded");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert_eq!(
+        body["checks"]["migrations"],
+        json!({ "status": "down", "details": "pending migrations: create sensors and readings" })
+    );
+
+    // cleanup
+    drop(client);
+    remove_sqlite_files(&sqlite_path);
+}
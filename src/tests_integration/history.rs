@@ -0,0 +1,289 @@
+use std::time::Duration;
+
+use super::rocket;
+use mongodb::Database;
+use mongodb::bson::{Bson, DateTime};
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::{Client, LocalRequest, LocalResponse};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use register::errors::api_error::ErrorCode;
+
+use crate::tests_integration::backends::backend_client;
+use crate::tests_integration::db_utils::{connect, count_readings_by_uuid, drop_all_collections, insert_reading};
+use crate::tests_integration::test_utils::{API_TOKEN, bearer, problem, register_sensor};
+
+async fn put_values(client: &Client, device_uuid: &str, feature_uuid: &str, sensor_type: &str, values: &[Value]) {
+    for value in values.iter() {
+        let req: LocalRequest = client
+            .put(format!(
+                "/sensors/{}/features/{}/{}/value",
+                device_uuid, feature_uuid, sensor_type
+            ))
+            .header(ContentType::JSON)
+            .header(bearer(API_TOKEN))
+            .body(json!({ "value": value }).to_string());
+        assert_eq!(req.dispatch().await.status(), Status::Ok);
+        // readings must have different timestamps
+        rocket::tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_sensor_history() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    for (sensor_type, values) in [
+        ("temperature", vec![json!(21.5), json!(22), json!(22.75)]),
+        ("motion", vec![json!(0), json!(1), json!(0)]),
+    ] {
+        // inputs
+        let device_uuid: String = Uuid::new_v4().to_string();
+        let feature_uuid: String = Uuid::new_v4().to_string();
+        assert_eq!(
+            register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+            Status::Created
+        );
+        put_values(&client, &device_uuid, &feature_uuid, sensor_type, &values).await;
+        let history_url = format!(
+            "/sensors/{}/features/{}/{}/history",
+            device_uuid, feature_uuid, sensor_type
+        );
+
+        // test api
+        let res: LocalResponse = client
+            .get(history_url.clone())
+            .header(bearer(API_TOKEN))
+            .dispatch()
+            .await;
+
+        // check results
+        assert_eq!(res.status(), Status::Ok);
+        let readings: Vec<Value> = res.into_json::<Vec<Value>>().await.unwrap();
+        assert_eq!(readings.len(), values.len());
+        for (reading, value) in readings.iter().zip(values.iter()) {
+            assert_eq!(reading["value"].as_f64().unwrap(), value.as_f64().unwrap());
+        }
+        let timestamps: Vec<i64> = readings
+            .iter()
+            .map(|reading| reading["timestamp"].as_i64().unwrap())
+            .collect();
+        assert!(timestamps.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // filter by time range
+        let res: LocalResponse = client
+            .get(format!("{}?from={}&to={}", history_url, timestamps[1], timestamps[2]))
+            .header(bearer(API_TOKEN))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.into_json::<Vec<Value>>().await.unwrap(), readings[1..].to_vec());
+
+        // limit
+        let res: LocalResponse = client
+            .get(format!("{}?limit=1", history_url))
+            .header(bearer(API_TOKEN))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.into_json::<Vec<Value>>().await.unwrap(), readings[..1].to_vec());
+    }
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_sensor_history_errors() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "humidity";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+    let history_url = format!(
+        "/sensors/{}/features/{}/{}/history",
+        device_uuid, feature_uuid, sensor_type
+    );
+
+    // invalid limit
+    for limit in [0, 10001] {
+        let res: LocalResponse = client
+            .get(format!("{}?limit={}", history_url, limit))
+            .header(bearer(API_TOKEN))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+    // from after to
+    let res: LocalResponse = client
+        .get(format!("{}?from=2000&to=1000", history_url))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::BadRequest);
+    // missing token
+    let res: LocalResponse = client.get(history_url).dispatch().await;
+    assert_eq!(res.status(), Status::Unauthorized);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_sensor_history_oldest_range() {
+    // init
+    let client: Client = backend_client("memory", None).await;
+
+    // inputs
+    let sensor_type = "humidity";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+
+    // test api, the default 'from' is before the oldest timestamp
+    let res: LocalResponse = client
+        .get(format!(
+            "/sensors/{}/features/{}/{}/history?to={}",
+            device_uuid,
+            feature_uuid,
+            sensor_type,
+            i64::MIN + 1000
+        ))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+
+    // check results
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.into_json::<Value>().await.unwrap(), json!([]));
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_sensor_history_malformed_reading() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "temperature";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+    put_values(&client, &device_uuid, &feature_uuid, sensor_type, &[json!(21.5)]).await;
+    insert_reading(
+        &db,
+        &device_uuid,
+        &feature_uuid,
+        sensor_type,
+        DateTime::now().timestamp_millis(),
+        Bson::String(String::from("abc")),
+    )
+    .await
+    .unwrap();
+
+    // test api
+    let res: LocalResponse = client
+        .get(format!(
+            "/sensors/{}/features/{}/{}/history",
+            device_uuid, feature_uuid, sensor_type
+        ))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+
+    // check results, a page without the malformed reading would be shorter than expected
+    assert_eq!(res.status(), Status::InternalServerError);
+    assert_eq!(problem(res).await.code, ErrorCode::InternalError);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn purge_sensor_history() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "light";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+    put_values(
+        &client,
+        &device_uuid,
+        &feature_uuid,
+        sensor_type,
+        &[json!(10), json!(20)],
+    )
+    .await;
+    assert_eq!(
+        count_readings_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+            .await
+            .unwrap(),
+        2
+    );
+
+    // soft-delete keeps the history
+    let res: LocalResponse = client
+        .delete(format!(
+            "/sensors/{}/features/{}/{}",
+            device_uuid, feature_uuid, sensor_type
+        ))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(
+        count_readings_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+            .await
+            .unwrap(),
+        2
+    );
+
+    // purge removes it
+    let res: LocalResponse = client
+        .delete(format!("/sensors/{}?purge=true", device_uuid))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(
+        count_readings_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+            .await
+            .unwrap(),
+        0
+    );
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
@@ -0,0 +1,127 @@
+use super::rocket;
+use mongodb::Database;
+use mongodb::IndexModel;
+use mongodb::bson::{Document, doc};
+use mongodb::options::IndexOptions;
+use rocket::local::asynchronous::Client;
+use rocket::serde::json::Json;
+use uuid::Uuid;
+
+use crate::tests_integration::db_utils::{connect, drop_all_collections, find_sensors_indexes, insert_sensor};
+use crate::tests_integration::test_utils::{create_register_input, get_random_mac};
+
+#[rocket::async_test]
+#[test_log::test]
+async fn create_indexes_at_startup() {
+    // init
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+    // indexes are created while igniting rocket
+    let _client: Client = Client::tracked(rocket()).await.unwrap();
+
+    let indexes: Vec<IndexModel> = find_sensors_indexes(&db).await.unwrap();
+    let find_index = |name: &str| {
+        indexes
+            .iter()
+            .find(|index| index.options.as_ref().unwrap().name.as_deref() == Some(name))
+            .unwrap()
+            .clone()
+    };
+
+    // check results
+    let key_index = find_index("deviceUuid_1_featureUuid_1_featureName_1");
+    assert_eq!(
+        key_index.keys,
+        doc! {"deviceUuid": 1, "featureUuid": 1, "featureName": 1}
+    );
+    assert_eq!(key_index.options.unwrap().unique, Some(true));
+    let profile_index = find_index("profileOwnerId_1");
+    assert_eq!(profile_index.keys, doc! {"profileOwnerId": 1});
+    assert_ne!(profile_index.options.unwrap().unique, Some(true));
+    let mac_index = find_index("mac_1");
+    assert_eq!(mac_index.keys, doc! {"mac": 1});
+    assert_ne!(mac_index.options.unwrap().unique, Some(true));
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn unique_index_prevents_duplicates() {
+    // init
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+    let _client: Client = Client::tracked(rocket()).await.unwrap();
+
+    // inputs
+    let profile_owner_id = String::from("63963ce7c7fd6d463c6c77a3");
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let mac: String = get_random_mac();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+
+    // insert the same sensor twice, bypassing the upsert done by the register api
+    let first_result = insert_sensor(
+        &db,
+        Json(create_register_input(
+            &profile_owner_id,
+            &device_uuid,
+            &mac,
+            &feature_uuid,
+        )),
+        "temperature",
+    )
+    .await;
+    let second_result = insert_sensor(
+        &db,
+        Json(create_register_input(
+            &profile_owner_id,
+            &device_uuid,
+            &mac,
+            &feature_uuid,
+        )),
+        "temperature",
+    )
+    .await;
+
+    // check results
+    assert!(first_result.is_ok());
+    assert!(second_result.is_err());
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn conflicting_index_prevents_startup() {
+    // init
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // create an index with the expected name and keys, but not unique
+    let options = IndexOptions::builder()
+        .name(String::from("deviceUuid_1_featureUuid_1_featureName_1"))
+        .build();
+    let conflicting_index = IndexModel::builder()
+        .keys(doc! {"deviceUuid": 1, "featureUuid": 1, "featureName": 1})
+        .options(options)
+        .build();
+    db.collection::<Document>("sensors")
+        .create_index(conflicting_index)
+        .await
+        .unwrap();
+
+    // check results
+    let client_result = Client::tracked(rocket()).await;
+    assert!(client_result.is_err());
+    let indexes: Vec<IndexModel> = find_sensors_indexes(&db).await.unwrap();
+    let key_index = indexes
+        .iter()
+        .find(|index| index.keys == doc! {"deviceUuid": 1, "featureUuid": 1, "featureName": 1})
+        .unwrap();
+    assert_ne!(key_index.options.as_ref().unwrap().unique, Some(true));
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use super::rocket;
+use mongodb::Database;
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::{Client, LocalRequest, LocalResponse};
+use serde_json::{Value, json};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::tests_integration::db_utils::{connect, drop_all_collections, find_sensor_by_uuid};
+use crate::tests_integration::test_utils::{API_TOKEN, bearer, register_sensor};
+
+#[rocket::async_test]
+#[test_log::test]
+async fn put_sensor_value() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // run tests for every sensor_type
+    let sensors_inputs: HashMap<&str, Value> = HashMap::from([
+        ("temperature", json!(28.12)),
+        ("humidity", json!(67)),
+        ("light", json!(12.5)),
+        ("airpressure", json!(1013.25)),
+        ("motion", json!(1)),
+        ("airquality", json!(3)),
+        ("online", json!(1)),
+    ]);
+
+    for (sensor_type, sensor_val) in &sensors_inputs {
+        info!(target: "test", "put_sensor_value - TEST with type = {} and value = {}", &sensor_type, sensor_val);
+        // inputs
+        let device_uuid: String = Uuid::new_v4().to_string();
+        let feature_uuid: String = Uuid::new_v4().to_string();
+        assert_eq!(
+            register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+            Status::Created
+        );
+
+        // test api
+        let req: LocalRequest = client
+            .put(format!(
+                "/sensors/{}/features/{}/{}/value",
+                device_uuid, feature_uuid, sensor_type
+            ))
+            .header(ContentType::JSON)
+            .header(bearer(API_TOKEN))
+            .body(json!({ "value": sensor_val }).to_string());
+        let res: LocalResponse = req.dispatch().await;
+
+        // check results
+        let document = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+            .await
+            .unwrap()
+            .unwrap();
+        let created_at = document.get_datetime("createdAt").unwrap().timestamp_millis();
+        let modified_at = document.get_datetime("modifiedAt").unwrap().timestamp_millis();
+        assert!(modified_at >= created_at);
+        match *sensor_type {
+            "motion" | "airquality" | "online" => {
+                assert_eq!(document.get_i64("value").unwrap(), sensor_val.as_i64().unwrap())
+            }
+            _ => assert_eq!(document.get_f64("value").unwrap(), sensor_val.as_f64().unwrap()),
+        }
+        assert_eq!(res.status(), Status::Ok);
+        let expected = json!({
+            "value": sensor_val.as_f64().unwrap(),
+            "createdAt": created_at,
+            "modifiedAt": modified_at,
+        });
+        assert_eq!(res.into_json::<Value>().await.unwrap(), expected);
+    }
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn put_sensor_value_errors() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "motion";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+    let value_url = format!(
+        "/sensors/{}/features/{}/{}/value",
+        device_uuid, feature_uuid, sensor_type
+    );
+
+    // float value for an int sensor
+    let res: LocalResponse = client
+        .put(value_url.clone())
+        .header(ContentType::JSON)
+        .header(bearer(API_TOKEN))
+        .body(json!({ "value": 1.5 }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::BadRequest);
+    // value out of the range of the sensor type
+    let res: LocalResponse = client
+        .put(value_url.clone())
+        .header(ContentType::JSON)
+        .header(bearer(API_TOKEN))
+        .body(json!({ "value": 2 }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::BadRequest);
+    // not a number
+    let res: LocalResponse = client
+        .put(value_url.clone())
+        .header(ContentType::JSON)
+        .header(bearer(API_TOKEN))
+        .body(json!({ "value": "1" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    // missing token
+    let res: LocalResponse = client
+        .put(value_url.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "value": 1 }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    // unregistered feature
+    let res: LocalResponse = client
+        .put(format!(
+            "/sensors/{}/features/{}/{}/value",
+            device_uuid,
+            Uuid::new_v4(),
+            sensor_type
+        ))
+        .header(ContentType::JSON)
+        .header(bearer(API_TOKEN))
+        .body(json!({ "value": 1 }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::NotFound);
+
+    // the value is unchanged
+    let document = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(document.get_i64("value").unwrap(), 0);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
@@ -0,0 +1,277 @@
+use std::time::Duration;
+
+use super::rocket;
+use mongodb::Database;
+use mongodb::bson::oid::ObjectId;
+use rocket::http::Status;
+use rocket::local::asynchronous::{Client, LocalResponse};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::tests_integration::db_utils::{connect, drop_all_collections, find_sensor_by_uuid};
+use crate::tests_integration::test_utils::{API_TOKEN, bearer, register_sensor_of_profile};
+
+async fn get_page(client: &Client, url: String) -> Value {
+    let res: LocalResponse = client.get(url).header(bearer(API_TOKEN)).dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    res.into_json::<Value>().await.unwrap()
+}
+
+fn feature_uuids(page: &Value) -> Vec<String> {
+    page["sensors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|sensor| sensor["featureUuid"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_device_sensors() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let profile_owner_id = ObjectId::new().to_hex();
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let mac = String::from("AA:BB:CC:DD:EE:FF");
+    let sensor_types = ["temperature", "humidity", "motion"];
+    let mut registered: Vec<String> = Vec::new();
+    for sensor_type in sensor_types {
+        let feature_uuid: String = Uuid::new_v4().to_string();
+        assert_eq!(
+            register_sensor_of_profile(
+                &client,
+                &profile_owner_id,
+                &device_uuid,
+                &mac,
+                &feature_uuid,
+                sensor_type,
+            )
+            .await,
+            Status::Created
+        );
+        registered.push(feature_uuid);
+        // sensors must have different 'modifiedAt'
+        rocket::tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    // most recently modified first
+    let newest_first: Vec<String> = registered.iter().rev().cloned().collect();
+
+    // test api
+    let page = get_page(&client, format!("/devices/{}/sensors", device_uuid)).await;
+
+    // check results
+    assert_eq!(feature_uuids(&page), newest_first);
+    assert_eq!(page["nextCursor"], Value::Null);
+    let document = find_sensor_by_uuid(&db, &device_uuid, &registered[2], "motion")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        page["sensors"][0],
+        json!({
+            "deviceUuid": device_uuid,
+            "featureUuid": registered[2],
+            "featureName": "motion",
+            "value": 0.0,
+            "createdAt": document.get_datetime("createdAt").unwrap().timestamp_millis(),
+            "modifiedAt": document.get_datetime("modifiedAt").unwrap().timestamp_millis(),
+            "mac": mac,
+            "model": "test-model",
+            "manufacturer": "ks89",
+        })
+    );
+
+    // pagination
+    let first_page = get_page(&client, format!("/devices/{}/sensors?limit=2", device_uuid)).await;
+    assert_eq!(feature_uuids(&first_page), newest_first[..2].to_vec());
+    let cursor = first_page["nextCursor"].as_str().unwrap();
+    let second_page = get_page(
+        &client,
+        format!("/devices/{}/sensors?limit=2&cursor={}", device_uuid, cursor),
+    )
+    .await;
+    assert_eq!(feature_uuids(&second_page), newest_first[2..].to_vec());
+    assert_eq!(second_page["nextCursor"], Value::Null);
+
+    // sort
+    let page = get_page(&client, format!("/devices/{}/sensors?sort=asc", device_uuid)).await;
+    assert_eq!(feature_uuids(&page), registered);
+
+    // filter by featureName
+    let page = get_page(
+        &client,
+        format!("/devices/{}/sensors?featureName=humidity", device_uuid),
+    )
+    .await;
+    assert_eq!(feature_uuids(&page), vec![registered[1].clone()]);
+
+    // soft-deleted sensors are hidden
+    let res: LocalResponse = client
+        .delete(format!(
+            "/sensors/{}/features/{}/temperature",
+            device_uuid, registered[0]
+        ))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+    let page = get_page(&client, format!("/devices/{}/sensors", device_uuid)).await;
+    assert_eq!(feature_uuids(&page), newest_first[..2].to_vec());
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_profile_sensors() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let profile_owner_id = ObjectId::new().to_hex();
+    let other_profile_owner_id = ObjectId::new().to_hex();
+    let mut registered: Vec<String> = Vec::new();
+    for mac in ["AA:BB:CC:DD:EE:01", "AA:BB:CC:DD:EE:02"] {
+        let device_uuid: String = Uuid::new_v4().to_string();
+        for sensor_type in ["temperature", "light"] {
+            let feature_uuid: String = Uuid::new_v4().to_string();
+            assert_eq!(
+                register_sensor_of_profile(
+                    &client,
+                    &profile_owner_id,
+                    &device_uuid,
+                    mac,
+                    &feature_uuid,
+                    sensor_type,
+                )
+                .await,
+                Status::Created
+            );
+            registered.push(feature_uuid);
+            // sensors must have different 'modifiedAt'
+            rocket::tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+    // a sensor of another profile
+    assert_eq!(
+        register_sensor_of_profile(
+            &client,
+            &other_profile_owner_id,
+            &Uuid::new_v4().to_string(),
+            "AA:BB:CC:DD:EE:03",
+            &Uuid::new_v4().to_string(),
+            "temperature",
+        )
+        .await,
+        Status::Created
+    );
+
+    // test api
+    let page = get_page(&client, format!("/profiles/{}/sensors?sort=asc", profile_owner_id)).await;
+
+    // check results
+    assert_eq!(feature_uuids(&page), registered);
+
+    // pagination with filter
+    let first_page = get_page(
+        &client,
+        format!(
+            "/profiles/{}/sensors?sort=asc&featureName=light&limit=1",
+            profile_owner_id
+        ),
+    )
+    .await;
+    assert_eq!(feature_uuids(&first_page), vec![registered[1].clone()]);
+    let cursor = first_page["nextCursor"].as_str().unwrap();
+    let second_page = get_page(
+        &client,
+        format!(
+            "/profiles/{}/sensors?sort=asc&featureName=light&limit=1&cursor={}",
+            profile_owner_id, cursor
+        ),
+    )
+    .await;
+    assert_eq!(feature_uuids(&second_page), vec![registered[3].clone()]);
+    assert_eq!(second_page["nextCursor"], Value::Null);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn list_sensors_errors() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let profile_owner_id = ObjectId::new().to_hex();
+    let device_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor_of_profile(
+            &client,
+            &profile_owner_id,
+            &device_uuid,
+            "AA:BB:CC:DD:EE:FF",
+            &Uuid::new_v4().to_string(),
+            "temperature",
+        )
+        .await,
+        Status::Created
+    );
+
+    // invalid limit, cursor or featureName
+    for query in [
+        "limit=0",
+        "limit=501",
+        "cursor=invalid",
+        "cursor=123_invalid",
+        "featureName=invalid",
+    ] {
+        let res: LocalResponse = client
+            .get(format!("/devices/{}/sensors?{}", device_uuid, query))
+            .header(bearer(API_TOKEN))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+    // invalid profile
+    let res: LocalResponse = client
+        .get("/profiles/invalid-profile/sensors")
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::BadRequest);
+    // unknown profile
+    let res: LocalResponse = client
+        .get(format!("/profiles/{}/sensors", ObjectId::new().to_hex()))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::NotFound);
+    // missing and wrong token
+    let res: LocalResponse = client
+        .get(format!("/profiles/{}/sensors", profile_owner_id))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    let res: LocalResponse = client
+        .get(format!("/devices/{}/sensors", device_uuid))
+        .header(bearer("2e8b2bd8-1d5f-4c3a-9b8e-0f6d7c5a4b3e"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Forbidden);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
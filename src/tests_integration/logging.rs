@@ -0,0 +1,171 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use register::config::{Env, LogFormat};
+use register::logging::size_rolling::SizeRollingFile;
+use register::logging::{log_filter, subscriber, writer};
+
+// in-memory destination of log events
+#[derive(Clone, Default)]
+pub struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    pub fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// lines logged by `log` with a subscriber in `format`, filtered by `directives`
+fn logged(format: LogFormat, directives: Option<&str>, log: impl FnOnce()) -> Vec<String> {
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let subscriber = subscriber(format, log_filter(directives).unwrap(), move || writer.clone());
+    tracing::subscriber::with_default(subscriber, log);
+    buffer.lines()
+}
+
+fn env_with(vars: &[(&str, &str)]) -> Env {
+    envy::from_iter(vars.iter().map(|(key, value)| (key.to_string(), value.to_string()))).unwrap()
+}
+
+fn temp_log_dir() -> PathBuf {
+    env::temp_dir().join(format!("register_logs_{}", Uuid::new_v4()))
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+#[test]
+fn text_format() {
+    let lines = logged(LogFormat::Text, None, || {
+        info!(target: "app", sensor_type = "temperature", "upsert_sensor - Called");
+    });
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].contains(" INFO app: upsert_sensor - Called"), "{}", lines[0]);
+    assert!(lines[0].contains("sensor_type=\"temperature\""), "{}", lines[0]);
+    assert!(!lines[0].starts_with('{'), "{}", lines[0]);
+}
+
+#[test]
+fn json_format() {
+    let lines = logged(LogFormat::Json, None, || {
+        info!(target: "app", sensor_type = "temperature", "upsert_sensor - Called");
+        error!(target: "app", "upsert_sensor - Cannot insert");
+    });
+    assert_eq!(lines.len(), 2);
+    let event: Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(event["level"], "INFO");
+    assert_eq!(event["target"], "app");
+    assert_eq!(event["message"], "upsert_sensor - Called");
+    assert_eq!(event["sensor_type"], "temperature");
+    assert!(event["timestamp"].is_string());
+    let event: Value = serde_json::from_str(&lines[1]).unwrap();
+    assert_eq!(event["level"], "ERROR");
+}
+
+#[test]
+fn filter_directives() {
+    let log = || {
+        debug!(target: "app", "app debug");
+        info!(target: "rocket", "rocket info");
+        warn!(target: "rocket", "rocket warn");
+        info!(target: "sqlx::query", "sqlx info");
+    };
+    let messages = |lines: Vec<String>| -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                serde_json::from_str::<Value>(line).unwrap()["message"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect()
+    };
+    // 'info,sqlx=warn' by default
+    assert_eq!(
+        messages(logged(LogFormat::Json, None, log)),
+        vec!["rocket info", "rocket warn"]
+    );
+    assert_eq!(
+        messages(logged(LogFormat::Json, Some("warn,app=debug"), log)),
+        vec!["app debug", "rocket warn"]
+    );
+
+    let error = log_filter(Some("app=loud")).err().unwrap();
+    assert!(error.starts_with("RUST_LOG = app=loud: "), "{}", error);
+}
+
+#[test]
+fn size_rolling_file() {
+    let log_dir = temp_log_dir();
+    let mut file = SizeRollingFile::new(&log_dir, "all", 100, 3).unwrap();
+    for index in 0..10 {
+        // 40 bytes for every line, 2 lines for every file
+        file.write_all(format!("{:039}\n", index).as_bytes()).unwrap();
+    }
+    file.flush().unwrap();
+
+    assert_eq!(read(&file.path(0)), format!("{:039}\n{:039}\n", 8, 9));
+    assert_eq!(read(&file.path(1)), format!("{:039}\n{:039}\n", 6, 7));
+    assert_eq!(read(&file.path(2)), format!("{:039}\n{:039}\n", 4, 5));
+    // older files are removed
+    assert!(!file.path(3).exists());
+    assert_eq!(fs::read_dir(&log_dir).unwrap().count(), 3);
+    fs::remove_dir_all(&log_dir).unwrap();
+}
+
+#[test]
+fn log_files_output() {
+    let log_dir = temp_log_dir();
+    let dir = log_dir.display().to_string();
+    let env = env_with(&[("LOG_OUTPUT", "file"), ("LOG_DIR", &dir), ("LOG_ROTATION", "size")]);
+    let subscriber = subscriber(LogFormat::Text, log_filter(None).unwrap(), writer(&env).unwrap());
+    tracing::subscriber::with_default(subscriber, || {
+        info!(target: "app", "sensor registered");
+        error!(target: "app", "cannot register sensor");
+    });
+
+    let all = read(&log_dir.join("all.log"));
+    assert!(all.contains("sensor registered"), "{}", all);
+    assert!(all.contains("cannot register sensor"), "{}", all);
+    // only errors
+    let errors = read(&log_dir.join("error.log"));
+    assert!(!errors.contains("sensor registered"), "{}", errors);
+    assert!(errors.contains("cannot register sensor"), "{}", errors);
+    fs::remove_dir_all(&log_dir).unwrap();
+}
+
+#[test]
+fn stdout_only_output() {
+    let log_dir = temp_log_dir();
+    let dir = log_dir.display().to_string();
+    let env = env_with(&[("LOG_OUTPUT", "stdout"), ("LOG_DIR", &dir)]);
+    let subscriber = subscriber(LogFormat::Json, log_filter(None).unwrap(), writer(&env).unwrap());
+    tracing::subscriber::with_default(subscriber, || info!(target: "app", "sensor registered"));
+    // no log files
+    assert!(!log_dir.exists());
+}
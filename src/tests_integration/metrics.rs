@@ -0,0 +1,149 @@
+use std::env;
+
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use uuid::Uuid;
+
+use crate::tests_integration::backends::backend_client;
+use crate::tests_integration::retry::unreachable_postgres_client;
+use crate::tests_integration::test_utils::{API_TOKEN, bearer, build_register_input, get_random_mac, register_sensor};
+
+async fn get_metrics(client: &Client) -> String {
+    let res: LocalResponse = client.get("/metrics").dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(
+        res.content_type().unwrap().to_string(),
+        "text/plain; version=0.0.4; charset=utf-8"
+    );
+    res.into_string().await.unwrap()
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn http_requests_metrics() {
+    let client: Client = backend_client("memory", None).await;
+
+    // inputs
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+
+    // test apis
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, "temperature").await,
+        Status::Created
+    );
+    let status = client
+        .get(format!(
+            "/sensors/{}/features/{}/temperature",
+            device_uuid, feature_uuid
+        ))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await
+        .status();
+    assert_eq!(status, Status::Ok);
+    let status = client.get("/unknown/url").dispatch().await.status();
+    assert_eq!(status, Status::NotFound);
+
+    // check results
+    let metrics = get_metrics(&client).await;
+    assert!(metrics.contains(
+        "register_http_requests_total{method=\"POST\",route=\"/sensors/register/<sensor_type>\",status=\"201\"} 1\n"
+    ));
+    assert!(metrics.contains("register_http_requests_total{method=\"GET\",route=\"/sensors/<device_uuid>/features/<feature_uuid>/<sensor_type>\",status=\"200\"} 1\n"));
+    assert!(metrics.contains("register_http_requests_total{method=\"GET\",route=\"unmatched\",status=\"404\"} 1\n"));
+    assert!(metrics.contains(
+        "register_http_request_duration_seconds_count{method=\"POST\",route=\"/sensors/register/<sensor_type>\"} 1\n"
+    ));
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn db_and_sensors_metrics() {
+    // SAFETY: integration tests run on a single thread (`--test-threads 1`)
+    unsafe { env::set_var("METRICS_STALE_AFTER_SECS", "3600") };
+    let client: Client = backend_client("memory", None).await;
+    unsafe { env::remove_var("METRICS_STALE_AFTER_SECS") };
+
+    // inputs
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+
+    // test apis
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, "temperature").await,
+        Status::Created
+    );
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, "temperature").await,
+        Status::Ok
+    );
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &Uuid::new_v4().to_string(), "motion").await,
+        Status::Created
+    );
+    // invalid inputs are rejected before the storage
+    let status = client
+        .post("/sensors/register/motion")
+        .header(ContentType::JSON)
+        .body(build_register_input(
+            "invalid",
+            &device_uuid,
+            &get_random_mac(),
+            &Uuid::new_v4().to_string(),
+        ))
+        .dispatch()
+        .await
+        .status();
+    assert_eq!(status, Status::UnprocessableEntity);
+
+    // check results
+    let metrics = get_metrics(&client).await;
+    assert!(
+        metrics.contains("register_sensor_registrations_total{result=\"created\",sensor_type=\"temperature\"} 1\n")
+    );
+    assert!(
+        metrics.contains("register_sensor_registrations_total{result=\"updated\",sensor_type=\"temperature\"} 1\n")
+    );
+    assert!(metrics.contains("register_sensor_registrations_total{result=\"created\",sensor_type=\"motion\"} 1\n"));
+    assert!(!metrics.contains("register_sensor_registrations_total{result=\"failed\""));
+    assert!(metrics.contains("register_db_operation_duration_seconds_count{operation=\"upsert_sensor\"} 3\n"));
+    assert!(metrics.contains("register_sensors_registered{sensor_type=\"temperature\"} 1\n"));
+    assert!(metrics.contains("register_sensors_registered{sensor_type=\"motion\"} 1\n"));
+    assert!(metrics.contains("register_sensors_stale{sensor_type=\"temperature\"} 0\n"));
+
+    // with a threshold of 0 seconds, every sensor is stale
+    drop(client);
+    unsafe { env::set_var("METRICS_STALE_AFTER_SECS", "0") };
+    let client: Client = backend_client("memory", None).await;
+    unsafe { env::remove_var("METRICS_STALE_AFTER_SECS") };
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, "humidity").await,
+        Status::Created
+    );
+    // wait for the sensor to become older than 'now'
+    rocket::tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    let metrics = get_metrics(&client).await;
+    assert!(metrics.contains("register_sensors_registered{sensor_type=\"humidity\"} 1\n"));
+    assert!(metrics.contains("register_sensors_stale{sensor_type=\"humidity\"} 1\n"));
+    assert!(!metrics.contains("register_sensors_registered{sensor_type=\"temperature\"}"));
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn db_errors_metrics() {
+    let client: Client = unreachable_postgres_client(true).await.unwrap();
+
+    // registration fails in the storage, checking the apiTokens of the device
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, "motion").await,
+        Status::ServiceUnavailable
+    );
+
+    // check results
+    let metrics = get_metrics(&client).await;
+    assert!(metrics.contains("register_db_operation_errors_total{operation=\"find_api_tokens\"} 1\n"));
+    assert!(!metrics.contains("register_db_operation_duration_seconds_count{operation=\"upsert_sensor\"}"));
+}
@@ -0,0 +1,289 @@
+use super::rocket;
+use mongodb::Database;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{Bson, DateTime, Document, doc};
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use uuid::Uuid;
+
+use register::db::indexes::ensure_indexes;
+use register::db::migrations::{MigrationStatus, run_migrations};
+use register::errors::db_error::DbError;
+use register::models::sensor::SENSOR_SCHEMA_VERSION;
+
+use crate::tests_integration::db_utils::{
+    connect, count_sensors_by_uuid, drop_all_collections, find_sensor_by_uuid, find_sensors_indexes,
+};
+use crate::tests_integration::test_utils::{API_TOKEN, build_register_input, get_random_mac};
+
+// sensor document created before `schemaVersion`, with `value` stored as it is
+async fn insert_legacy_sensor(db: &Database, device_uuid: &str, feature_uuid: &str, feature_name: &str, value: Bson) {
+    let date_now = DateTime::now();
+    let sensor_doc = doc! {
+        "_id": ObjectId::new(),
+        "profileOwnerId": ObjectId::new(),
+        "apiToken": API_TOKEN,
+        "deviceUuid": device_uuid,
+        "mac": get_random_mac(),
+        "model": "test-model",
+        "manufacturer": "ks89",
+        "featureUuid": feature_uuid,
+        "featureName": feature_name,
+        "value": value,
+        "createdAt": date_now,
+        "modifiedAt": date_now,
+    };
+    db.collection::<Document>("sensors")
+        .insert_one(sensor_doc)
+        .await
+        .expect("insert legacy sensor");
+}
+
+async fn count_migrations(db: &Database) -> u64 {
+    db.collection::<Document>("migrations")
+        .count_documents(doc! {})
+        .await
+        .unwrap()
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn apply_migrations_at_startup() {
+    // init
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let airpressure_uuid: String = Uuid::new_v4().to_string();
+    let motion_uuid: String = Uuid::new_v4().to_string();
+    let removed_type_uuid: String = Uuid::new_v4().to_string();
+    insert_legacy_sensor(&db, &device_uuid, &airpressure_uuid, "airpressure", Bson::Int64(1013)).await;
+    insert_legacy_sensor(&db, &device_uuid, &motion_uuid, "motion", Bson::Int64(1)).await;
+    // type removed from the catalogue
+    insert_legacy_sensor(&db, &device_uuid, &removed_type_uuid, "noise", Bson::Int32(30)).await;
+
+    // migrations are applied while igniting rocket
+    let _client: Client = Client::tracked(rocket()).await.unwrap();
+
+    // check results
+    let airpressure_doc = find_sensor_by_uuid(&db, &device_uuid, &airpressure_uuid, "airpressure")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(airpressure_doc.get_i32("schemaVersion").unwrap(), SENSOR_SCHEMA_VERSION);
+    assert_eq!(airpressure_doc.get("value"), Some(&Bson::Double(1013.0)));
+    let motion_doc = find_sensor_by_uuid(&db, &device_uuid, &motion_uuid, "motion")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(motion_doc.get_i32("schemaVersion").unwrap(), SENSOR_SCHEMA_VERSION);
+    assert_eq!(motion_doc.get("value"), Some(&Bson::Int64(1)));
+    let removed_type_doc = find_sensor_by_uuid(&db, &device_uuid, &removed_type_uuid, "noise")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        removed_type_doc.get_i32("schemaVersion").unwrap(),
+        SENSOR_SCHEMA_VERSION
+    );
+    assert_eq!(removed_type_doc.get("value"), Some(&Bson::Int32(30)));
+    assert_eq!(count_migrations(&db).await, 3);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn dry_run_and_apply_migrations() {
+    // init
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    insert_legacy_sensor(&db, &device_uuid, &feature_uuid, "airpressure", Bson::Int32(1000)).await;
+    // sensor already at the last `schemaVersion`, that migrations must not touch
+    let migrated_uuid: String = Uuid::new_v4().to_string();
+    insert_legacy_sensor(&db, &device_uuid, &migrated_uuid, "airpressure", Bson::Double(990.0)).await;
+    db.collection::<Document>("sensors")
+        .update_one(
+            doc! {"featureUuid": &migrated_uuid},
+            doc! {"$set": {"schemaVersion": SENSOR_SCHEMA_VERSION}},
+        )
+        .await
+        .unwrap();
+
+    // dry-run doesn't change anything
+    let outcomes = run_migrations(&db, true).await.unwrap();
+    assert_eq!(
+        outcomes
+            .iter()
+            .map(|outcome| (outcome.version, outcome.status, outcome.documents))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, MigrationStatus::Pending, 1),
+            (2, MigrationStatus::Pending, 1),
+            (3, MigrationStatus::Pending, 0)
+        ]
+    );
+    let sensor_doc = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, "airpressure")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(sensor_doc.get("schemaVersion").is_none());
+    assert_eq!(sensor_doc.get("value"), Some(&Bson::Int32(1000)));
+    assert_eq!(count_migrations(&db).await, 0);
+
+    // apply
+    let outcomes = run_migrations(&db, false).await.unwrap();
+    assert_eq!(
+        outcomes
+            .iter()
+            .map(|outcome| (outcome.version, outcome.status, outcome.documents))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, MigrationStatus::Applied, 1),
+            (2, MigrationStatus::Applied, 1),
+            (3, MigrationStatus::Applied, 0)
+        ]
+    );
+    let sensor_doc = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, "airpressure")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(sensor_doc.get_i32("schemaVersion").unwrap(), SENSOR_SCHEMA_VERSION);
+    assert_eq!(sensor_doc.get("value"), Some(&Bson::Double(1000.0)));
+    assert_eq!(count_migrations(&db).await, 3);
+    let migrated_doc = find_sensor_by_uuid(&db, &device_uuid, &migrated_uuid, "airpressure")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(migrated_doc.get_i32("schemaVersion").unwrap(), SENSOR_SCHEMA_VERSION);
+    assert_eq!(migrated_doc.get("value"), Some(&Bson::Double(990.0)));
+
+    // applied migrations are skipped
+    let outcomes = run_migrations(&db, false).await.unwrap();
+    assert!(
+        outcomes
+            .iter()
+            .all(|outcome| outcome.status == MigrationStatus::AlreadyApplied)
+    );
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn register_sets_schema_version() {
+    // init
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+
+    // inputs
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    let register_body = build_register_input(
+        &ObjectId::new().to_hex(),
+        &device_uuid,
+        &get_random_mac(),
+        &feature_uuid,
+    );
+
+    // test api
+    let status = client
+        .post("/sensors/register/temperature")
+        .header(ContentType::JSON)
+        .body(register_body)
+        .dispatch()
+        .await
+        .status();
+    assert_eq!(status, Status::Created);
+
+    // check results
+    let sensor_doc = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, "temperature")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(sensor_doc.get_i32("schemaVersion").unwrap(), SENSOR_SCHEMA_VERSION);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn remove_duplicated_sensors_at_startup() {
+    // init
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs, registered twice before the unique index
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    for value in [20.5, 21.5] {
+        insert_legacy_sensor(&db, &device_uuid, &feature_uuid, "temperature", Bson::Double(value)).await;
+        // sensors must have different 'modifiedAt'
+        rocket::tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    // migrations are applied, before creating the unique index, while igniting rocket
+    let _client: Client = Client::tracked(rocket()).await.unwrap();
+
+    // check results, the last modified sensor is kept
+    assert_eq!(
+        count_sensors_by_uuid(&db, &device_uuid, &feature_uuid, "temperature")
+            .await
+            .unwrap(),
+        1
+    );
+    let sensor_doc = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, "temperature")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(sensor_doc.get("value"), Some(&Bson::Double(21.5)));
+    let indexes = find_sensors_indexes(&db).await.unwrap();
+    assert!(indexes.iter().any(|index| {
+        index.options.as_ref().unwrap().name.as_deref() == Some("deviceUuid_1_featureUuid_1_featureName_1")
+    }));
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn duplicated_sensors_without_migrations_error() {
+    // init
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs, registered twice before the unique index
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    for _ in 0..2 {
+        insert_legacy_sensor(&db, &device_uuid, &feature_uuid, "motion", Bson::Int64(0)).await;
+    }
+
+    // the unique index cannot be created
+    let indexes_result = ensure_indexes(&db, false).await;
+
+    // check results
+    match indexes_result {
+        Err(DbError::DuplicateKey(message)) => assert!(message.contains(&device_uuid), "{}", message),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert_eq!(
+        count_sensors_by_uuid(&db, &device_uuid, &feature_uuid, "motion")
+            .await
+            .unwrap(),
+        2
+    );
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
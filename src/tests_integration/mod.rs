@@ -1,8 +1,28 @@
 use super::rocket;
 
+mod aggregate;
+mod backends;
+mod config;
+mod db_errors;
+mod deregister;
 mod errors_catchers;
+mod health;
+mod history;
+mod indexes;
+mod ingest;
 mod keepalive;
+mod list;
+mod logging;
+mod metrics;
+mod migrations;
 mod register;
+mod request_id;
+mod retry;
+mod sensor_auth;
+mod sensor_types;
+#[cfg(feature = "otel")]
+mod telemetry;
+mod validation;
 
 // test utils
 mod db_utils;
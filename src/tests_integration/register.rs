@@ -10,13 +10,15 @@ use tracing::info;
 use uuid::Uuid;
 
 use register::models::inputs::RegisterInput;
-use register::routes::api::VALID_SENSOR_TYPES;
+use register::models::sensor_type::SensorType;
 
 use crate::tests_integration::db_utils::{
-    connect, drop_all_collections, find_sensor_by_uuid, insert_sensor, update_sensor_float_value_by_uuid,
-    update_sensor_int_value_by_uuid,
+    connect, count_sensors_by_uuid, drop_all_collections, find_sensor_by_uuid, insert_sensor,
+    update_sensor_float_value_by_uuid, update_sensor_int_value_by_uuid,
+};
+use crate::tests_integration::test_utils::{
+    API_TOKEN, bearer, build_register_input, create_register_input, get_random_mac,
 };
-use crate::tests_integration::test_utils::{build_register_input, create_register_input, get_random_mac};
 
 #[rocket::async_test]
 #[test_log::test]
@@ -26,7 +28,7 @@ async fn register_sensor() {
     let db: Database = connect().await.unwrap();
     drop_all_collections(&db).await;
 
-    for sensor_type in VALID_SENSOR_TYPES.iter() {
+    for sensor_type in SensorType::all().map(SensorType::as_str) {
         // inputs
         let profile_owner_id = String::from("63963ce7c7fd6d463c6c77a3");
         let device_uuid: String = Uuid::new_v4().to_string();
@@ -48,14 +50,181 @@ async fn register_sensor() {
         let inserted_id = document.get_object_id("_id").unwrap().to_hex();
 
         // check results
+        assert_eq!(res.status(), Status::Created);
+        assert_eq!(res.into_json::<Value>().await.unwrap(), json!({ "id": inserted_id }));
+    }
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn register_sensor_again() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    for sensor_type in SensorType::all().map(SensorType::as_str) {
+        // inputs
+        let profile_owner_id = String::from("63963ce7c7fd6d463c6c77a3");
+        let device_uuid: String = Uuid::new_v4().to_string();
+        let feature_uuid: String = Uuid::new_v4().to_string();
+        let first_register_body =
+            build_register_input(&profile_owner_id, &device_uuid, &get_random_mac(), &feature_uuid);
+
+        // register the sensor for the first time
+        let req: LocalRequest = client
+            .post("/sensors/register/".to_owned() + sensor_type)
+            .header(ContentType::JSON)
+            .body(first_register_body);
+        let res: LocalResponse = req.dispatch().await;
+        assert_eq!(res.status(), Status::Created);
+        let first_document = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // register the same sensor again (e.g. after a device reboot) with updated metadata
+        let new_mac: String = get_random_mac();
+        let mut second_register_input: RegisterInput =
+            create_register_input(&profile_owner_id, &device_uuid, &new_mac, &feature_uuid);
+        second_register_input.apiToken = String::from("a4c1b0e2-3f0d-4b8e-9d1c-2b7f6e5a4d3c");
+        second_register_input.model = String::from("test-model-v2");
+        let req: LocalRequest = client
+            .post("/sensors/register/".to_owned() + sensor_type)
+            .header(ContentType::JSON)
+            .header(bearer(API_TOKEN))
+            .body(serde_json::to_string(&second_register_input).unwrap());
+        let res: LocalResponse = req.dispatch().await;
+
+        // check results
+        let inserted_id = first_document.get_object_id("_id").unwrap().to_hex();
         assert_eq!(res.status(), Status::Ok);
         assert_eq!(res.into_json::<Value>().await.unwrap(), json!({ "id": inserted_id }));
+        assert_eq!(
+            count_sensors_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+                .await
+                .unwrap(),
+            1
+        );
+        let second_document = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+            .await
+            .unwrap()
+            .unwrap();
+        // immutable fields are preserved
+        assert_eq!(second_document.get("_id"), first_document.get("_id"));
+        assert_eq!(second_document.get("value"), first_document.get("value"));
+        assert_eq!(second_document.get("createdAt"), first_document.get("createdAt"));
+        // metadata is refreshed
+        assert_eq!(second_document.get_str("mac").unwrap(), new_mac);
+        assert_eq!(second_document.get_str("model").unwrap(), "test-model-v2");
+        assert_eq!(
+            second_document.get_str("apiToken").unwrap(),
+            "a4c1b0e2-3f0d-4b8e-9d1c-2b7f6e5a4d3c"
+        );
+        assert!(
+            second_document.get_datetime("modifiedAt").unwrap() >= first_document.get_datetime("modifiedAt").unwrap()
+        );
     }
 
     // cleanup
     drop_all_collections(&db).await;
 }
 
+#[rocket::async_test]
+#[test_log::test]
+async fn register_sensor_again_same_body() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "temperature";
+    let profile_owner_id = String::from("63963ce7c7fd6d463c6c77a3");
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    let register_body = build_register_input(&profile_owner_id, &device_uuid, &get_random_mac(), &feature_uuid);
+
+    // devices register again after every reboot with the same body, without an Authorization header
+    let mut ids: Vec<Value> = Vec::new();
+    for expected_status in [Status::Created, Status::Ok] {
+        let req: LocalRequest = client
+            .post("/sensors/register/".to_owned() + sensor_type)
+            .header(ContentType::JSON)
+            .body(register_body.clone());
+        let res: LocalResponse = req.dispatch().await;
+        assert_eq!(res.status(), expected_status);
+        ids.push(res.into_json::<Value>().await.unwrap()["id"].clone());
+    }
+
+    // check results
+    assert_eq!(ids[0], ids[1]);
+    assert_eq!(
+        count_sensors_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+            .await
+            .unwrap(),
+        1
+    );
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn register_sensor_again_wrong_token_error() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "temperature";
+    let profile_owner_id = String::from("63963ce7c7fd6d463c6c77a3");
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    let register_body = build_register_input(&profile_owner_id, &device_uuid, &get_random_mac(), &feature_uuid);
+    let req: LocalRequest = client
+        .post("/sensors/register/".to_owned() + sensor_type)
+        .header(ContentType::JSON)
+        .body(register_body);
+    assert_eq!(req.dispatch().await.status(), Status::Created);
+
+    // try to replace the apiToken of the sensor, without its current apiToken
+    let mut register_input: RegisterInput =
+        create_register_input(&profile_owner_id, &device_uuid, &get_random_mac(), &feature_uuid);
+    register_input.apiToken = String::from("a4c1b0e2-3f0d-4b8e-9d1c-2b7f6e5a4d3c");
+    for token in [None, Some("a4c1b0e2-3f0d-4b8e-9d1c-2b7f6e5a4d3c")] {
+        let mut req: LocalRequest = client
+            .post("/sensors/register/".to_owned() + sensor_type)
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&register_input).unwrap());
+        if let Some(token) = token {
+            req = req.header(bearer(token));
+        }
+        assert_eq!(req.dispatch().await.status(), Status::Forbidden);
+    }
+
+    // check results
+    assert_eq!(
+        count_sensors_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+            .await
+            .unwrap(),
+        1
+    );
+    let document = find_sensor_by_uuid(&db, &device_uuid, &feature_uuid, sensor_type)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(document.get_str("apiToken").unwrap(), API_TOKEN);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
 #[rocket::async_test]
 #[test_log::test]
 async fn register_sensor_wrong_profile_error() {
@@ -65,7 +234,7 @@ async fn register_sensor_wrong_profile_error() {
     drop_all_collections(&db).await;
 
     // run tests for every sensor_type
-    for sensor_type in VALID_SENSOR_TYPES.iter() {
+    for sensor_type in SensorType::all().map(SensorType::as_str) {
         // inputs
         let wrong_profile_id = String::from("dasd7dasjdhdsygsyuad");
         let device_uuid: String = Uuid::new_v4().to_string();
@@ -82,7 +251,7 @@ async fn register_sensor_wrong_profile_error() {
         let res: LocalResponse = req.dispatch().await;
 
         // check results
-        assert_eq!(res.status(), Status::BadRequest);
+        assert_eq!(res.status(), Status::UnprocessableEntity);
     }
 
     // cleanup
@@ -162,10 +331,12 @@ async fn get_float_sensor_value() {
         let modified_at = document.get_datetime("modifiedAt").unwrap().timestamp_millis();
 
         // test api
-        let req: LocalRequest = client.get(format!(
-            "/sensors/{}/features/{}/{}",
-            device_uuid, feature_uuid, sensor_type
-        ));
+        let req: LocalRequest = client
+            .get(format!(
+                "/sensors/{}/features/{}/{}",
+                device_uuid, feature_uuid, sensor_type
+            ))
+            .header(bearer(API_TOKEN));
         let res: LocalResponse = req.dispatch().await;
 
         // check results
@@ -220,10 +391,12 @@ async fn get_int_sensor_value() {
         let modified_at = document.get_datetime("modifiedAt").unwrap().timestamp_millis();
 
         // test api
-        let req: LocalRequest = client.get(format!(
-            "/sensors/{}/features/{}/{}",
-            device_uuid, feature_uuid, sensor_type
-        ));
+        let req: LocalRequest = client
+            .get(format!(
+                "/sensors/{}/features/{}/{}",
+                device_uuid, feature_uuid, sensor_type
+            ))
+            .header(bearer(API_TOKEN));
         let res: LocalResponse = req.dispatch().await;
 
         // check results
@@ -0,0 +1,131 @@
+use mongodb::bson::oid::ObjectId;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use serde_json::Value;
+use uuid::Uuid;
+
+use register::config::LogFormat;
+use register::errors::api_error::{ErrorCode, Problem, REQUEST_ID_HEADER};
+use register::logging::{log_filter, subscriber};
+
+use crate::tests_integration::backends::backend_client;
+use crate::tests_integration::logging::Buffer;
+use crate::tests_integration::test_utils::{build_register_input, get_random_mac, problem};
+
+fn response_request_id(res: &LocalResponse<'_>) -> String {
+    res.headers().get_one(REQUEST_ID_HEADER).unwrap().to_string()
+}
+
+#[rocket::async_test]
+async fn request_id_generated() {
+    let client: Client = backend_client("memory", None).await;
+
+    let res: LocalResponse = client.get("/health/live").dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let first_id = response_request_id(&res);
+    assert!(Uuid::try_parse(&first_id).is_ok(), "{}", first_id);
+
+    let res: LocalResponse = client.get("/health/live").dispatch().await;
+    assert_ne!(response_request_id(&res), first_id);
+}
+
+#[rocket::async_test]
+async fn request_id_from_client() {
+    let client: Client = backend_client("memory", None).await;
+
+    let res: LocalResponse = client
+        .get("/health/live")
+        .header(Header::new(REQUEST_ID_HEADER, "gateway-42:a_b.c"))
+        .dispatch()
+        .await;
+    assert_eq!(response_request_id(&res), "gateway-42:a_b.c");
+
+    // replaced if not safe to log
+    let too_long = "a".repeat(129);
+    for invalid_id in ["", "id with spaces", "id\u{7f}", too_long.as_str()] {
+        let res: LocalResponse = client
+            .get("/health/live")
+            .header(Header::new(REQUEST_ID_HEADER, invalid_id.to_string()))
+            .dispatch()
+            .await;
+        let id = response_request_id(&res);
+        assert!(Uuid::try_parse(&id).is_ok(), "{} replaced by {}", invalid_id, id);
+    }
+}
+
+#[rocket::async_test]
+async fn request_id_in_errors() {
+    let client: Client = backend_client("memory", None).await;
+
+    // response of a catcher
+    let res: LocalResponse = client.get("/unknown").dispatch().await;
+    assert_eq!(res.status(), Status::NotFound);
+    let id = response_request_id(&res);
+    let body: Problem = problem(res).await;
+    assert_eq!(body.request_id, Some(id));
+
+    // error returned by a route
+    let register_body = build_register_input(
+        &ObjectId::new().to_hex(),
+        &Uuid::new_v4().to_string(),
+        &get_random_mac(),
+        &Uuid::new_v4().to_string(),
+    );
+    let res: LocalResponse = client
+        .post("/sensors/register/unknown")
+        .header(ContentType::JSON)
+        .header(Header::new(REQUEST_ID_HEADER, "req-1"))
+        .body(register_body)
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::BadRequest);
+    assert_eq!(response_request_id(&res), "req-1");
+    let body: Problem = problem(res).await;
+    assert_eq!(body.code, ErrorCode::InvalidSensorType);
+    assert_eq!(body.request_id.as_deref(), Some("req-1"));
+}
+
+#[rocket::async_test]
+async fn request_id_in_logs() {
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let _guard = tracing::subscriber::set_default(subscriber(
+        LogFormat::Json,
+        log_filter(Some("app=info")).unwrap(),
+        move || writer.clone(),
+    ));
+    let client: Client = backend_client("memory", None).await;
+
+    let res: LocalResponse = client
+        .get("/sensors/types")
+        .header(Header::new(REQUEST_ID_HEADER, "req-types"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+    let res: LocalResponse = client
+        .get("/unknown")
+        .header(Header::new(REQUEST_ID_HEADER, "req-unknown"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::NotFound);
+
+    let events: Vec<Value> = buffer
+        .lines()
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let request_id_of = |message: &str| -> Value {
+        let event = events.iter().find(|event| event["message"] == message).unwrap();
+        assert_eq!(event["span"]["name"], "request");
+        event["span"]["request_id"].clone()
+    };
+    // events of routes and catchers
+    assert_eq!(request_id_of("REST - GET - get_sensor_types"), "req-types");
+    assert_eq!(request_id_of("catcher 404 - not_found"), "req-unknown");
+    // events out of requests
+    let startup = events
+        .iter()
+        .find(|event| event["message"] == "Starting application...")
+        .unwrap();
+    assert!(startup.get("span").is_none());
+}
@@ -0,0 +1,239 @@
+use std::env;
+use std::net::SocketAddr;
+
+use super::rocket;
+use mongodb::Database;
+use mongodb::bson::oid::ObjectId;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use uuid::Uuid;
+
+use register::errors::api_error::ErrorCode;
+
+use crate::tests_integration::backends::backend_client;
+use crate::tests_integration::db_utils::{connect, drop_all_collections};
+use crate::tests_integration::test_utils::{
+    API_TOKEN, bearer, create_register_input, get_random_mac, problem, register_sensor, register_sensor_of_profile,
+};
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_sensor_value_auth_errors() {
+    // init
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "temperature";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+    let sensor_url = format!("/sensors/{}/features/{}/{}", device_uuid, feature_uuid, sensor_type);
+
+    // missing token
+    let res: LocalResponse = client.get(sensor_url.clone()).dispatch().await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    assert_eq!(problem(res).await.code, ErrorCode::Unauthorized);
+    // not a bearer token
+    let res: LocalResponse = client
+        .get(sensor_url.clone())
+        .header(Header::new("Authorization", API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    // wrong token
+    let res: LocalResponse = client
+        .get(sensor_url.clone())
+        .header(bearer("2e8b2bd8-1d5f-4c3a-9b8e-0f6d7c5a4b3e"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Forbidden);
+    assert_eq!(problem(res).await.code, ErrorCode::Forbidden);
+    // unknown sensor
+    let res: LocalResponse = client
+        .get(format!(
+            "/sensors/{}/features/{}/{}",
+            device_uuid,
+            Uuid::new_v4(),
+            sensor_type
+        ))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::NotFound);
+    // right token
+    let res: LocalResponse = client.get(sensor_url).header(bearer(API_TOKEN)).dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_sensor_value_trusted_caller() {
+    // init
+    // SAFETY: integration tests run on a single thread (`--test-threads 1`)
+    unsafe { env::set_var("TRUSTED_CALLERS", "10.1.2.3,10.1.2.4") };
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    unsafe { env::remove_var("TRUSTED_CALLERS") };
+    let db: Database = connect().await.unwrap();
+    drop_all_collections(&db).await;
+
+    // inputs
+    let sensor_type = "humidity";
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let feature_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor(&client, &device_uuid, &feature_uuid, sensor_type).await,
+        Status::Created
+    );
+    let sensor_url = format!("/sensors/{}/features/{}/{}", device_uuid, feature_uuid, sensor_type);
+
+    // trusted caller without token
+    let trusted: SocketAddr = "10.1.2.4:41000".parse().unwrap();
+    let res: LocalResponse = client.get(sensor_url.clone()).remote(trusted).dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    // untrusted caller without token
+    let untrusted: SocketAddr = "10.1.2.5:41000".parse().unwrap();
+    let res: LocalResponse = client.get(sensor_url.clone()).remote(untrusted).dispatch().await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    // `X-Real-IP` cannot be used to impersonate a trusted caller
+    let res: LocalResponse = client
+        .get(sensor_url)
+        .remote(untrusted)
+        .header(Header::new("X-Real-IP", "10.1.2.3"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Unauthorized);
+
+    // cleanup
+    drop_all_collections(&db).await;
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn register_sensor_in_other_device_or_profile_error() {
+    // init
+    let client: Client = backend_client("memory", None).await;
+
+    // inputs
+    let profile_owner_id = ObjectId::new().to_hex();
+    let device_uuid: String = Uuid::new_v4().to_string();
+    assert_eq!(
+        register_sensor_of_profile(
+            &client,
+            &profile_owner_id,
+            &device_uuid,
+            &get_random_mac(),
+            &Uuid::new_v4().to_string(),
+            "temperature",
+        )
+        .await,
+        Status::Created
+    );
+
+    // an attacker adds a sensor with its own apiToken to the device or to the profile of another user,
+    // to read their sensors
+    let attacker_token = "a4c1b0e2-3f0d-4b8e-9d1c-2b7f6e5a4d3c";
+    for (attacker_profile_owner_id, attacker_device_uuid) in [
+        (profile_owner_id.clone(), device_uuid.clone()),
+        (profile_owner_id.clone(), Uuid::new_v4().to_string()),
+        (ObjectId::new().to_hex(), device_uuid.clone()),
+    ] {
+        let mut register_input = create_register_input(
+            &attacker_profile_owner_id,
+            &attacker_device_uuid,
+            &get_random_mac(),
+            &Uuid::new_v4().to_string(),
+        );
+        register_input.apiToken = String::from(attacker_token);
+        for token in [None, Some(attacker_token)] {
+            let mut req = client
+                .post("/sensors/register/motion")
+                .header(ContentType::JSON)
+                .body(serde_json::to_string(&register_input).unwrap());
+            if let Some(token) = token {
+                req = req.header(bearer(token));
+            }
+            let res: LocalResponse = req.dispatch().await;
+            assert_eq!(res.status(), Status::Forbidden);
+            assert_eq!(problem(res).await.code, ErrorCode::Forbidden);
+        }
+    }
+    for url in [
+        format!("/devices/{}/sensors", device_uuid),
+        format!("/profiles/{}/sensors", profile_owner_id),
+    ] {
+        let res: LocalResponse = client.get(url).header(bearer(attacker_token)).dispatch().await;
+        assert_eq!(res.status(), Status::Forbidden);
+    }
+
+    // the owner can add sensors with the apiToken of its sensors
+    assert_eq!(
+        register_sensor_of_profile(
+            &client,
+            &profile_owner_id,
+            &device_uuid,
+            &get_random_mac(),
+            &Uuid::new_v4().to_string(),
+            "motion",
+        )
+        .await,
+        Status::Created
+    );
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn register_sensor_in_same_device_or_profile() {
+    // init
+    let client: Client = backend_client("memory", None).await;
+
+    // inputs
+    let profile_owner_id = ObjectId::new().to_hex();
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let other_token = "a4c1b0e2-3f0d-4b8e-9d1c-2b7f6e5a4d3c";
+    let register = async |device_uuid: &str, api_token: &str, token: Option<&str>| -> Status {
+        let mut register_input = create_register_input(
+            &profile_owner_id,
+            device_uuid,
+            &get_random_mac(),
+            &Uuid::new_v4().to_string(),
+        );
+        register_input.apiToken = String::from(api_token);
+        let mut req = client
+            .post("/sensors/register/motion")
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&register_input).unwrap());
+        if let Some(token) = token {
+            req = req.header(bearer(token));
+        }
+        req.dispatch().await.status()
+    };
+
+    // devices send their apiToken only in the body, without an Authorization header
+    assert_eq!(register(&device_uuid, API_TOKEN, None).await, Status::Created);
+    assert_eq!(register(&device_uuid, API_TOKEN, None).await, Status::Created);
+    assert_eq!(
+        register(&Uuid::new_v4().to_string(), API_TOKEN, None).await,
+        Status::Created
+    );
+
+    // an apiToken that doesn't match the ones of the device and of the profile
+    assert_eq!(register(&device_uuid, other_token, None).await, Status::Forbidden);
+    assert_eq!(
+        register(&device_uuid, other_token, Some(other_token)).await,
+        Status::Forbidden
+    );
+
+    // unless the caller has one of their apiTokens
+    assert_eq!(
+        register(&device_uuid, other_token, Some(API_TOKEN)).await,
+        Status::Created
+    );
+}
@@ -0,0 +1,207 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::rocket;
+use pretty_assertions::assert_eq;
+use rocket::http::Status;
+use rocket::local::asynchronous::{Client, LocalRequest, LocalResponse};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use register::config::sensor_types::load_sensor_types;
+use register::models::sensor_type::{
+    SensorType, SensorTypeDef, ValueKind, default_sensor_types, validate_sensor_types,
+};
+
+fn write_temp_file(extension: &str, content: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("sensor_types_{}.{}", Uuid::new_v4(), extension));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn get_sensor_types() {
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+
+    let req: LocalRequest = client.get("/sensors/types");
+    let res: LocalResponse = req.dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let types = res.into_json::<Value>().await.unwrap();
+    assert_eq!(types, serde_json::to_value(default_sensor_types().unwrap()).unwrap());
+    assert_eq!(
+        types[0],
+        json!({
+            "name": "temperature",
+            "kind": "float",
+            "unit": "°C",
+            "min": -50.0,
+            "max": 150.0,
+            "default": 0.0,
+        })
+    );
+}
+
+#[test_log::test]
+fn load_sensor_types_from_toml() {
+    let path = write_temp_file(
+        "toml",
+        r#"
+[[types]]
+name = "co2"
+kind = "int"
+unit = "ppm"
+min = 0
+max = 10000
+default = 400
+
+[[types]]
+name = "uv"
+kind = "float"
+min = 0
+max = 15
+default = 0
+"#,
+    );
+
+    let types = load_sensor_types(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        types,
+        vec![
+            SensorTypeDef {
+                name: "co2".to_string(),
+                kind: ValueKind::Int,
+                unit: "ppm".to_string(),
+                min: 0.0,
+                max: 10000.0,
+                default: 400.0,
+            },
+            SensorTypeDef {
+                name: "uv".to_string(),
+                kind: ValueKind::Float,
+                unit: "".to_string(),
+                min: 0.0,
+                max: 15.0,
+                default: 0.0,
+            },
+        ]
+    );
+}
+
+#[test_log::test]
+fn load_sensor_types_from_json() {
+    let path = write_temp_file(
+        "json",
+        &json!({
+            "types": [{ "name": "noise", "kind": "float", "unit": "dB", "min": 0, "max": 140, "default": 30 }]
+        })
+        .to_string(),
+    );
+
+    let types = load_sensor_types(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(types.len(), 1);
+    assert_eq!(types[0].name, "noise");
+    assert_eq!(types[0].kind, ValueKind::Float);
+    assert_eq!(types[0].default, 30.0);
+}
+
+#[test_log::test]
+fn default_sensor_types_file() {
+    // the built-in catalogue is the one of sensor_types.toml, that must be valid
+    let types = default_sensor_types().unwrap();
+    assert_eq!(validate_sensor_types(&types), Ok(()));
+    let names: Vec<&str> = types.iter().map(|def| def.name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec![
+            "temperature",
+            "humidity",
+            "light",
+            "motion",
+            "airquality",
+            "airpressure",
+            "online"
+        ]
+    );
+    assert_eq!(load_sensor_types(Path::new("sensor_types.toml")).unwrap(), types);
+    // sensors are registered with value 0, as before the catalogue
+    assert!(types.iter().all(|def| def.default == 0.0));
+}
+
+#[test_log::test]
+fn sensor_type_serde_by_name() {
+    let sensor_type = SensorType::from_str("airpressure").unwrap();
+    assert_eq!(serde_json::to_value(sensor_type).unwrap(), json!("airpressure"));
+    assert_eq!(
+        serde_json::from_value::<SensorType>(json!("airpressure")).unwrap(),
+        sensor_type
+    );
+    let error = serde_json::from_value::<SensorType>(json!("unknown")).unwrap_err();
+    assert_eq!(error.to_string(), "Unknown sensor_type = unknown");
+}
+
+#[test_log::test]
+fn load_sensor_types_errors() {
+    let path = write_temp_file(
+        "toml",
+        r#"
+[[types]]
+name = "Power"
+kind = "float"
+min = 0
+max = 10000
+default = 0
+
+[[types]]
+name = "co2"
+kind = "int"
+min = 0
+max = 10000
+default = 400.5
+
+[[types]]
+name = "co2"
+kind = "int"
+min = 10
+max = 0
+default = 0
+
+[[types]]
+name = "uv"
+kind = "float"
+min = 0
+max = 15
+default = 20
+"#,
+    );
+
+    let errors = load_sensor_types(&path).unwrap_err();
+    fs::remove_file(&path).unwrap();
+
+    // all errors are reported
+    assert_eq!(
+        errors,
+        vec![
+            "sensor type 'Power' must have a name of lowercase letters, digits or '_'",
+            "sensor type 'co2' has kind int, but min, max or default is not an integer",
+            "sensor type 'co2' is defined more than once",
+            "sensor type 'co2' has an invalid range 10..0",
+            "sensor type 'uv' has default = 20 out of range 0..15",
+        ]
+    );
+
+    // unsupported or malformed files
+    let path = write_temp_file("yaml", "types: []");
+    assert!(load_sensor_types(&path).is_err());
+    fs::remove_file(&path).unwrap();
+    let path = write_temp_file("json", "{\"types\": [{\"name\": \"co2\"}]}");
+    assert!(load_sensor_types(&path).is_err());
+    fs::remove_file(&path).unwrap();
+    assert!(load_sensor_types(Path::new("missing_sensor_types.toml")).is_err());
+}
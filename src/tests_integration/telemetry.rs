@@ -0,0 +1,255 @@
+use std::env;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::rocket;
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use serde_json::Value;
+use tracing::subscriber::DefaultGuard;
+use uuid::Uuid;
+
+use register::config::{Env, LogFormat};
+use register::logging::{log_filter, subscriber_with_layers};
+use register::telemetry::otlp;
+
+use crate::tests_integration::backends::backend_client;
+use crate::tests_integration::logging::Buffer;
+use crate::tests_integration::test_utils::{API_TOKEN, bearer};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+// stand-in of an OpenTelemetry collector, keeping the bodies of requests to /v1/traces
+#[derive(Clone, Default)]
+struct Collector(Arc<Mutex<Vec<Value>>>);
+
+impl Collector {
+    // listen on a free port, returning the endpoint of the collector
+    fn start(&self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let collector = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let collector = collector.clone();
+                thread::spawn(move || collector.serve(stream));
+            }
+        });
+        endpoint
+    }
+
+    // serve the requests of a connection, until it's closed
+    fn serve(&self, stream: TcpStream) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        loop {
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                return;
+            }
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            if request_line.starts_with("POST /v1/traces ") {
+                self.0.lock().unwrap().push(serde_json::from_slice(&body).unwrap());
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 2\r\n\r\n{}")
+                .unwrap();
+        }
+    }
+
+    // exported spans, with the name of the service in their `service` field
+    fn spans(&self) -> Vec<Value> {
+        let mut spans = Vec::new();
+        for body in self.0.lock().unwrap().iter() {
+            for resource_spans in body["resourceSpans"].as_array().unwrap() {
+                let service = attribute(&resource_spans["resource"], "service.name");
+                for scope_spans in resource_spans["scopeSpans"].as_array().unwrap() {
+                    for span in scope_spans["spans"].as_array().unwrap() {
+                        let mut span = span.clone();
+                        span["service"] = service.clone();
+                        spans.push(span);
+                    }
+                }
+            }
+        }
+        spans
+    }
+}
+
+// value of the attribute `key` of a span or of a resource
+fn attribute(item: &Value, key: &str) -> Value {
+    let attributes = item["attributes"].as_array().cloned().unwrap_or_default();
+    match attributes.iter().find(|attribute| attribute["key"] == key) {
+        Some(attribute) => {
+            let value = &attribute["value"];
+            let int_value = &value["intValue"];
+            if int_value.is_null() {
+                value["stringValue"].clone()
+            } else {
+                // 64-bit integers may be sent as strings
+                Value::from(int_value.to_string().trim_matches('"').parse::<i64>().unwrap())
+            }
+        }
+        None => Value::Null,
+    }
+}
+
+fn env_with(vars: &[(&str, &str)]) -> Env {
+    envy::from_iter(vars.iter().map(|(key, value)| (key.to_string(), value.to_string()))).unwrap()
+}
+
+// provider exporting spans to `collector` as JSON, with the layer set as default subscriber
+fn trace_to(
+    collector: &Collector,
+    vars: &[(&str, &str)],
+) -> (opentelemetry_sdk::trace::SdkTracerProvider, DefaultGuard) {
+    let endpoint = collector.start();
+    let mut vars = vars.to_vec();
+    vars.push(("OTEL_EXPORTER_OTLP_ENDPOINT", &endpoint));
+    vars.push(("OTEL_EXPORTER_OTLP_PROTOCOL", "http/json"));
+    let provider = otlp::tracer_provider(&env_with(&vars)).unwrap();
+    let layer = Box::new(otlp::layer(&provider));
+    let writer = Buffer::default();
+    let guard = tracing::subscriber::set_default(subscriber_with_layers(
+        LogFormat::Json,
+        log_filter(Some("app=info")).unwrap(),
+        move || writer.clone(),
+        vec![layer],
+    ));
+    (provider, guard)
+}
+
+#[rocket::async_test]
+async fn request_spans_exported() {
+    let collector = Collector::default();
+    let (provider, _guard) = trace_to(&collector, &[("OTEL_SERVICE_NAME", "register-test")]);
+    let client: Client = backend_client("memory", None).await;
+
+    let res: LocalResponse = client
+        .get("/sensors/types")
+        .header(Header::new(
+            "traceparent",
+            format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID),
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+    // the span ends with the request
+    drop(res);
+    let res: LocalResponse = client.get("/unknown").dispatch().await;
+    assert_eq!(res.status(), Status::NotFound);
+    drop(res);
+    provider.force_flush().unwrap();
+
+    let spans = collector.spans();
+    assert_eq!(spans.len(), 2, "{:?}", spans);
+    // continues the trace of the caller
+    let span = spans.iter().find(|span| span["name"] == "GET /sensors/types").unwrap();
+    assert_eq!(span["traceId"], TRACE_ID);
+    assert_eq!(span["parentSpanId"], PARENT_SPAN_ID);
+    assert_eq!(span["service"], "register-test");
+    assert_eq!(attribute(span, "http.request.method"), "GET");
+    assert_eq!(attribute(span, "http.route"), "/sensors/types");
+    assert_eq!(attribute(span, "http.response.status_code"), 200);
+    assert!(attribute(span, "request_id").is_string());
+    // starts a new trace, without a route
+    let span = spans.iter().find(|span| span["name"] == "GET").unwrap();
+    assert_ne!(span["traceId"], TRACE_ID);
+    assert_eq!(span["parentSpanId"].as_str().unwrap_or_default(), "");
+    assert_eq!(attribute(span, "url.path"), "/unknown");
+    assert_eq!(attribute(span, "http.response.status_code"), 404);
+}
+
+#[rocket::async_test]
+async fn request_spans_sampled() {
+    let collector = Collector::default();
+    let (provider, _guard) = trace_to(&collector, &[("OTEL_TRACES_SAMPLER_ARG", "0")]);
+    let client: Client = backend_client("memory", None).await;
+
+    // sampled as decided by the caller, or by the ratio without a caller
+    for (traceparent, sampled) in [
+        (Some(format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID)), true),
+        (Some(format!("00-{}-{}-00", TRACE_ID, PARENT_SPAN_ID)), false),
+        (None, false),
+    ] {
+        let mut req = client.get("/health/live");
+        if let Some(traceparent) = traceparent.clone() {
+            req = req.header(Header::new("traceparent", traceparent));
+        }
+        let res: LocalResponse = req.dispatch().await;
+        assert_eq!(res.status(), Status::Ok);
+        drop(res);
+        provider.force_flush().unwrap();
+        let exported = collector.spans().len();
+        assert_eq!(exported, usize::from(sampled), "{:?}", traceparent);
+        collector.0.lock().unwrap().clear();
+    }
+}
+
+#[rocket::async_test]
+async fn storage_spans_exported() {
+    let collector = Collector::default();
+    let (provider, _guard) = trace_to(&collector, &[]);
+    // MongoDB on a port without a server, so that operations fail fast
+    // SAFETY: integration tests run on a single thread (`--test-threads 1`)
+    unsafe { env::set_var("STORAGE_BACKEND", "mongodb") };
+    unsafe { env::set_var("MONGO_URI", "mongodb://127.0.0.1:1") };
+    unsafe { env::set_var("MONGO_SERVER_SELECTION_TIMEOUT_MS", "50") };
+    unsafe { env::set_var("DB_START_DEGRADED", "true") };
+    let client: Client = Client::tracked(rocket()).await.unwrap();
+    for key in [
+        "STORAGE_BACKEND",
+        "MONGO_URI",
+        "MONGO_SERVER_SELECTION_TIMEOUT_MS",
+        "DB_START_DEGRADED",
+    ] {
+        unsafe { env::remove_var(key) };
+    }
+
+    // the sensor guard reads the apiTokens of the sensor
+    let res: LocalResponse = client
+        .get(format!(
+            "/sensors/{}/features/{}/temperature",
+            Uuid::new_v4(),
+            Uuid::new_v4()
+        ))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::ServiceUnavailable);
+    drop(res);
+    provider.force_flush().unwrap();
+
+    let spans = collector.spans();
+    let request_span = spans
+        .iter()
+        .find(|span| span["name"] == "GET /sensors/<device_uuid>/features/<feature_uuid>/<sensor_type>")
+        .unwrap();
+    let storage_span = spans.iter().find(|span| span["name"] == "find_api_tokens").unwrap();
+    // child of the request span
+    assert_eq!(storage_span["traceId"], request_span["traceId"]);
+    assert_eq!(storage_span["parentSpanId"], request_span["spanId"]);
+    // client span, see `SpanKind` of OTLP
+    assert_eq!(storage_span["kind"], 3);
+    assert_eq!(attribute(storage_span, "db.system.name"), "mongodb");
+    assert_eq!(attribute(storage_span, "db.operation.name"), "find_api_tokens");
+    assert_eq!(attribute(storage_span, "db.collection.name"), "sensors");
+}
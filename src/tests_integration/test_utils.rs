@@ -1,7 +1,12 @@
 use rand::prelude::*;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
 
+use register::errors::api_error::Problem;
 use register::models::inputs::RegisterInput;
 
+pub const API_TOKEN: &str = "473a4861-632b-4915-b01e-cf1d418966c6";
+
 pub fn create_register_input(
     profile_owner_id: &str,
     device_uuid: &str,
@@ -11,7 +16,7 @@ pub fn create_register_input(
     RegisterInput {
         // profile info
         profileOwnerId: profile_owner_id.to_string(),
-        apiToken: String::from("473a4861-632b-4915-b01e-cf1d418966c6"),
+        apiToken: String::from(API_TOKEN),
         // device info
         deviceUuid: device_uuid.to_string(),
         mac: mac.to_string(),
@@ -26,6 +31,47 @@ pub fn build_register_input(profile_owner_id: &str, device_uuid: &str, mac: &str
     serde_json::to_string(&create_register_input(profile_owner_id, device_uuid, mac, feature_uuid)).unwrap()
 }
 
+/// Register a sensor of the default profile with a random mac, returning the status of the response
+pub async fn register_sensor(client: &Client, device_uuid: &str, feature_uuid: &str, sensor_type: &str) -> Status {
+    register_sensor_of_profile(
+        client,
+        "63963ce7c7fd6d463c6c77a3",
+        device_uuid,
+        &get_random_mac(),
+        feature_uuid,
+        sensor_type,
+    )
+    .await
+}
+
+/// Register a sensor with `API_TOKEN`, returning the status of the response
+pub async fn register_sensor_of_profile(
+    client: &Client,
+    profile_owner_id: &str,
+    device_uuid: &str,
+    mac: &str,
+    feature_uuid: &str,
+    sensor_type: &str,
+) -> Status {
+    client
+        .post(format!("/sensors/register/{}", sensor_type))
+        .header(ContentType::JSON)
+        .body(build_register_input(profile_owner_id, device_uuid, mac, feature_uuid))
+        .dispatch()
+        .await
+        .status()
+}
+
+/// Body of an error response, checking that it's `application/problem+json`
+pub async fn problem(res: LocalResponse<'_>) -> Problem {
+    assert_eq!(res.content_type().unwrap().to_string(), "application/problem+json");
+    res.into_json::<Problem>().await.unwrap()
+}
+
+pub fn bearer(api_token: &str) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {}", api_token))
+}
+
 pub fn get_random_mac() -> String {
     const CHARSET: &[u8] = b"ABCDEF0123456789";
     let mut rng = rand::rng();
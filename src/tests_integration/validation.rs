@@ -0,0 +1,151 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use serde_json::Value;
+use uuid::Uuid;
+
+use register::errors::api_error::{ErrorCode, FieldError, Problem};
+use register::models::inputs::RegisterInput;
+use register::models::validation::{Validate, normalize_mac};
+
+use crate::tests_integration::backends::backend_client;
+use crate::tests_integration::test_utils::{API_TOKEN, bearer, build_register_input, create_register_input, problem};
+
+fn valid_input() -> RegisterInput {
+    create_register_input(
+        "63963ce7c7fd6d463c6c77a3",
+        "b5a3f0b4-9e8f-4d1e-8f0a-3c2b1d0e9f8a",
+        "AA:BB:CC:DD:EE:FF",
+        "f3c8e1a2-5b7d-4e9f-8a6c-2d1b0e9f8a7c",
+    )
+}
+
+#[test]
+fn normalize_mac_formats() {
+    for mac in [
+        "AA:BB:CC:DD:EE:0F",
+        "aa:bb:cc:dd:ee:0f",
+        "aa-bb-cc-dd-ee-0f",
+        "aabbccddee0f",
+    ] {
+        assert_eq!(normalize_mac(mac), Some(String::from("AA:BB:CC:DD:EE:0F")));
+    }
+    for mac in [
+        "",
+        "AA:BB:CC:DD:EE",
+        "AA:BB:CC:DD:EE:FF:00",
+        "AA:BB:CC:DD:EE:GG",
+        "AA:BB-CC:DD:EE:FF",
+        "A:BB:CC:DD:EE:FFF",
+        "aabbccddeef",
+        "àabbccddeef",
+    ] {
+        assert_eq!(normalize_mac(mac), None, "mac = {}", mac);
+    }
+}
+
+#[test]
+fn validate_register_input() {
+    let mut input = valid_input();
+    input.mac = String::from("aa-bb-cc-dd-ee-ff");
+    assert_eq!(input.validate(), Ok(()));
+    assert_eq!(input.mac, "AA:BB:CC:DD:EE:FF");
+
+    let mut input = RegisterInput {
+        profileOwnerId: String::from("invalid"),
+        apiToken: String::new(),
+        deviceUuid: String::from("b5a3f0b49e8f4d1e8f0a3c2b1d0e9f8a"),
+        mac: String::from("invalid"),
+        model: "m".repeat(101),
+        manufacturer: String::new(),
+        featureUuid: String::from("feature"),
+    };
+    assert_eq!(
+        input.validate(),
+        Err(vec![
+            FieldError::new("profileOwnerId", "must be an ObjectId of 24 hex characters"),
+            FieldError::new("apiToken", "must be between 1 and 256 characters"),
+            FieldError::new("deviceUuid", "must be a UUID"),
+            FieldError::new("mac", "must be a MAC address, like AA:BB:CC:DD:EE:FF"),
+            FieldError::new("model", "must be between 1 and 100 characters"),
+            FieldError::new("manufacturer", "must be between 1 and 100 characters"),
+            FieldError::new("featureUuid", "must be a UUID"),
+        ])
+    );
+
+    // limits are in characters, not bytes
+    let mut input = valid_input();
+    input.model = "è".repeat(100);
+    assert_eq!(input.validate(), Ok(()));
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn register_invalid_input() {
+    for backend in ["memory", "sqlite"] {
+        let client: Client = backend_client(backend, None).await;
+
+        let register_body = build_register_input("invalid", "device", "AA:BB:CC:DD:EE:FF", &Uuid::new_v4().to_string());
+        let res: LocalResponse = client
+            .post("/sensors/register/temperature")
+            .header(ContentType::JSON)
+            .body(register_body)
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+        let body: Problem = problem(res).await;
+        assert_eq!(body.code, ErrorCode::ValidationFailed);
+        assert_eq!(
+            body.errors,
+            vec![
+                FieldError::new("profileOwnerId", "must be an ObjectId of 24 hex characters"),
+                FieldError::new("deviceUuid", "must be a UUID"),
+            ]
+        );
+
+        // json not matching the input has no field errors
+        let res: LocalResponse = client
+            .post("/sensors/register/temperature")
+            .header(ContentType::JSON)
+            .body("{\"mac\": \"AA:BB:CC:DD:EE:FF\"}")
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+        let body: Problem = problem(res).await;
+        assert_eq!(body.code, ErrorCode::UnprocessableEntity);
+        assert!(body.errors.is_empty());
+    }
+}
+
+#[rocket::async_test]
+#[test_log::test]
+async fn register_normalized_mac() {
+    let client: Client = backend_client("memory", None).await;
+
+    // inputs
+    let device_uuid: String = Uuid::new_v4().to_string();
+    let register_body = build_register_input(
+        "63963ce7c7fd6d463c6c77a3",
+        &device_uuid,
+        "aa-bb-cc-dd-ee-0f",
+        &Uuid::new_v4().to_string(),
+    );
+
+    // test apis
+    let res: LocalResponse = client
+        .post("/sensors/register/temperature")
+        .header(ContentType::JSON)
+        .body(register_body)
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Created);
+    let res: LocalResponse = client
+        .get(format!("/devices/{}/sensors", device_uuid))
+        .header(bearer(API_TOKEN))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+
+    // check results
+    let body = res.into_json::<Value>().await.unwrap();
+    assert_eq!(body["sensors"][0]["mac"], "AA:BB:CC:DD:EE:0F");
+}